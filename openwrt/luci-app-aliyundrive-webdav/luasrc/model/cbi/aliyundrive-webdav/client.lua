@@ -41,6 +41,8 @@ tls_key = e:option(Value, "tls_key", translate("TLS private key file path"))
 auth_user = e:option(Value, "auth_user", translate("Username"))
 auth_password = e:option(Value, "auth_password", translate("Password"))
 auth_password.password = true
+auth_password_hash = e:option(Value, "auth_password_hash", translate("Password Hash"))
+auth_password_hash.description = translate("bcrypt, argon2 or sha512-crypt password hash, generate one with aliyundrive-webdav hash-password, takes precedence over the password")
 
 read_buffer_size = e:option(Value, "read_buffer_size", translate("Read Buffer Size"))
 read_buffer_size.default = "10485760"
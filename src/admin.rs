@@ -0,0 +1,181 @@
+//! Token protected HTTP API to manage a running server
+
+use std::collections::HashMap;
+
+use dav_server::body::Body;
+use headers::authorization::Bearer;
+use headers::{Authorization, HeaderMapExt};
+use hyper::header::CONTENT_TYPE;
+use hyper::{Method, Request, Response, StatusCode};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::auth::constant_time_eq;
+use crate::mount::is_ancestor;
+use crate::vfs::AliyunDriveFileSystem;
+
+/// Path prefix of the admin API
+pub const PREFIX: &str = "/_admin/";
+
+/// Handle a request below [`PREFIX`], `mounts` are the served file systems with their paths
+pub async fn handle(
+    token: &str,
+    mounts: &[(String, AliyunDriveFileSystem)],
+    req: Request<hyper::Body>,
+) -> Response<Body> {
+    let authorized = req
+        .headers()
+        .typed_get::<Authorization<Bearer>>()
+        .is_some_and(|Authorization(bearer)| constant_time_eq(bearer.token(), token));
+    if !authorized {
+        return hyper::Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .header("WWW-Authenticate", "Bearer realm=\"admin\"")
+            .body(Body::from("Authentication required".to_string()))
+            .unwrap();
+    }
+    let params: HashMap<String, String> = req
+        .uri()
+        .query()
+        .map(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect()
+        })
+        .unwrap_or_default();
+    let endpoint = &req.uri().path()[PREFIX.len()..];
+    let result = match (req.method(), endpoint) {
+        (&Method::GET, "status") => Ok(status(mounts).await),
+        (&Method::GET, "quota") => Ok(quota(mounts).await),
+        (&Method::GET, "uploads") => Ok(uploads(mounts)),
+        (&Method::POST, "cache/invalidate") => invalidate(mounts, &params).await,
+        (&Method::POST, "token/refresh") => refresh(mounts).await,
+        (_, "status" | "quota" | "uploads" | "cache/invalidate" | "token/refresh") => Err((
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method not allowed".to_string(),
+        )),
+        _ => Err((StatusCode::NOT_FOUND, "not found".to_string())),
+    };
+    let (status, body) = match result {
+        Ok(body) => (StatusCode::OK, body),
+        Err((status, error)) => (status, json!({ "error": error })),
+    };
+    hyper::Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+async fn status(mounts: &[(String, AliyunDriveFileSystem)]) -> Value {
+    let mut drives = Vec::new();
+    for (path, fs) in mounts {
+        let drive = &fs.drive;
+        drives.push(json!({
+            "path": path,
+            "drive_id": drive.drive_id().ok(),
+            "token": drive.token_status().await,
+            "ready": drive.check_ready().await.is_ok(),
+        }));
+    }
+    json!({ "drives": drives })
+}
+
+async fn quota(mounts: &[(String, AliyunDriveFileSystem)]) -> Value {
+    let mut drives = Vec::new();
+    for (path, fs) in mounts {
+        let drive = match fs.drive.get_quota().await {
+            Ok((used, total)) => json!({ "path": path, "used": used, "total": total }),
+            Err(err) => json!({ "path": path, "error": err.to_string() }),
+        };
+        drives.push(drive);
+    }
+    json!({ "drives": drives })
+}
+
+fn uploads(mounts: &[(String, AliyunDriveFileSystem)]) -> Value {
+    let uploads: Vec<_> = mounts
+        .iter()
+        .flat_map(|(path, fs)| {
+            fs.uploads().into_iter().map(move |(parent_file_id, file)| {
+                json!({
+                    "mount": path,
+                    "parent_file_id": parent_file_id,
+                    "name": file.name,
+                    "size": file.size,
+                })
+            })
+        })
+        .collect();
+    json!({ "uploads": uploads })
+}
+
+/// Invalidate the cached listing of `path`, or of its whole subtree with `recursive=true`
+async fn invalidate(
+    mounts: &[(String, AliyunDriveFileSystem)],
+    params: &HashMap<String, String>,
+) -> Result<Value, (StatusCode, String)> {
+    let recursive = match params.get("recursive").map(String::as_str) {
+        None | Some("false") | Some("0") => false,
+        Some("true") | Some("1") => true,
+        Some(value) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("invalid recursive value `{}`", value),
+            ))
+        }
+    };
+    let path = params.get("path").map(String::as_str).unwrap_or("/");
+    if !path.starts_with('/') || path.split('/').any(|part| part == "..") {
+        return Err((StatusCode::BAD_REQUEST, format!("invalid path `{}`", path)));
+    }
+    let path = match path.trim_end_matches('/') {
+        "" => "/",
+        path => path,
+    };
+    let mut invalidated = Vec::new();
+    for (prefix, fs) in mounts {
+        if path == prefix || is_ancestor(prefix, path) {
+            let rest = if prefix == "/" {
+                path
+            } else {
+                &path[prefix.len()..]
+            };
+            fs.invalidate_cache(rest, recursive).await;
+        } else if recursive && is_ancestor(path, prefix) {
+            fs.invalidate_cache("/", true).await;
+        } else {
+            continue;
+        }
+        invalidated.push(prefix);
+    }
+    if invalidated.is_empty() {
+        return Err((StatusCode::NOT_FOUND, format!("no mount serves `{}`", path)));
+    }
+    info!(path = %path, recursive = recursive, "cache invalidated by admin api");
+    Ok(json!({ "mounts": invalidated }))
+}
+
+/// Refresh the access token of every account once
+async fn refresh(
+    mounts: &[(String, AliyunDriveFileSystem)],
+) -> Result<Value, (StatusCode, String)> {
+    let mut refreshed: Vec<&AliyunDriveFileSystem> = Vec::new();
+    for (path, fs) in mounts {
+        if refreshed
+            .iter()
+            .any(|other| other.drive.same_account(&fs.drive))
+        {
+            continue;
+        }
+        if let Err(err) = fs.drive.refresh_access_token().await {
+            warn!(path = %path, error = %err, "refresh token by admin api failed");
+            return Err((
+                StatusCode::BAD_GATEWAY,
+                format!("refresh token of {} failed: {}", path, err),
+            ));
+        }
+        refreshed.push(fs);
+    }
+    Ok(status(mounts).await)
+}
@@ -0,0 +1,507 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::{PasswordHash, PasswordHasher, SaltString};
+use argon2::{Argon2, PasswordVerifier};
+use clap::ValueEnum;
+use headers::authorization::{Basic, Bearer};
+use headers::{Authorization, HeaderMapExt};
+use hyper::header::AUTHORIZATION;
+use hyper::{HeaderMap, Method, Uri};
+use md5::{Digest, Md5};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+use tracing::warn;
+
+use crate::users::{User, Users};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum HashAlgorithm {
+    Bcrypt,
+    Argon2,
+    #[value(name = "sha512-crypt")]
+    Sha512Crypt,
+}
+
+impl HashAlgorithm {
+    pub fn hash(&self, password: &str) -> Result<String> {
+        let hash = match self {
+            HashAlgorithm::Bcrypt => bcrypt::hash(password, bcrypt::DEFAULT_COST)?,
+            HashAlgorithm::Argon2 => Argon2::default()
+                .hash_password(password.as_bytes(), &SaltString::generate(&mut OsRng))
+                .map_err(|err| anyhow::anyhow!("argon2 hash failed: {}", err))?
+                .to_string(),
+            HashAlgorithm::Sha512Crypt => pwhash::sha512_crypt::hash(password)?,
+        };
+        Ok(hash)
+    }
+
+    fn detect(hash: &str) -> Option<Self> {
+        if hash.starts_with("$2") {
+            Some(HashAlgorithm::Bcrypt)
+        } else if hash.starts_with("$argon2") {
+            Some(HashAlgorithm::Argon2)
+        } else if hash.starts_with("$6$") {
+            Some(HashAlgorithm::Sha512Crypt)
+        } else {
+            None
+        }
+    }
+}
+
+/// Check that a password hash is in a supported format
+pub fn check_password_hash(hash: &str) -> Result<()> {
+    match HashAlgorithm::detect(hash) {
+        Some(HashAlgorithm::Argon2) => {
+            if let Err(err) = PasswordHash::new(hash) {
+                bail!("invalid argon2 password hash: {}", err);
+            }
+        }
+        Some(_) => {}
+        None => bail!("unsupported password hash, expected a bcrypt, argon2 or sha512-crypt hash"),
+    }
+    Ok(())
+}
+
+/// Verify a password against a bcrypt, argon2 or sha512-crypt hash
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match HashAlgorithm::detect(hash) {
+        Some(HashAlgorithm::Bcrypt) => bcrypt::verify(password, hash).unwrap_or_else(|err| {
+            warn!(error = %err, "invalid bcrypt password hash");
+            false
+        }),
+        Some(HashAlgorithm::Argon2) => match PasswordHash::new(hash) {
+            Ok(hash) => Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok(),
+            Err(err) => {
+                warn!(error = %err, "invalid argon2 password hash");
+                false
+            }
+        },
+        Some(HashAlgorithm::Sha512Crypt) => pwhash::sha512_crypt::verify(password, hash),
+        None => {
+            warn!("unsupported password hash format");
+            false
+        }
+    }
+}
+
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+/// Keyed hashes of recently verified passwords, password hashes are slow to verify
+#[derive(Debug, Default)]
+pub struct VerifiedPasswords {
+    verified: Mutex<HashMap<String, u64>>,
+    hasher: RandomState,
+}
+
+impl VerifiedPasswords {
+    /// Verify the password of `name` against `hash`, remembering successful verifications
+    pub fn verify(&self, name: &str, password: &str, hash: &str) -> bool {
+        let tag = self.hasher.hash_one((name, password, hash));
+        if self.verified.lock().unwrap().get(name) == Some(&tag) {
+            return true;
+        }
+        if verify_password(password, hash) {
+            self.verified.lock().unwrap().insert(name.to_string(), tag);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Password {
+    Plain(String),
+    Hash(String),
+}
+
+/// Credentials of the single `--auth-user`
+#[derive(Debug)]
+pub struct Credentials {
+    user: String,
+    password: Password,
+    bearer_tokens: Vec<String>,
+    verified: VerifiedPasswords,
+}
+
+impl Credentials {
+    pub fn new(user: String, password: Password, bearer_tokens: Vec<String>) -> Self {
+        Self {
+            user,
+            password,
+            bearer_tokens,
+            verified: VerifiedPasswords::default(),
+        }
+    }
+
+    pub fn verify(&self, user: &str, password: &str) -> bool {
+        let user_matches = constant_time_eq(&self.user, user);
+        let password_matches = match &self.password {
+            Password::Plain(expected) => constant_time_eq(expected, password),
+            Password::Hash(hash) => self.verified.verify(&self.user, password, hash),
+        };
+        user_matches & password_matches
+    }
+
+    fn verify_token(&self, token: &str) -> bool {
+        self.bearer_tokens.iter().fold(false, |found, expected| {
+            found | constant_time_eq(expected, token)
+        })
+    }
+}
+
+pub const REALM: &str = "aliyundrive-webdav";
+
+/// Digest nonces are valid for 5 minutes, clients retry with a fresh one after that
+const NONCE_TTL: u64 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AuthScheme {
+    Basic,
+    /// RFC 7616 HTTP Digest
+    Digest,
+    /// Static API tokens
+    Bearer,
+}
+
+/// Accounts requests are authenticated against
+pub enum Accounts {
+    Single(Credentials),
+    Users(Arc<Users>),
+}
+
+/// Who a request is authenticated as
+#[derive(Debug, Clone)]
+pub enum Principal {
+    /// The single `--auth-user`
+    Single(String),
+    /// A user from the users file
+    User(User),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    /// No or wrong credentials
+    Unauthorized,
+    /// Correct digest response computed with an expired nonce
+    StaleNonce,
+}
+
+/// Authenticates requests with the enabled schemes
+pub struct Authenticator {
+    schemes: Vec<AuthScheme>,
+    accounts: Accounts,
+    nonce_secret: [u8; 32],
+    /// Highest `nc` seen by nonce and cnonce with the nonce's timestamp, to refuse replayed digests
+    nonce_counts: Mutex<HashMap<(String, String), (u64, u64)>>,
+}
+
+impl Authenticator {
+    pub fn new(schemes: Vec<AuthScheme>, accounts: Accounts) -> Result<Self> {
+        if schemes.is_empty() {
+            bail!("no authentication scheme enabled");
+        }
+        if schemes.contains(&AuthScheme::Digest) {
+            match &accounts {
+                Accounts::Single(Credentials {
+                    password: Password::Plain(_),
+                    ..
+                }) => {}
+                // HTTP Digest needs the password, the users file only has hashes of it
+                _ => bail!(
+                    "digest authentication requires a plain text auth-password and can't be used with a users file"
+                ),
+            }
+        }
+        if schemes.contains(&AuthScheme::Bearer) {
+            let has_tokens = match &accounts {
+                Accounts::Single(credentials) => !credentials.bearer_tokens.is_empty(),
+                Accounts::Users(users) => users.has_bearer_tokens(),
+            };
+            if !has_tokens {
+                bail!("bearer authentication requires at least one bearer token");
+            }
+        }
+        let mut nonce_secret = [0; 32];
+        OsRng.fill_bytes(&mut nonce_secret);
+        Ok(Self {
+            schemes,
+            accounts,
+            nonce_secret,
+            nonce_counts: Mutex::default(),
+        })
+    }
+
+    fn enabled(&self, scheme: AuthScheme) -> bool {
+        self.schemes.contains(&scheme)
+    }
+
+    pub async fn authenticate(
+        self: &Arc<Self>,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+    ) -> Result<Principal, AuthError> {
+        if self.enabled(AuthScheme::Basic) {
+            if let Some(Authorization(basic)) = headers.typed_get::<Authorization<Basic>>() {
+                let auth = self.clone();
+                let name = basic.username().to_string();
+                let password = basic.password().to_string();
+                // password hashes are slow to verify
+                return tokio::task::spawn_blocking(move || auth.verify_basic(name, &password))
+                    .await
+                    .ok()
+                    .flatten()
+                    .ok_or(AuthError::Unauthorized);
+            }
+        }
+        if self.enabled(AuthScheme::Bearer) {
+            if let Some(Authorization(bearer)) = headers.typed_get::<Authorization<Bearer>>() {
+                return self
+                    .verify_bearer(bearer.token())
+                    .ok_or(AuthError::Unauthorized);
+            }
+        }
+        if self.enabled(AuthScheme::Digest) {
+            let digest = headers
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| {
+                    let (scheme, params) = value.split_once(' ')?;
+                    scheme.eq_ignore_ascii_case("digest").then_some(params)
+                });
+            if let Some(params) = digest {
+                return self.verify_digest(method, uri, params);
+            }
+        }
+        Err(AuthError::Unauthorized)
+    }
+
+    fn verify_basic(&self, name: String, password: &str) -> Option<Principal> {
+        match &self.accounts {
+            Accounts::Single(credentials) => credentials
+                .verify(&name, password)
+                .then_some(Principal::Single(name)),
+            Accounts::Users(users) => users
+                .authenticate(&name, password)
+                .map(|user| Principal::User(user.clone())),
+        }
+    }
+
+    fn verify_bearer(&self, token: &str) -> Option<Principal> {
+        match &self.accounts {
+            Accounts::Single(credentials) => credentials
+                .verify_token(token)
+                .then(|| Principal::Single(credentials.user.clone())),
+            Accounts::Users(users) => users
+                .authenticate_token(token)
+                .map(|user| Principal::User(user.clone())),
+        }
+    }
+
+    fn verify_digest(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        params: &str,
+    ) -> Result<Principal, AuthError> {
+        let Accounts::Single(Credentials {
+            user,
+            password: Password::Plain(password),
+            ..
+        }) = &self.accounts
+        else {
+            return Err(AuthError::Unauthorized);
+        };
+        let params = parse_digest_params(params);
+        let param = |name: &str| params.get(name).map(String::as_str);
+        let (Some(username), Some(nonce), Some(digest_uri), Some(response)) = (
+            param("username"),
+            param("nonce"),
+            param("uri"),
+            param("response"),
+        ) else {
+            return Err(AuthError::Unauthorized);
+        };
+        let algorithm = match param("algorithm") {
+            None => DigestAlgorithm::Md5,
+            Some(name) => DigestAlgorithm::from_name(name).ok_or(AuthError::Unauthorized)?,
+        };
+        if param("realm") != Some(REALM) || param("userhash") == Some("true") {
+            return Err(AuthError::Unauthorized);
+        }
+        // the digested uri must be the one requested
+        let request_uri = uri
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or_else(|| uri.path());
+        if digest_uri != request_uri && *uri != *digest_uri {
+            return Err(AuthError::Unauthorized);
+        }
+
+        let ha1 = algorithm.hex(&format!("{}:{}:{}", user, REALM, password));
+        let ha2 = algorithm.hex(&format!("{}:{}", method, digest_uri));
+        // without qop there is no nonce count to detect replayed responses
+        let (Some("auth"), Some(nc), Some(cnonce)) = (param("qop"), param("nc"), param("cnonce"))
+        else {
+            return Err(AuthError::Unauthorized);
+        };
+        let expected = algorithm.hex(&format!("{}:{}:{}:{}:auth:{}", ha1, nonce, nc, cnonce, ha2));
+        let user_matches = constant_time_eq(user, username);
+        if !(user_matches & constant_time_eq(&expected, &response.to_ascii_lowercase())) {
+            return Err(AuthError::Unauthorized);
+        }
+        match self.nonce_age(nonce) {
+            Some(age) if age <= NONCE_TTL => {
+                let nc = u64::from_str_radix(nc, 16).map_err(|_| AuthError::Unauthorized)?;
+                if !self.count_nonce(nonce, cnonce, nc, age) {
+                    warn!(user = %user, "replayed digest response refused");
+                    return Err(AuthError::Unauthorized);
+                }
+                Ok(Principal::Single(user.clone()))
+            }
+            Some(_) => Err(AuthError::StaleNonce),
+            None => Err(AuthError::Unauthorized),
+        }
+    }
+
+    /// Record the use of `nonce` with `nc`, which must be higher than the last one (RFC 7616 3.4)
+    fn count_nonce(&self, nonce: &str, cnonce: &str, nc: u64, age: u64) -> bool {
+        let now = unix_timestamp();
+        let mut counts = self.nonce_counts.lock().unwrap();
+        // expired nonces are refused anyway
+        counts.retain(|_, (_, issued)| now.saturating_sub(*issued) <= NONCE_TTL);
+        let key = (nonce.to_string(), cnonce.to_string());
+        match counts.get_mut(&key) {
+            Some((last, _)) if nc <= *last => false,
+            Some((last, _)) => {
+                *last = nc;
+                true
+            }
+            None => {
+                counts.insert(key, (nc, now.saturating_sub(age)));
+                true
+            }
+        }
+    }
+
+    /// Nonce made of a timestamp and its keyed hash, so no per client state is needed
+    fn nonce_at(&self, timestamp: u64) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.nonce_secret);
+        hasher.update(timestamp.to_be_bytes());
+        format!("{:016x}{:x}", timestamp, hasher.finalize())
+    }
+
+    /// Age in seconds of a nonce issued by this server
+    fn nonce_age(&self, nonce: &str) -> Option<u64> {
+        let timestamp = u64::from_str_radix(nonce.get(..16)?, 16).ok()?;
+        if !constant_time_eq(&self.nonce_at(timestamp), nonce) {
+            return None;
+        }
+        Some(unix_timestamp().saturating_sub(timestamp))
+    }
+
+    /// `WWW-Authenticate` challenges of the enabled schemes
+    pub fn challenges(&self, error: AuthError) -> Vec<String> {
+        let mut challenges = Vec::new();
+        for scheme in &self.schemes {
+            match scheme {
+                AuthScheme::Basic => challenges.push(format!("Basic realm=\"{}\"", REALM)),
+                AuthScheme::Digest => {
+                    let nonce = self.nonce_at(unix_timestamp());
+                    let stale = if error == AuthError::StaleNonce {
+                        ", stale=true"
+                    } else {
+                        ""
+                    };
+                    // strongest algorithm first, older clients only know MD5
+                    for algorithm in ["SHA-256", "MD5"] {
+                        challenges.push(format!(
+                            "Digest realm=\"{}\", qop=\"auth\", algorithm={}, nonce=\"{}\"{}",
+                            REALM, algorithm, nonce, stale
+                        ));
+                    }
+                }
+                AuthScheme::Bearer => challenges.push(format!("Bearer realm=\"{}\"", REALM)),
+            }
+        }
+        challenges
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DigestAlgorithm {
+    Md5,
+    Sha256,
+}
+
+impl DigestAlgorithm {
+    fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("MD5") {
+            Some(DigestAlgorithm::Md5)
+        } else if name.eq_ignore_ascii_case("SHA-256") {
+            Some(DigestAlgorithm::Sha256)
+        } else {
+            None
+        }
+    }
+
+    fn hex(&self, data: &str) -> String {
+        match self {
+            DigestAlgorithm::Md5 => format!("{:x}", Md5::digest(data.as_bytes())),
+            DigestAlgorithm::Sha256 => format!("{:x}", Sha256::digest(data.as_bytes())),
+        }
+    }
+}
+
+/// Parse the comma separated `name=value` pairs of a Digest authorization header
+fn parse_digest_params(input: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut chars = input.chars().peekable();
+    loop {
+        while chars.next_if(|c| *c == ',' || c.is_whitespace()).is_some() {}
+        let name: String = chars
+            .by_ref()
+            .take_while(|c| *c != '=')
+            .collect::<String>()
+            .trim()
+            .to_ascii_lowercase();
+        if name.is_empty() {
+            break;
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                value.push(c);
+            }
+        }
+        params.insert(name, value.trim().to_string());
+    }
+    params
+}
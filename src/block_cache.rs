@@ -0,0 +1,186 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use anyhow::Result;
+use bytes::Bytes;
+use tracing::{debug, warn};
+
+/// Persistent cache of file content on local disk, split into fixed size blocks
+/// and evicted in least recently used order once over capacity
+#[derive(Clone)]
+pub struct BlockCache {
+    dir: PathBuf,
+    block_size: u64,
+    capacity: u64,
+    index: Arc<Mutex<LruIndex>>,
+    /// Suffix of temporary files, so that concurrent writes of a block don't share one
+    tmp_counter: Arc<AtomicU64>,
+}
+
+/// Block file names with their length, ordered by last access
+#[derive(Debug, Default)]
+struct LruIndex {
+    blocks: HashMap<String, (u64, u64)>,
+    order: BTreeMap<u64, String>,
+    size: u64,
+    tick: u64,
+}
+
+impl LruIndex {
+    fn touch(&mut self, name: &str) -> bool {
+        let Some((_, last_used)) = self.blocks.get_mut(name) else {
+            return false;
+        };
+        self.order.remove(last_used);
+        self.tick += 1;
+        *last_used = self.tick;
+        self.order.insert(self.tick, name.to_string());
+        true
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some((len, last_used)) = self.blocks.remove(name) {
+            self.order.remove(&last_used);
+            self.size -= len;
+        }
+    }
+
+    /// Insert a block, returns the blocks evicted to stay within `capacity`
+    fn insert(&mut self, name: String, len: u64, capacity: u64) -> Vec<String> {
+        self.remove(&name);
+        self.tick += 1;
+        self.blocks.insert(name.clone(), (len, self.tick));
+        self.order.insert(self.tick, name);
+        self.size += len;
+        let mut evicted = Vec::new();
+        while self.size > capacity {
+            let Some((_, name)) = self.order.pop_first() else {
+                break;
+            };
+            if let Some((len, _)) = self.blocks.remove(&name) {
+                self.size -= len;
+            }
+            evicted.push(name);
+        }
+        evicted
+    }
+}
+
+impl BlockCache {
+    pub async fn new(dir: PathBuf, capacity: u64, block_size: u64) -> Result<Self> {
+        tokio::fs::create_dir_all(&dir).await?;
+        let cache = Self {
+            dir,
+            block_size: block_size.max(1),
+            capacity,
+            index: Arc::new(Mutex::new(LruIndex::default())),
+            tmp_counter: Arc::default(),
+        };
+        cache.load().await?;
+        Ok(cache)
+    }
+
+    /// Index blocks left by a previous run, least recently modified first
+    async fn load(&self) -> Result<()> {
+        let mut blocks = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !self.is_block(&name) {
+                // leftover of an interrupted write, or a block of another block size
+                let _ = tokio::fs::remove_file(entry.path()).await;
+                continue;
+            }
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            blocks.push((modified, name, metadata.len()));
+        }
+        blocks.sort();
+        debug!(dir = %self.dir.display(), count = blocks.len(), "block cache loaded");
+        let evicted = {
+            let mut index = self.index.lock().unwrap();
+            blocks
+                .into_iter()
+                .flat_map(|(_, name, len)| index.insert(name, len, self.capacity))
+                .collect::<Vec<_>>()
+        };
+        self.remove_blocks(evicted).await;
+        Ok(())
+    }
+
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// Name of a block file, blocks are only valid for the block size they were cut with
+    fn block_name(&self, file_id: &str, content_hash: &str, index: u64) -> String {
+        format!(
+            "{}-{}-{}-{}.blk",
+            file_id, content_hash, self.block_size, index
+        )
+    }
+
+    /// Whether `name` is a block file of the current block size
+    fn is_block(&self, name: &str) -> bool {
+        name.strip_suffix(".blk")
+            .and_then(|name| name.rsplit('-').nth(1))
+            .is_some_and(|block_size| block_size == self.block_size.to_string())
+    }
+
+    pub async fn get(&self, file_id: &str, content_hash: &str, index: u64) -> Option<Bytes> {
+        let name = self.block_name(file_id, content_hash, index);
+        if !self.index.lock().unwrap().touch(&name) {
+            return None;
+        }
+        match tokio::fs::read(self.dir.join(&name)).await {
+            Ok(data) => {
+                debug!(block = %name, "block cache: hit");
+                Some(Bytes::from(data))
+            }
+            Err(err) => {
+                warn!(block = %name, error = %err, "read cached block failed");
+                self.index.lock().unwrap().remove(&name);
+                None
+            }
+        }
+    }
+
+    pub async fn insert(
+        &self,
+        file_id: &str,
+        content_hash: &str,
+        index: u64,
+        data: &Bytes,
+    ) -> Result<()> {
+        let name = self.block_name(file_id, content_hash, index);
+        let path = self.dir.join(&name);
+        // write to a temporary file first so that a crash never leaves a truncated block
+        let tmp = self.tmp_counter.fetch_add(1, Ordering::Relaxed);
+        let tmp_path = self.dir.join(format!("{}.{}.tmp", name, tmp));
+        tokio::fs::write(&tmp_path, data).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        debug!(block = %name, size = data.len(), "block cache: insert");
+        let evicted = self
+            .index
+            .lock()
+            .unwrap()
+            .insert(name, data.len() as u64, self.capacity);
+        self.remove_blocks(evicted).await;
+        Ok(())
+    }
+
+    async fn remove_blocks(&self, names: Vec<String>) {
+        for name in names {
+            debug!(block = %name, "block cache: evict");
+            if let Err(err) = tokio::fs::remove_file(self.dir.join(&name)).await {
+                warn!(block = %name, error = %err, "remove cached block failed");
+            }
+        }
+    }
+}
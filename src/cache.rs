@@ -1,39 +1,177 @@
+use std::collections::HashSet;
 use std::path::Path;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use anyhow::Result;
 use moka::future::Cache as MokaCache;
-use tracing::debug;
+use tracing::{debug, info, warn};
 
 use crate::drive::AliyunFile;
+use crate::meta_store::{MetadataStore, StoredListing};
+use crate::metrics::metrics;
+use crate::path_index::PathIndex;
+
+#[derive(Clone)]
+struct Entry {
+    inserted: Instant,
+    /// File id of the listed directory
+    dir_id: String,
+    files: Vec<AliyunFile>,
+}
+
+/// A file changed in the drive
+pub struct Change {
+    pub file_id: String,
+    /// Folder the file is in after the change, `None` if it was deleted
+    pub parent_file_id: Option<String>,
+}
 
 #[derive(Clone)]
 pub struct Cache {
-    inner: MokaCache<String, Vec<AliyunFile>>,
+    inner: MokaCache<String, Entry>,
+    /// Expiration time in seconds, checked on read so that it can be changed while serving
+    ttl: Arc<AtomicU64>,
+    /// On-disk copy of the listings, kept in sync with the cache when set
+    store: Arc<OnceLock<MetadataStore>>,
+    /// File ids of the paths seen in listings
+    index: PathIndex,
 }
 
 impl Cache {
     pub fn new(max_capacity: u64, ttl: u64) -> Self {
+        let store: Arc<OnceLock<MetadataStore>> = Arc::default();
+        let evicted_store = store.clone();
         let inner = MokaCache::builder()
             .max_capacity(max_capacity)
-            .time_to_live(Duration::from_secs(ttl))
+            .eviction_listener_with_queued_delivery_mode(move |key: Arc<String>, _, cause| {
+                if cause.was_evicted() {
+                    metrics().cache_evictions.inc();
+                    if let Some(store) = evicted_store.get() {
+                        if let Err(err) = store.remove(&[key.to_string()]) {
+                            warn!(key = %key, error = %err, "remove evicted listing from store failed");
+                        }
+                    }
+                }
+            })
             .build();
-        Self { inner }
+        let ttl = Arc::new(AtomicU64::new(ttl));
+        Self {
+            inner,
+            index: PathIndex::new(ttl.clone()),
+            ttl,
+            store,
+        }
+    }
+
+    /// Keep a copy of the listings in `store`, loading the ones it has that are not expired
+    pub fn set_store(&self, store: MetadataStore) -> Result<()> {
+        let ttl = self.ttl.load(Ordering::Relaxed);
+        let now = unix_time();
+        let mut expired = Vec::new();
+        let mut loaded = 0;
+        for (key, listing) in store.load()? {
+            let age = now.saturating_sub(listing.cached_at);
+            match Instant::now().checked_sub(Duration::from_secs(age)) {
+                Some(inserted) if age < ttl => {
+                    self.index.insert_listing(
+                        Path::new(&key),
+                        &listing.dir_id,
+                        &listing.files,
+                        inserted,
+                    );
+                    let entry = Entry {
+                        inserted,
+                        dir_id: listing.dir_id,
+                        files: listing.files,
+                    };
+                    self.inner.blocking().insert(key, entry);
+                    loaded += 1;
+                }
+                _ => expired.push(key),
+            }
+        }
+        store.remove(&expired)?;
+        info!(loaded = loaded, "directory cache loaded from disk");
+        let _ = self.store.set(store);
+        Ok(())
+    }
+
+    /// Run `op` on the store, if any, without blocking the runtime
+    async fn persist(&self, op: impl FnOnce(&MetadataStore) -> Result<()> + Send + 'static) {
+        let Some(store) = self.store.get().cloned() else {
+            return;
+        };
+        match tokio::task::spawn_blocking(move || op(&store)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => warn!(error = %err, "update metadata store failed"),
+            Err(err) => warn!(error = %err, "update metadata store failed"),
+        }
+    }
+
+    /// Change feed cursor the stored listings are up to date with
+    pub fn saved_cursor(&self) -> Option<String> {
+        let store = self.store.get()?;
+        store
+            .cursor()
+            .map_err(|err| warn!(error = %err, "read change cursor failed"))
+            .ok()
+            .flatten()
+    }
+
+    pub async fn save_cursor(&self, cursor: &str) {
+        let cursor = cursor.to_string();
+        self.persist(move |store| store.set_cursor(&cursor)).await;
+    }
+
+    pub fn set_ttl(&self, ttl: u64) {
+        self.ttl.store(ttl, Ordering::Relaxed);
     }
 
     pub fn get(&self, key: &str) -> Option<Vec<AliyunFile>> {
         debug!(key = %key, "cache: get");
-        self.inner.get(key)
+        let ttl = Duration::from_secs(self.ttl.load(Ordering::Relaxed));
+        let value = self
+            .inner
+            .get(key)
+            .and_then(|entry| (entry.inserted.elapsed() < ttl).then_some(entry.files));
+        if value.is_some() {
+            metrics().cache_hits.inc();
+        } else {
+            metrics().cache_misses.inc();
+        }
+        value
     }
 
-    pub async fn insert(&self, key: String, value: Vec<AliyunFile>) {
+    pub async fn insert(&self, key: String, dir_id: String, files: Vec<AliyunFile>) {
         debug!(key = %key, "cache: insert");
-        self.inner.insert(key, value).await;
+        if self.store.get().is_some() {
+            let listing = StoredListing {
+                cached_at: unix_time(),
+                dir_id: dir_id.clone(),
+                files: files.clone(),
+            };
+            let stored_key = key.clone();
+            self.persist(move |store| store.put(&stored_key, &listing))
+                .await;
+        }
+        let inserted = Instant::now();
+        self.index
+            .insert_listing(Path::new(&key), &dir_id, &files, inserted);
+        let entry = Entry {
+            inserted,
+            dir_id,
+            files,
+        };
+        self.inner.insert(key, entry).await;
     }
 
     pub async fn invalidate(&self, path: &Path) {
         let key = path.to_string_lossy().into_owned();
         debug!(path = %path.display(), key = %key, "cache: invalidate");
         self.inner.invalidate(&key).await;
+        self.persist(move |store| store.remove(&[key])).await;
     }
 
     pub async fn invalidate_parent(&self, path: &Path) {
@@ -42,8 +180,79 @@ impl Cache {
         }
     }
 
+    /// Invalidate the directory at `path` and every directory below it
+    pub async fn invalidate_subtree(&self, path: &Path) {
+        let prefix = path.to_string_lossy();
+        debug!(path = %path.display(), "cache: invalidate subtree");
+        let prefix = prefix.trim_end_matches('/');
+        for (key, _) in self.inner.iter() {
+            let below = key
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+            if below {
+                self.inner.invalidate(key.as_str()).await;
+            }
+        }
+        self.index.remove(path);
+        let prefix = prefix.to_string();
+        self.persist(move |store| store.remove_subtree(&prefix))
+            .await;
+    }
+
+    /// Invalidate the listings of the directories changed files were or now are in,
+    /// and everything below changed directories as their paths may have changed
+    pub async fn invalidate_changes(&self, changes: &[Change]) {
+        let changed: HashSet<&str> = changes.iter().map(|c| c.file_id.as_str()).collect();
+        let parents: HashSet<&str> = changes
+            .iter()
+            .filter_map(|c| c.parent_file_id.as_deref())
+            .collect();
+        let mut keys = Vec::new();
+        let mut subtrees = Vec::new();
+        for (key, entry) in self.inner.iter() {
+            if changed.contains(entry.dir_id.as_str()) {
+                subtrees.push(key.to_string());
+            } else if parents.contains(entry.dir_id.as_str())
+                || entry.files.iter().any(|f| changed.contains(f.id.as_str()))
+            {
+                keys.push(key.to_string());
+            }
+        }
+        debug!(
+            changes = changes.len(),
+            invalidated = keys.len() + subtrees.len(),
+            "cache: invalidate changes"
+        );
+        self.index.remove_ids(&changed);
+        for key in &keys {
+            self.inner.invalidate(key).await;
+        }
+        self.persist(move |store| store.remove(&keys)).await;
+        for key in subtrees {
+            self.invalidate_subtree(Path::new(&key)).await;
+        }
+    }
+
+    /// File ids of paths in the drive, filled from the cached listings
+    pub fn index(&self) -> &PathIndex {
+        &self.index
+    }
+
     pub fn invalidate_all(&self) {
         debug!("cache: invalidate all");
         self.inner.invalidate_all();
+        self.index.clear();
+        if let Some(store) = self.store.get() {
+            if let Err(err) = store.clear() {
+                warn!(error = %err, "clear metadata store failed");
+            }
+        }
     }
 }
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
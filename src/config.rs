@@ -0,0 +1,144 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use anyhow::{bail, Context, Result};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::Command;
+
+/// Settings that can be changed while serving, shared by every clone of the file systems
+#[derive(Debug)]
+pub struct LiveSettings {
+    read_only: AtomicBool,
+    read_buffer_size: AtomicUsize,
+    upload_buffer_size: AtomicUsize,
+}
+
+impl Default for LiveSettings {
+    fn default() -> Self {
+        Self {
+            read_only: AtomicBool::new(false),
+            read_buffer_size: AtomicUsize::new(10 * 1024 * 1024),
+            upload_buffer_size: AtomicUsize::new(16 * 1024 * 1024),
+        }
+    }
+}
+
+impl LiveSettings {
+    pub fn read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    pub fn read_buffer_size(&self) -> usize {
+        self.read_buffer_size.load(Ordering::Relaxed)
+    }
+
+    pub fn set_read_buffer_size(&self, read_buffer_size: usize) {
+        self.read_buffer_size
+            .store(read_buffer_size.max(1), Ordering::Relaxed);
+    }
+
+    pub fn upload_buffer_size(&self) -> usize {
+        self.upload_buffer_size.load(Ordering::Relaxed)
+    }
+
+    pub fn set_upload_buffer_size(&self, upload_buffer_size: usize) {
+        self.upload_buffer_size
+            .store(upload_buffer_size.max(1), Ordering::Relaxed);
+    }
+}
+
+/// Prepend the settings of the TOML file given by `--config` to the command line arguments
+///
+/// Keys are the option names with underscores, e.g. `read_only = true` or `auth_user = "admin"`.
+/// Options given on the command line or in the environment take precedence over the file.
+pub fn args_with_config(command: &Command, args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let matches = match command.clone().try_get_matches_from(&args) {
+        Ok(matches) => matches,
+        // let clap print help and version itself
+        Err(err)
+            if matches!(
+                err.kind(),
+                ErrorKind::DisplayHelp
+                    | ErrorKind::DisplayVersion
+                    | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+            ) =>
+        {
+            return Ok(args)
+        }
+        // an invalid argument must not silently drop the config file
+        Err(err) => return Err(err.into()),
+    };
+    let Some(path) = matches.get_one::<PathBuf>("config") else {
+        return Ok(args);
+    };
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("read config file {} failed", path.display()))?;
+    let table: toml::Table = toml::from_str(&content)
+        .with_context(|| format!("parse config file {} failed", path.display()))?;
+
+    let mut config_args = Vec::new();
+    for (key, value) in table {
+        let id = key.replace('-', "_");
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id.as_str() && arg.get_long().is_some())
+        else {
+            bail!(
+                "unknown setting `{}` in config file {}",
+                key,
+                path.display()
+            );
+        };
+        if id == "config" {
+            bail!(
+                "config file {} can not include another config",
+                path.display()
+            );
+        }
+        if matches!(
+            matches.value_source(&id),
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
+        let long = arg.get_long().unwrap();
+        let values = match value {
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::String(value) => value,
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                toml::Value::Boolean(value) if !arg.get_action().takes_values() => {
+                    if value {
+                        config_args.push(OsString::from(format!("--{}", long)));
+                    }
+                    continue;
+                }
+                toml::Value::Boolean(value) => value.to_string(),
+                _ => bail!(
+                    "unsupported value of `{}` in config file {}",
+                    key,
+                    path.display()
+                ),
+            };
+            config_args.push(OsString::from(format!("--{}={}", long, value)));
+        }
+    }
+
+    let mut args = args.into_iter();
+    Ok(args
+        .next()
+        .into_iter()
+        .chain(config_args)
+        .chain(args)
+        .collect())
+}
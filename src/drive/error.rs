@@ -0,0 +1,158 @@
+//! Errors of OpenAPI calls, classified by the `code` of the response body
+
+use std::cell::Cell;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use dav_server::fs::FsError;
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+/// `Retry-After` sent to clients when the drive didn't say how long to wait
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+tokio::task_local! {
+    /// Failures of the drive calls made for the current request
+    static FAILURES: Cell<Failures>;
+}
+
+/// How the drive calls of a request failed, to answer with a more precise status
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Failures {
+    /// How long the client should wait, set when the drive was unavailable
+    pub retry_after: Option<Duration>,
+    /// The drive refused to replace a file with the same name
+    pub conflict: bool,
+}
+
+fn record(f: impl FnOnce(&mut Failures)) {
+    let _ = FAILURES.try_with(|cell| {
+        let mut failures = cell.get();
+        f(&mut failures);
+        cell.set(failures);
+    });
+}
+
+/// Body of a failed OpenAPI call
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: StatusCode,
+    #[serde(default)]
+    pub code: String,
+    #[serde(default)]
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum DriveError {
+    /// Not enough space left in the drive
+    QuotaExhausted(ApiError),
+    /// A file with the same name exists
+    AlreadyExists(ApiError),
+    NotFound(ApiError),
+    /// Not allowed, e.g. on files in the recycle bin
+    Forbidden(ApiError),
+    /// Throttled or failing even after retrying, with the delay the drive asked for
+    Unavailable(ApiError, Option<Duration>),
+    Other(ApiError),
+}
+
+impl DriveError {
+    /// Classify the failed response with `status` and `body`
+    pub fn new(status: StatusCode, body: &str, retry_after: Option<Duration>) -> Self {
+        let mut err: ApiError = serde_json::from_str(body).unwrap_or_else(|_| ApiError {
+            message: body.to_string(),
+            ..Default::default()
+        });
+        err.status = status;
+        let code = err.code.as_str();
+        if code.starts_with("QuotaExhausted") || status == StatusCode::INSUFFICIENT_STORAGE {
+            DriveError::QuotaExhausted(err)
+        } else if code.starts_with("AlreadyExist") || status == StatusCode::CONFLICT {
+            DriveError::AlreadyExists(err)
+        } else if code.starts_with("NotFound") || status == StatusCode::NOT_FOUND {
+            DriveError::NotFound(err)
+        } else if code.starts_with("Forbidden") || status == StatusCode::FORBIDDEN {
+            DriveError::Forbidden(err)
+        } else if code == "TooManyRequests"
+            || matches!(
+                status,
+                StatusCode::TOO_MANY_REQUESTS
+                    | StatusCode::REQUEST_TIMEOUT
+                    | StatusCode::INTERNAL_SERVER_ERROR
+                    | StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            )
+        {
+            DriveError::Unavailable(err, retry_after)
+        } else {
+            DriveError::Other(err)
+        }
+    }
+
+    pub fn api_error(&self) -> &ApiError {
+        match self {
+            DriveError::QuotaExhausted(err)
+            | DriveError::AlreadyExists(err)
+            | DriveError::NotFound(err)
+            | DriveError::Forbidden(err)
+            | DriveError::Unavailable(err, _)
+            | DriveError::Other(err) => err,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.api_error().status
+    }
+}
+
+impl fmt::Display for DriveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let err = self.api_error();
+        write!(f, "{} {}: {}", err.status, err.code, err.message)
+    }
+}
+
+impl std::error::Error for DriveError {}
+
+impl From<&DriveError> for FsError {
+    fn from(err: &DriveError) -> Self {
+        match err {
+            DriveError::QuotaExhausted(_) => FsError::InsufficientStorage,
+            DriveError::AlreadyExists(_) => {
+                // answered with 409 or 412 by the server instead of 405
+                record(|failures| failures.conflict = true);
+                FsError::Exists
+            }
+            DriveError::NotFound(_) => FsError::NotFound,
+            DriveError::Forbidden(_) => FsError::Forbidden,
+            DriveError::Unavailable(_, retry_after) => {
+                // answered with 503 by the server instead of 500
+                record(|failures| {
+                    failures.retry_after = Some(retry_after.unwrap_or(DEFAULT_RETRY_AFTER))
+                });
+                FsError::GeneralFailure
+            }
+            DriveError::Other(_) => FsError::GeneralFailure,
+        }
+    }
+}
+
+/// File system error of a failed drive operation
+pub fn fs_error(err: &anyhow::Error) -> FsError {
+    err.downcast_ref::<DriveError>()
+        .map_or(FsError::GeneralFailure, FsError::from)
+}
+
+/// Run `f`, returning how its drive calls failed
+pub async fn track_failures<F: Future>(f: F) -> (F::Output, Failures) {
+    FAILURES
+        .scope(Cell::default(), async move {
+            let output = f.await;
+            (output, FAILURES.with(Cell::get))
+        })
+        .await
+}
@@ -1,13 +1,14 @@
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{bail, Context, Result};
 use bytes::Bytes;
 use clap::ValueEnum;
 use dav_server::fs::{DavDirEntry, DavMetaData, FsFuture, FsResult};
 use futures_util::future::FutureExt;
+use md5::{Digest, Md5};
 use reqwest::{
     header::{HeaderMap, HeaderValue},
     IntoUrl, StatusCode,
@@ -15,24 +16,34 @@ use reqwest::{
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tokio::{
     sync::{oneshot, RwLock},
     time,
 };
 use tracing::{debug, error, info, warn};
 
+use crate::metrics::metrics;
+
+mod error;
 pub mod model;
+mod rate_limit;
 
+pub use error::{fs_error, track_failures, DriveError};
 use model::*;
 pub use model::{AliyunFile, DateTime, FileType};
+pub use rate_limit::RateLimits;
+use rate_limit::{Endpoint, RateLimiter};
 
 const ORIGIN: &str = "https://www.aliyundrive.com";
 const REFERER: &str = "https://www.aliyundrive.com/";
+/// Retries of an OpenAPI call failing with a transient error
+const MAX_RETRIES: u32 = 5;
 const UA: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/99.0.4844.83 Safari/537.36";
 
 /// Aliyundrive drive type
-#[derive(Debug, Clone, Copy, ValueEnum)]
+#[derive(Debug, Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DriveType {
     /// Resource drive
     Resource,
@@ -50,18 +61,33 @@ pub struct DriveConfig {
     pub client_id: Option<String>,
     pub client_secret: Option<String>,
     pub drive_type: Option<DriveType>,
+    pub rate_limits: RateLimits,
 }
 
 #[derive(Debug, Clone)]
 struct Credentials {
     refresh_token: String,
     access_token: Option<String>,
+    expires_at: Option<Instant>,
+    /// Error of the last token refresh if it failed
+    refresh_error: Option<String>,
+}
+
+/// Access token state reported by the admin API
+#[derive(Debug, Serialize)]
+pub struct TokenStatus {
+    /// Seconds until the access token expires
+    pub expires_in: Option<u64>,
+    pub refresh_error: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct AliyunDrive {
     config: DriveConfig,
     client: ClientWithMiddleware,
+    /// Client of OpenAPI calls, which are retried by [`request`](Self::request) instead
+    api_client: reqwest::Client,
+    rate_limiter: RateLimiter,
     credentials: Arc<RwLock<Credentials>>,
     drive_id: Option<String>,
 }
@@ -72,6 +98,8 @@ impl AliyunDrive {
         let credentials = Credentials {
             refresh_token,
             access_token: None,
+            expires_at: None,
+            refresh_error: None,
         };
         let mut headers = HeaderMap::new();
         headers.insert("Origin", HeaderValue::from_static(ORIGIN));
@@ -94,13 +122,17 @@ impl AliyunDrive {
             .connect_timeout(Duration::from_secs(10))
             .timeout(Duration::from_secs(30))
             .build()?;
+        let api_client = client.clone();
         let client = ClientBuilder::new(client)
             .with(RetryTransientMiddleware::new_with_policy(retry_policy))
             .build();
-        let drive_type = config.drive_type.clone();
+        let drive_type = config.drive_type;
+        let rate_limiter = RateLimiter::new(config.rate_limits);
         let mut drive = Self {
             config,
             client,
+            api_client,
+            rate_limiter,
             credentials: Arc::new(RwLock::new(credentials)),
             drive_id: None,
         };
@@ -162,6 +194,19 @@ impl AliyunDrive {
         Ok(drive)
     }
 
+    /// Another drive of the same account, sharing credentials and the token refresh loop
+    pub async fn with_drive_type(&self, drive_type: Option<DriveType>) -> Result<Self> {
+        let mut drive = self.clone();
+        let drive_id = drive
+            .get_drive_id(drive_type)
+            .await
+            .context("get drive id failed")?;
+        debug!(drive_id = %drive_id, drive_type = ?drive_type, "found drive");
+        drive.config.drive_type = drive_type;
+        drive.drive_id = Some(drive_id);
+        Ok(drive)
+    }
+
     async fn save_refresh_token(&self, refresh_token: &str) -> Result<()> {
         if let Some(dir) = self.config.workdir.as_ref() {
             tokio::fs::create_dir_all(dir).await?;
@@ -220,6 +265,8 @@ impl AliyunDrive {
                     let mut cred = self.credentials.write().await;
                     cred.refresh_token = res.refresh_token.clone();
                     cred.access_token = Some(res.access_token.clone());
+                    cred.expires_at = Some(Instant::now() + Duration::from_secs(res.expires_in));
+                    cred.refresh_error = None;
                     if let Err(err) = self.save_refresh_token(&res.refresh_token).await {
                         error!(error = %err, "save refresh token failed");
                     }
@@ -259,7 +306,9 @@ impl AliyunDrive {
                 }
             }
         }
-        Err(last_err.unwrap())
+        let err = last_err.unwrap();
+        self.credentials.write().await.refresh_error = Some(err.to_string());
+        Err(err)
     }
 
     async fn refresh_token(&self) -> String {
@@ -272,7 +321,45 @@ impl AliyunDrive {
         cred.access_token.clone().context("missing access_token")
     }
 
-    fn drive_id(&self) -> Result<&str> {
+    /// Check whether the drive can serve requests, i.e. it has a drive id
+    /// and an unexpired access token from a successful refresh
+    pub async fn check_ready(&self) -> Result<()> {
+        self.drive_id()?;
+        let cred = self.credentials.read().await;
+        if let Some(err) = cred.refresh_error.as_ref() {
+            bail!("last token refresh failed: {}", err);
+        }
+        match cred.expires_at {
+            _ if cred.access_token.is_none() => bail!("missing access_token"),
+            Some(expires_at) if expires_at <= Instant::now() => bail!("access_token expired"),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn token_status(&self) -> TokenStatus {
+        let cred = self.credentials.read().await;
+        TokenStatus {
+            expires_in: cred.expires_at.map(|expires_at| {
+                expires_at
+                    .saturating_duration_since(Instant::now())
+                    .as_secs()
+            }),
+            refresh_error: cred.refresh_error.clone(),
+        }
+    }
+
+    /// Refresh the access token now instead of waiting for the background task
+    pub async fn refresh_access_token(&self) -> Result<()> {
+        self.do_refresh_token_with_retry(None).await?;
+        Ok(())
+    }
+
+    /// Whether both drives belong to the same account and share credentials
+    pub fn same_account(&self, other: &AliyunDrive) -> bool {
+        Arc::ptr_eq(&self.credentials, &other.credentials)
+    }
+
+    pub fn drive_id(&self) -> Result<&str> {
         self.drive_id.as_deref().context("missing drive_id")
     }
 
@@ -283,65 +370,74 @@ impl AliyunDrive {
     {
         let mut access_token = self.access_token().await?;
         let url = reqwest::Url::parse(&url)?;
-        let res = self
-            .client
-            .post(url.clone())
-            .bearer_auth(&access_token)
-            .json(&req)
-            .send()
-            .await?;
-        match res.error_for_status_ref() {
-            Ok(_) => {
-                if res.status() == StatusCode::NO_CONTENT {
+        let endpoint = Endpoint::of(url.path());
+        let mut refreshed = false;
+        let mut attempt = 0;
+        loop {
+            self.rate_limiter.acquire(endpoint).await;
+            // time each attempt, not the waits between retries
+            let timer = metrics()
+                .upstream_duration
+                .with_label_values(&[url.path()])
+                .start_timer();
+            let res = self
+                .api_client
+                .post(url.clone())
+                .bearer_auth(&access_token)
+                .json(&req)
+                .send()
+                .await;
+            timer.observe_duration();
+            let res = match res {
+                Ok(res) => res,
+                Err(err) if (err.is_connect() || err.is_timeout()) && attempt < MAX_RETRIES => {
+                    debug!(error = %err, url = %url, "request failed, retrying");
+                    time::sleep(backoff(attempt)).await;
+                    attempt += 1;
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let status = res.status();
+            if status.is_success() {
+                self.rate_limiter.succeeded(endpoint);
+                if status == StatusCode::NO_CONTENT {
                     return Ok(None);
                 }
-                // let res = res.text().await?;
-                // println!("{}: {}", url, res);
-                // let res = serde_json::from_str(&res)?;
                 let res = res.json::<U>().await?;
-                Ok(Some(res))
+                return Ok(Some(res));
             }
-            Err(err) => {
-                let err_msg = res.text().await?;
-                debug!(error = %err_msg, url = %url, "request failed");
-                match err.status() {
-                    Some(
-                        status_code
-                        @
-                        // 4xx
-                        (StatusCode::UNAUTHORIZED
-                        | StatusCode::REQUEST_TIMEOUT
-                        | StatusCode::TOO_MANY_REQUESTS
-                        // 5xx
-                        | StatusCode::INTERNAL_SERVER_ERROR
-                        | StatusCode::BAD_GATEWAY
-                        | StatusCode::SERVICE_UNAVAILABLE
-                        | StatusCode::GATEWAY_TIMEOUT),
-                    ) => {
-                        if status_code == StatusCode::UNAUTHORIZED {
-                            // refresh token and retry
-                            let token_res = self.do_refresh_token_with_retry(None).await?;
-                            access_token = token_res.access_token;
-                        } else {
-                            // wait for a while and retry
-                            time::sleep(Duration::from_secs(1)).await;
-                        }
-                        let res = self
-                            .client
-                            .post(url)
-                            .bearer_auth(&access_token)
-                            .json(&req)
-                            .send()
-                            .await?
-                            .error_for_status()?;
-                        if res.status() == StatusCode::NO_CONTENT {
-                            return Ok(None);
-                        }
-                        let res = res.json::<U>().await?;
-                        Ok(Some(res))
-                    }
-                    _ => Err(err.into()),
+            let retry_after = rate_limit::retry_after(res.headers());
+            let err_msg = res.text().await?;
+            debug!(error = %err_msg, url = %url, "request failed");
+            match status {
+                StatusCode::UNAUTHORIZED if !refreshed => {
+                    // refresh token and retry
+                    metrics().token_refreshes.inc();
+                    let token_res = self.do_refresh_token_with_retry(None).await?;
+                    access_token = token_res.access_token;
+                    refreshed = true;
+                }
+                StatusCode::TOO_MANY_REQUESTS if attempt < MAX_RETRIES => {
+                    metrics().throttled.inc();
+                    let delay = retry_after.unwrap_or_else(|| backoff(attempt));
+                    warn!(url = %url, delay = ?delay, "request throttled");
+                    // every request of this kind waits, not only the retry
+                    self.rate_limiter.throttled(endpoint, delay);
+                    attempt += 1;
                 }
+                StatusCode::REQUEST_TIMEOUT
+                | StatusCode::INTERNAL_SERVER_ERROR
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT
+                    if attempt < MAX_RETRIES =>
+                {
+                    // wait for a while and retry
+                    time::sleep(retry_after.unwrap_or_else(|| backoff(attempt))).await;
+                    attempt += 1;
+                }
+                _ => return Err(DriveError::new(status, &err_msg, retry_after).into()),
             }
         }
     }
@@ -382,43 +478,8 @@ impl AliyunDrive {
             .and_then(|res| res.context("expect response"));
         match res {
             Ok(file) => Ok(Some(file.into())),
-            Err(err) => {
-                if let Some(req_err) = err.downcast_ref::<reqwest::Error>() {
-                    if matches!(req_err.status(), Some(StatusCode::NOT_FOUND)) {
-                        Ok(None)
-                    } else {
-                        Err(err)
-                    }
-                } else {
-                    Err(err)
-                }
-            }
-        }
-    }
-
-    pub async fn get_by_path(&self, path: &str) -> Result<Option<AliyunFile>> {
-        let drive_id = self.drive_id()?;
-        debug!(drive_id = %drive_id, path = %path, "get file by path");
-        if path == "/" || path.is_empty() {
-            return Ok(Some(AliyunFile::new_root()));
-        }
-        let req = GetFileByPathRequest {
-            drive_id,
-            file_path: path,
-        };
-        let res: Result<AliyunFile> = self
-            .request(
-                format!(
-                    "{}/adrive/v1.0/openFile/get_by_path",
-                    self.config.api_base_url
-                ),
-                &req,
-            )
-            .await
-            .and_then(|res| res.context("expect response"));
-        match res {
-            Ok(file) => Ok(Some(file)),
-            Err(_) => Ok(None),
+            Err(err) if matches!(err.downcast_ref(), Some(DriveError::NotFound(_))) => Ok(None),
+            Err(err) => Err(err),
         }
     }
 
@@ -478,7 +539,9 @@ impl AliyunDrive {
             debug!(url = %url, "download file");
             self.client.get(url).send().await?.error_for_status()?
         };
-        Ok(res.bytes().await?)
+        let bytes = res.bytes().await?;
+        metrics().downloaded_bytes.inc_by(bytes.len() as u64);
+        Ok(bytes)
     }
 
     pub async fn get_download_url(&self, file_id: &str) -> Result<GetFileDownloadUrlResponse> {
@@ -517,11 +580,11 @@ impl AliyunDrive {
             )
             .await;
         if let Err(err) = res {
-            if let Some(req_err) = err.downcast_ref::<reqwest::Error>() {
+            if let Some(drive_err) = err.downcast_ref::<DriveError>() {
                 // Ignore 404 and 400 status codes
                 if !matches!(
-                    req_err.status(),
-                    Some(StatusCode::NOT_FOUND | StatusCode::BAD_REQUEST)
+                    drive_err.status(),
+                    StatusCode::NOT_FOUND | StatusCode::BAD_REQUEST
                 ) {
                     return Err(err);
                 }
@@ -532,7 +595,7 @@ impl AliyunDrive {
 
     async fn delete_file(&self, file_id: &str) -> Result<()> {
         debug!(file_id = %file_id, "delete file");
-        let req = TrashRequest {
+        let req = DeleteFileRequest {
             drive_id: self.drive_id()?,
             file_id,
         };
@@ -543,11 +606,11 @@ impl AliyunDrive {
             )
             .await;
         if let Err(err) = res {
-            if let Some(req_err) = err.downcast_ref::<reqwest::Error>() {
+            if let Some(drive_err) = err.downcast_ref::<DriveError>() {
                 // Ignore 404 and 400 status codes
                 if !matches!(
-                    req_err.status(),
-                    Some(StatusCode::NOT_FOUND | StatusCode::BAD_REQUEST)
+                    drive_err.status(),
+                    StatusCode::NOT_FOUND | StatusCode::BAD_REQUEST
                 ) {
                     return Err(err);
                 }
@@ -556,6 +619,137 @@ impl AliyunDrive {
         Ok(())
     }
 
+    /// Files matching `query` in the drive's search syntax, e.g. `name match "report"`,
+    /// most recently updated first and at most `max_results` of them
+    pub async fn search_all(&self, query: &str, max_results: usize) -> Result<Vec<ListFileItem>> {
+        let drive_id = self.drive_id()?;
+        let mut items = Vec::new();
+        let mut marker: Option<String> = None;
+        loop {
+            debug!(drive_id = %drive_id, query = %query, marker = ?marker, "search file");
+            let req = SearchFileRequest {
+                drive_id,
+                query,
+                limit: 100,
+                marker: marker.as_deref(),
+                order_by: "updated_at DESC",
+            };
+            let res: ListFileResponse = self
+                .request(
+                    format!("{}/adrive/v1.0/openFile/search", self.config.api_base_url),
+                    &req,
+                )
+                .await
+                .and_then(|res| res.context("expect response"))?;
+            items.extend(res.items);
+            if res.next_marker.is_empty() || items.len() >= max_results {
+                break;
+            }
+            marker = Some(res.next_marker);
+        }
+        items.truncate(max_results);
+        Ok(items)
+    }
+
+    /// Cursor of the latest change of the drive, the start of [`list_delta_all`](Self::list_delta_all)
+    pub async fn get_last_cursor(&self) -> Result<String> {
+        let req = GetLastCursorRequest {
+            drive_id: self.drive_id()?,
+        };
+        let res: GetLastCursorResponse = self
+            .request(
+                format!(
+                    "{}/adrive/v1.0/openFile/get_last_cursor",
+                    self.config.api_base_url
+                ),
+                &req,
+            )
+            .await
+            .and_then(|res| res.context("expect response"))?;
+        Ok(res.cursor)
+    }
+
+    /// Changes of the drive after `cursor`, returning the cursor to continue from
+    pub async fn list_delta_all(&self, cursor: &str) -> Result<(Vec<DeltaItem>, String)> {
+        let drive_id = self.drive_id()?;
+        let mut items = Vec::new();
+        let mut cursor = cursor.to_string();
+        loop {
+            debug!(drive_id = %drive_id, cursor = %cursor, "list delta");
+            let req = ListDeltaRequest {
+                drive_id,
+                cursor: &cursor,
+                limit: 100,
+            };
+            let res: ListDeltaResponse = self
+                .request(
+                    format!(
+                        "{}/adrive/v1.0/openFile/list_delta",
+                        self.config.api_base_url
+                    ),
+                    &req,
+                )
+                .await
+                .and_then(|res| res.context("expect response"))?;
+            items.extend(res.items);
+            cursor = res.cursor;
+            if !res.has_more {
+                break;
+            }
+        }
+        Ok((items, cursor))
+    }
+
+    /// All items in the recycle bin, folders are listed without their children
+    pub async fn list_recyclebin_all(&self) -> Result<Vec<ListFileItem>> {
+        let drive_id = self.drive_id()?;
+        let mut items = Vec::new();
+        let mut marker: Option<String> = None;
+        loop {
+            debug!(drive_id = %drive_id, marker = ?marker, "list recyclebin");
+            let req = ListRecycleBinRequest {
+                drive_id,
+                limit: 200,
+                marker: marker.as_deref(),
+            };
+            let res: ListFileResponse = self
+                .request(
+                    format!(
+                        "{}/adrive/v1.0/openFile/recyclebin/list",
+                        self.config.api_base_url
+                    ),
+                    &req,
+                )
+                .await
+                .and_then(|res| res.context("expect response"))?;
+            items.extend(res.items);
+            if res.next_marker.is_empty() {
+                break;
+            }
+            marker = Some(res.next_marker);
+        }
+        Ok(items)
+    }
+
+    /// Restore a file or folder from the recycle bin to where it was trashed from
+    pub async fn restore_file(&self, file_id: &str) -> Result<()> {
+        debug!(file_id = %file_id, "restore file");
+        let req = RestoreFileRequest {
+            drive_id: self.drive_id()?,
+            file_id,
+        };
+        let _res: Option<serde::de::IgnoredAny> = self
+            .request(
+                format!(
+                    "{}/adrive/v1.0/openFile/recyclebin/restore",
+                    self.config.api_base_url
+                ),
+                &req,
+            )
+            .await?;
+        Ok(())
+    }
+
     pub async fn remove_file(&self, file_id: &str, trash: bool) -> Result<()> {
         if trash {
             self.trash(file_id).await?;
@@ -565,7 +759,8 @@ impl AliyunDrive {
         Ok(())
     }
 
-    pub async fn create_folder(&self, parent_file_id: &str, name: &str) -> Result<()> {
+    /// Create a folder and return its file id
+    pub async fn create_folder(&self, parent_file_id: &str, name: &str) -> Result<String> {
         debug!(parent_file_id = %parent_file_id, name = %name, "create folder");
         let req = CreateFolderRequest {
             check_name_mode: "refuse",
@@ -574,13 +769,14 @@ impl AliyunDrive {
             parent_file_id,
             r#type: "folder",
         };
-        let _res: Option<serde::de::IgnoredAny> = self
+        let res: CreateFolderResponse = self
             .request(
                 format!("{}/adrive/v1.0/openFile/create", self.config.api_base_url),
                 &req,
             )
-            .await?;
-        Ok(())
+            .await
+            .and_then(|res| res.context("expect response"))?;
+        Ok(res.file_id)
     }
 
     pub async fn rename_file(&self, file_id: &str, name: &str) -> Result<()> {
@@ -622,7 +818,8 @@ impl AliyunDrive {
         Ok(())
     }
 
-    pub async fn copy_file(&self, file_id: &str, to_parent_file_id: &str) -> Result<()> {
+    /// Copy `file_id` into `to_parent_file_id`, returning the id of the copy
+    pub async fn copy_file(&self, file_id: &str, to_parent_file_id: &str) -> Result<String> {
         debug!(file_id = %file_id, to_parent_file_id = %to_parent_file_id, "copy file");
         let drive_id = self.drive_id()?;
         let req = CopyFileRequest {
@@ -631,13 +828,26 @@ impl AliyunDrive {
             to_parent_file_id,
             auto_rename: false,
         };
-        let _res: Option<serde::de::IgnoredAny> = self
+        let res: CopyFileResponse = self
             .request(
                 format!("{}/adrive/v1.0/openFile/copy", self.config.api_base_url),
                 &req,
             )
-            .await?;
-        Ok(())
+            .await
+            .and_then(|res| res.context("expect response"))?;
+        Ok(res.file_id)
+    }
+
+    /// Offset of the bytes used to compute `proof_code` for rapid upload,
+    /// which is derived from the current access token
+    pub async fn proof_offset(&self, size: u64) -> Result<u64> {
+        if size == 0 {
+            bail!("can not compute proof offset of empty file");
+        }
+        let access_token = self.access_token().await?;
+        let hash = format!("{:x}", Md5::digest(access_token.as_bytes()));
+        let n = u64::from_str_radix(&hash[..16], 16)?;
+        Ok(n % size)
     }
 
     pub async fn create_file_with_proof(
@@ -646,8 +856,9 @@ impl AliyunDrive {
         parent_file_id: &str,
         size: u64,
         chunk_count: u64,
+        content_hash: Option<&ContentHash>,
     ) -> Result<CreateFileWithProofResponse> {
-        debug!(name = %name, parent_file_id = %parent_file_id, size = size, "create file with proof");
+        debug!(name = %name, parent_file_id = %parent_file_id, size = size, rapid = content_hash.is_some(), "create file with proof");
         let drive_id = self.drive_id()?;
         let part_info_list = (1..=chunk_count)
             .map(|part_number| UploadPartInfo {
@@ -657,12 +868,18 @@ impl AliyunDrive {
             .collect();
         let req = CreateFileWithProofRequest {
             check_name_mode: "refuse",
-            content_hash: "",
-            content_hash_name: "none",
+            content_hash: content_hash.map(|h| h.sha1.as_str()).unwrap_or_default(),
+            content_hash_name: if content_hash.is_some() {
+                "sha1"
+            } else {
+                "none"
+            },
             drive_id,
             name,
             parent_file_id,
-            proof_code: "",
+            proof_code: content_hash
+                .map(|h| h.proof_code.as_str())
+                .unwrap_or_default(),
             proof_version: "v1",
             size,
             part_info_list,
@@ -696,6 +913,7 @@ impl AliyunDrive {
     }
 
     pub async fn upload(&self, url: &str, body: Bytes) -> Result<()> {
+        let len = body.len() as u64;
         let res = self.client.put(url).body(body).send().await?;
         if let Err(err) = res.error_for_status_ref() {
             let detail = res
@@ -704,6 +922,7 @@ impl AliyunDrive {
                 .unwrap_or_else(|_| "unknown error".to_string());
             bail!("{}: {}", err, detail);
         }
+        metrics().uploaded_bytes.inc_by(len);
         Ok(())
     }
 
@@ -781,7 +1000,7 @@ impl DavDirEntry for AliyunFile {
         self.name.as_bytes().to_vec()
     }
 
-    fn metadata(&self) -> FsFuture<Box<dyn DavMetaData>> {
+    fn metadata(&self) -> FsFuture<'_, Box<dyn DavMetaData>> {
         async move { Ok(Box::new(self.clone()) as Box<dyn DavMetaData>) }.boxed()
     }
 }
@@ -797,3 +1016,8 @@ pub async fn read_refresh_token(workdir: &Path) -> Result<String> {
     }
     Ok(token)
 }
+
+/// Delay before the retry after `attempt` failed ones, when the server doesn't say
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_millis(500 << attempt.min(6)).min(Duration::from_secs(30))
+}
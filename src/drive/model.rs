@@ -3,13 +3,14 @@ use std::ops;
 use std::time::SystemTime;
 
 use ::time::{format_description::well_known::Rfc3339, OffsetDateTime};
-use serde::{Deserialize, Deserializer, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct RefreshTokenResponse {
     pub access_token: String,
     pub refresh_token: String,
     pub expires_in: u64,
+    #[allow(dead_code)]
     pub token_type: String,
 }
 
@@ -49,12 +50,7 @@ pub struct ListFileItem {
     pub size: Option<u64>,
     pub url: Option<String>,
     pub content_hash: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize)]
-pub struct GetFileByPathRequest<'a> {
-    pub drive_id: &'a str,
-    pub file_path: &'a str,
+    pub parent_file_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -129,7 +125,9 @@ pub struct GetFileDownloadUrlResponse {
     pub url: String,
     #[serde(default)]
     pub streams_url: HashMap<String, String>,
+    #[allow(dead_code)]
     pub expiration: String,
+    #[allow(dead_code)]
     pub method: String,
 }
 
@@ -145,6 +143,68 @@ pub struct DeleteFileRequest<'a> {
     pub file_id: &'a str,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct ListRecycleBinRequest<'a> {
+    pub drive_id: &'a str,
+    pub limit: u64,
+    pub marker: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchFileRequest<'a> {
+    pub drive_id: &'a str,
+    pub query: &'a str,
+    pub limit: u64,
+    pub marker: Option<&'a str>,
+    pub order_by: &'a str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GetLastCursorRequest<'a> {
+    pub drive_id: &'a str,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GetLastCursorResponse {
+    pub cursor: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ListDeltaRequest<'a> {
+    pub drive_id: &'a str,
+    pub cursor: &'a str,
+    pub limit: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListDeltaResponse {
+    pub items: Vec<DeltaItem>,
+    pub cursor: String,
+    pub has_more: bool,
+}
+
+/// A change of the file delta feed
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeltaItem {
+    /// `create`, `update`, `move`, `trash`, `restore`, `delete` ...
+    pub op: String,
+    pub file_id: String,
+    /// The file after the change, missing for deletions
+    #[serde(default)]
+    pub file: Option<DeltaFile>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeltaFile {
+    pub parent_file_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreFileRequest<'a> {
+    pub drive_id: &'a str,
+    pub file_id: &'a str,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct CreateFolderRequest<'a> {
     pub check_name_mode: &'a str,
@@ -154,6 +214,11 @@ pub struct CreateFolderRequest<'a> {
     pub r#type: &'a str,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateFolderResponse {
+    pub file_id: String,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct RenameFileRequest<'a> {
     pub drive_id: &'a str,
@@ -177,6 +242,11 @@ pub struct CopyFileRequest<'a> {
     pub auto_rename: bool,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct CopyFileResponse {
+    pub file_id: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UploadPartInfo {
     pub part_number: u64,
@@ -199,12 +269,24 @@ pub struct CreateFileWithProofRequest<'a> {
     pub r#type: &'a str,
 }
 
+/// Content hash of a file and its proof, used for rapid upload
+#[derive(Debug, Clone)]
+pub struct ContentHash {
+    /// Upper case hex encoded sha1 of the file content
+    pub sha1: String,
+    /// Base64 encoded file content at the offset given by `AliyunDrive::proof_offset`
+    pub proof_code: String,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct CreateFileWithProofResponse {
     #[serde(default)]
     pub part_info_list: Vec<UploadPartInfo>,
     pub file_id: String,
     pub upload_id: Option<String>,
+    #[serde(default)]
+    pub rapid_upload: bool,
+    #[allow(dead_code)]
     pub file_name: String,
 }
 
@@ -251,6 +333,15 @@ impl<'a> Deserialize<'a> for DateTime {
     }
 }
 
+impl Serialize for DateTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let dt = OffsetDateTime::from(self.0)
+            .format(&Rfc3339)
+            .map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&dt)
+    }
+}
+
 impl ops::Deref for DateTime {
     type Target = SystemTime;
 
@@ -259,14 +350,14 @@ impl ops::Deref for DateTime {
     }
 }
 
-#[derive(Debug, Clone, Copy, Deserialize)]
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum FileType {
     Folder,
     File,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AliyunFile {
     pub name: String,
     #[serde(rename = "file_id")]
@@ -0,0 +1,177 @@
+//! Client side rate limiting of OpenAPI calls
+//!
+//! Every kind of endpoint has a token bucket, a 429 response pauses its bucket for the
+//! `Retry-After` delay and halves its rate, which then recovers with each successful call.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use time::format_description::well_known::Rfc2822;
+use time::OffsetDateTime;
+use tracing::debug;
+
+/// Longest `Retry-After` delay honoured
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Requests per second allowed for each kind of OpenAPI endpoint, 0 means unlimited
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimits {
+    /// Directory listings, searches and the change feed
+    pub list: f64,
+    /// Every other call
+    pub metadata: f64,
+    pub download_url: f64,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            list: 10.0,
+            metadata: 10.0,
+            download_url: 10.0,
+        }
+    }
+}
+
+/// Kind of an OpenAPI endpoint, each kind has its own budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    List,
+    Metadata,
+    DownloadUrl,
+}
+
+impl Endpoint {
+    /// Kind of the endpoint at the URL path `path`
+    pub fn of(path: &str) -> Self {
+        match path.rsplit('/').next().unwrap_or_default() {
+            "getDownloadUrl" => Endpoint::DownloadUrl,
+            name if name.starts_with("list") || name == "search" => Endpoint::List,
+            _ => Endpoint::Metadata,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    /// Rate after backing off, at most the configured rate
+    rate: f64,
+    updated: Instant,
+    paused_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Configured requests per second
+    rate: f64,
+    state: Mutex<BucketState>,
+}
+
+impl Bucket {
+    fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            state: Mutex::new(BucketState {
+                tokens: rate.max(1.0),
+                rate,
+                updated: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Time to wait before a request can be sent, `None` if a token was taken
+    fn try_acquire(&self) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        if let Some(until) = state.paused_until {
+            if until > now {
+                return Some(until - now);
+            }
+            state.paused_until = None;
+            state.updated = now;
+        }
+        if self.rate <= 0.0 {
+            return None;
+        }
+        let elapsed = now.duration_since(state.updated).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * state.rate).min(state.rate.max(1.0));
+        state.updated = now;
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - state.tokens) / state.rate))
+        }
+    }
+
+    fn throttled(&self, delay: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.rate = (state.rate / 2.0).max(self.rate / 16.0);
+        state.tokens = 0.0;
+        let until = Instant::now() + delay.min(MAX_RETRY_AFTER);
+        state.paused_until = Some(state.paused_until.map_or(until, |paused| paused.max(until)));
+    }
+
+    fn succeeded(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.rate = (state.rate + self.rate / 32.0).min(self.rate);
+    }
+}
+
+/// Token buckets shared by all API calls of a drive
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    list: Arc<Bucket>,
+    metadata: Arc<Bucket>,
+    download_url: Arc<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            list: Arc::new(Bucket::new(limits.list)),
+            metadata: Arc::new(Bucket::new(limits.metadata)),
+            download_url: Arc::new(Bucket::new(limits.download_url)),
+        }
+    }
+
+    fn bucket(&self, endpoint: Endpoint) -> &Bucket {
+        match endpoint {
+            Endpoint::List => &self.list,
+            Endpoint::Metadata => &self.metadata,
+            Endpoint::DownloadUrl => &self.download_url,
+        }
+    }
+
+    /// Wait until a request to `endpoint` is allowed
+    pub async fn acquire(&self, endpoint: Endpoint) {
+        while let Some(wait) = self.bucket(endpoint).try_acquire() {
+            debug!(endpoint = ?endpoint, wait = ?wait, "rate limited");
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Pause requests to `endpoint` for `delay` and back off its rate
+    pub fn throttled(&self, endpoint: Endpoint, delay: Duration) {
+        self.bucket(endpoint).throttled(delay);
+    }
+
+    /// Let the rate of `endpoint` recover after a successful request
+    pub fn succeeded(&self, endpoint: Endpoint) {
+        self.bucket(endpoint).succeeded();
+    }
+}
+
+/// Delay of a `Retry-After` header, in seconds or as an HTTP date
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = OffsetDateTime::parse(&value.replace(" GMT", " +0000"), &Rfc2822).ok()?;
+    let delay = date - OffsetDateTime::now_utc();
+    Some(delay.try_into().unwrap_or_default())
+}
@@ -1,28 +1,53 @@
 use std::env;
 use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use anyhow::bail;
-use clap::{Parser, Subcommand};
-use dav_server::{memls::MemLs, DavHandler};
+use clap::{CommandFactory, Parser, Subcommand};
+use dav_server::{fs::DavFileSystem, memls::MemLs, DavHandler};
 #[cfg(unix)]
 use futures_util::stream::StreamExt;
 use self_update::cargo_crate_version;
-use tracing::{debug, info, warn};
+use tracing::{debug, error, info, warn};
 use tracing_subscriber::EnvFilter;
 #[cfg(unix)]
 use {signal_hook::consts::signal::*, signal_hook_tokio::Signals};
 
+use auth::{
+    check_password_hash, Accounts, AuthScheme, Authenticator, Credentials, HashAlgorithm, Password,
+};
+use block_cache::BlockCache;
 use cache::Cache;
-use drive::{read_refresh_token, AliyunDrive, DriveConfig, DriveType};
-use vfs::AliyunDriveFileSystem;
-use webdav::WebDavServer;
+use config::LiveSettings;
+use drive::{read_refresh_token, AliyunDrive, DriveConfig, DriveType, RateLimits};
+use meta_store::MetadataStore;
+use mount::MountTable;
+use users::{UserFileSystem, Users};
+use vfs::{AliyunDriveFileSystem, UploadMemory};
+use webdav::{ServiceOptions, SharedAuth, WebDavServer};
 
+mod admin;
+mod auth;
+mod block_cache;
 mod cache;
+mod config;
 mod drive;
 mod login;
+mod meta_store;
+mod metrics;
+mod mount;
+mod path_index;
+mod search;
+mod upload;
+mod users;
 mod vfs;
+mod web;
 mod webdav;
 
+#[cfg(test)]
+mod tests;
+
 #[derive(Parser, Debug)]
 #[command(name = "aliyundrive-webdav", about, version, author)]
 #[command(args_conflicts_with_subcommands = true)]
@@ -51,30 +76,117 @@ struct Opt {
     /// WebDAV authentication password
     #[arg(short = 'W', long, env = "WEBDAV_AUTH_PASSWORD")]
     auth_password: Option<String>,
+    /// WebDAV authentication password hash in bcrypt, argon2 or sha512-crypt format,
+    /// generate one with the hash-password subcommand
+    #[arg(
+        long,
+        env = "WEBDAV_AUTH_PASSWORD_HASH",
+        conflicts_with = "auth_password"
+    )]
+    auth_password_hash: Option<String>,
+    /// WebDAV authentication schemes, digest requires a plain text auth-password
+    /// and is not available with a users file, which only stores password hashes
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "basic",
+        env = "WEBDAV_AUTH_SCHEME"
+    )]
+    auth_scheme: Vec<AuthScheme>,
+    /// Static API tokens accepted as auth-user with bearer authentication
+    #[arg(
+        long,
+        value_delimiter = ',',
+        env = "WEBDAV_AUTH_BEARER_TOKEN",
+        requires = "auth_user"
+    )]
+    auth_bearer_token: Vec<String>,
     /// Automatically generate index.html
     #[arg(short = 'I', long)]
     auto_index: bool,
+    /// Serve a web file browser with upload, rename and delete to browsers,
+    /// takes precedence over --auto-index
+    #[arg(long)]
+    web_ui: bool,
     /// Read/download buffer size in bytes, defaults to 10MB
     #[arg(short = 'S', long, default_value = "10485760")]
     read_buffer_size: usize,
     /// Upload buffer size in bytes, defaults to 16MB
     #[arg(long, default_value = "16777216")]
     upload_buffer_size: usize,
+    /// Number of file parts uploaded concurrently per file
+    #[arg(long, default_value = "4")]
+    upload_concurrency: usize,
+    /// Memory limit in bytes for parts being uploaded across all files, defaults to 256MB
+    #[arg(long, default_value = "268435456")]
+    upload_memory_limit: usize,
     /// Directory entries cache size
     #[arg(long, default_value = "1000")]
     cache_size: u64,
     /// Directory entries cache expiration time in seconds
     #[arg(long, default_value = "600")]
     cache_ttl: u64,
+    /// Poll the drive's change feed every this many seconds to drop changed directories
+    /// from the cache, allowing a long --cache-ttl while staying fresh. 0 disables polling
+    #[arg(long, default_value = "0")]
+    change_poll_interval: u64,
+    /// Directory listings and searches per second sent to the OpenAPI, 0 is unlimited
+    #[arg(long, default_value = "10")]
+    list_rate_limit: f64,
+    /// Other metadata calls per second sent to the OpenAPI, 0 is unlimited
+    #[arg(long, default_value = "10")]
+    metadata_rate_limit: f64,
+    /// Download url requests per second sent to the OpenAPI, 0 is unlimited
+    #[arg(long, default_value = "10")]
+    download_url_rate_limit: f64,
+    /// Refuse uploads that would take the usage of the whole drive above this many bytes,
+    /// mounts can override it and users can have a lower `soft_quota` in the users file
+    #[arg(long)]
+    soft_quota: Option<u64>,
+    /// Keep the directory cache in the working directory so that it survives restarts,
+    /// entries are reloaded until --cache-ttl expires them
+    #[arg(long, requires = "workdir")]
+    persistent_cache: bool,
     /// Root directory path
     #[arg(long, env = "WEBDAV_ROOT", default_value = "/")]
     root: String,
     /// Working directory, refresh_token will be stored in there if specified
     #[arg(short = 'w', long)]
     workdir: Option<PathBuf>,
+    /// Number of read buffer sized windows downloaded ahead of sequential reads
+    #[arg(long, default_value = "2")]
+    read_ahead: usize,
+    /// Read cache directory, downloaded file blocks are cached on local disk if specified
+    #[arg(long)]
+    read_cache_dir: Option<PathBuf>,
+    /// Read cache size limit in bytes, defaults to 1GB
+    #[arg(long, default_value = "1073741824")]
+    read_cache_size: u64,
+    /// Read cache block size in bytes, defaults to 1MB
+    #[arg(long, default_value = "1048576")]
+    read_cache_block_size: u64,
+    /// Users file with WebDAV accounts, their home directory and permissions per path
+    #[arg(long)]
+    users: Option<PathBuf>,
+    /// Mount table file serving several accounts or drives under separate path prefixes,
+    /// overrides refresh token, drive type and root options
+    #[arg(long)]
+    mounts: Option<PathBuf>,
+    /// Spool directory, uploads of unknown size are written to local disk first if specified
+    #[arg(long)]
+    spool_dir: Option<PathBuf>,
     /// Delete file permanently instead of trashing it
     #[arg(long)]
     no_trash: bool,
+    /// Expose the recycle bin as the virtual /.trash folder, MOVE out of it restores
+    /// and DELETE inside it purges
+    #[arg(long)]
+    trash_folder: bool,
+    /// Answer WebDAV SEARCH requests sent to the root and expose search results
+    /// as the virtual /.search/<query> folders
+    #[arg(long)]
+    search: bool,
     /// Enable read only mode
     #[arg(long)]
     read_only: bool,
@@ -102,6 +214,17 @@ struct Opt {
     /// Enable 302 redirect when possible
     #[arg(long)]
     redirect: bool,
+    /// Serve Prometheus metrics at /metrics, shadowing a file with that name in the root directory
+    #[arg(long)]
+    metrics: bool,
+    /// Bearer token of the admin API served under /_admin/
+    #[arg(long, env = "WEBDAV_ADMIN_TOKEN")]
+    admin_token: Option<String>,
+    /// TOML config file with the same settings as the command line options, e.g.
+    /// `read_only = true`, command line options and environment variables take precedence.
+    /// Authentication, read only, cache TTL and buffer sizes are reloaded on SIGHUP
+    #[arg(long, env = "WEBDAV_CONFIG")]
+    config: Option<PathBuf>,
 
     #[command(subcommand)]
     subcommands: Option<Commands>,
@@ -112,6 +235,14 @@ enum Commands {
     /// Scan QRCode
     #[command(subcommand)]
     Qr(QrCommand),
+    /// Hash a password for --auth-password-hash or the users file
+    HashPassword {
+        /// Hash algorithm
+        #[arg(long, value_enum, default_value = "bcrypt")]
+        algorithm: HashAlgorithm,
+        /// Password to hash, read from stdin if omitted
+        password: Option<String>,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -134,7 +265,15 @@ async fn main() -> anyhow::Result<()> {
     #[cfg(feature = "native-tls-vendored")]
     openssl_probe::init_ssl_cert_env_vars();
 
-    let opt = Opt::parse();
+    let args = match config::args_with_config(&Opt::command(), env::args_os().collect()) {
+        Ok(args) => args,
+        // report invalid arguments the way clap does
+        Err(err) => match err.downcast::<clap::Error>() {
+            Ok(err) => err.exit(),
+            Err(err) => return Err(err),
+        },
+    };
+    let opt = Opt::parse_from(args);
     if env::var("RUST_LOG").is_err() {
         if opt.debug {
             env::set_var("RUST_LOG", "aliyundrive_webdav=debug,reqwest=debug");
@@ -149,23 +288,43 @@ async fn main() -> anyhow::Result<()> {
 
     let workdir = opt
         .workdir
+        .clone()
         .or_else(|| dirs::cache_dir().map(|c| c.join("aliyundrive-webdav")));
+    let api_base_url = env::var("ALIYUNDRIVE_API_BASE_URL")
+        .unwrap_or_else(|_| "https://openapi.aliyundrive.com".to_string());
     let refresh_token_host = if opt.client_id.is_none() || opt.client_secret.is_none() {
         env::var("ALIYUNDRIVE_OAUTH_SERVER")
             .unwrap_or_else(|_| "https://aliyundrive-oauth.messense.me".to_string())
     } else {
-        "https://openapi.aliyundrive.com".to_string()
+        api_base_url.clone()
     };
     let drive_config = DriveConfig {
-        api_base_url: "https://openapi.aliyundrive.com".to_string(),
+        api_base_url,
         refresh_token_host,
         workdir,
         client_id: opt.client_id.clone(),
         client_secret: opt.client_secret.clone(),
-        drive_type: opt.drive_type.clone(),
+        drive_type: opt.drive_type,
+        rate_limits: RateLimits {
+            list: opt.list_rate_limit,
+            metadata: opt.metadata_rate_limit,
+            download_url: opt.download_url_rate_limit,
+        },
     };
 
     // subcommands
+    if let Some(Commands::HashPassword {
+        algorithm,
+        password,
+    }) = opt.subcommands.as_ref()
+    {
+        let password = match password {
+            Some(password) => password.clone(),
+            None => read_password()?,
+        };
+        println!("{}", algorithm.hash(&password)?);
+        return Ok(());
+    }
     if let Some(Commands::Qr(qr)) = opt.subcommands.as_ref() {
         match qr {
             QrCommand::Login => {
@@ -199,13 +358,14 @@ async fn main() -> anyhow::Result<()> {
         .await?;
     }
 
-    let auth_user = opt.auth_user;
-    let auth_password = opt.auth_password;
-    if (auth_user.is_some() && auth_password.is_none())
-        || (auth_user.is_none() && auth_password.is_some())
-    {
-        bail!("auth-user and auth-password must be specified together.");
-    }
+    let auth: SharedAuth = Arc::new(RwLock::new(build_auth(&opt).await?));
+    let live = Arc::new(LiveSettings::default());
+    let mut reloadable = Reloadable {
+        auth: auth.clone(),
+        live: live.clone(),
+        dir_caches: Vec::new(),
+    };
+    reloadable.apply(&opt);
 
     let tls_config = match (opt.tls_cert, opt.tls_key) {
         (Some(cert), Some(key)) => Some((cert, key)),
@@ -213,40 +373,85 @@ async fn main() -> anyhow::Result<()> {
         _ => bail!("tls-cert and tls-key must be specified together."),
     };
 
-    let refresh_token_from_file = if let Some(dir) = drive_config.workdir.as_ref() {
-        read_refresh_token(dir).await.ok()
-    } else {
-        None
+    let read_cache = match opt.read_cache_dir {
+        Some(dir) => {
+            Some(BlockCache::new(dir, opt.read_cache_size, opt.read_cache_block_size).await?)
+        }
+        None => None,
     };
-    let refresh_token = if opt.refresh_token.is_none()
-        && refresh_token_from_file.is_none()
-        && atty::is(atty::Stream::Stdout)
-    {
-        login(drive_config.clone(), 30).await?
-    } else {
-        let token = opt.refresh_token.unwrap_or_default();
-        if !token.is_empty() && token.split('.').count() < 3 {
-            bail!("Invalid refresh token value found in `--refresh-token` argument");
+    // one budget for the uploads of all mounts
+    let upload_memory = UploadMemory::new(opt.upload_memory_limit);
+    let make_fs = |drive: AliyunDrive, root: String, workdir: Option<PathBuf>| {
+        let mut fs = AliyunDriveFileSystem::new(drive, root, opt.cache_size, opt.cache_ttl)?;
+        fs.set_no_trash(opt.no_trash)
+            .set_trash_folder(opt.trash_folder)
+            .set_search_folder(opt.search)
+            .set_upload_sessions_dir(workdir.as_ref().map(|dir| dir.join("uploads")))
+            .set_spool_dir(opt.spool_dir.clone())
+            .set_read_cache(read_cache.clone())
+            .set_read_ahead(opt.read_ahead)
+            .set_live_settings(live.clone())
+            .set_upload_concurrency(opt.upload_concurrency)
+            .set_upload_memory(upload_memory.clone())
+            .set_skip_upload_same_size(opt.skip_upload_same_size)
+            .set_prefer_http_download(opt.prefer_http_download)
+            .set_soft_quota(opt.soft_quota);
+        if let (true, Some(dir)) = (opt.persistent_cache, workdir.as_ref()) {
+            let store = MetadataStore::open(dir, fs.drive.drive_id()?)?;
+            fs.dir_cache.set_store(store)?;
         }
-        token
+        Ok(fs)
     };
 
-    let drive = AliyunDrive::new(drive_config, refresh_token).await?;
-    let mut fs = AliyunDriveFileSystem::new(drive, opt.root, opt.cache_size, opt.cache_ttl)?;
-    fs.set_no_trash(opt.no_trash)
-        .set_read_only(opt.read_only)
-        .set_upload_buffer_size(opt.upload_buffer_size)
-        .set_skip_upload_same_size(opt.skip_upload_same_size)
-        .set_prefer_http_download(opt.prefer_http_download);
-    debug!("aliyundrive file system initialized");
+    #[allow(clippy::type_complexity)]
+    let (fs, user_fs, dir_caches, mounts): (
+        Box<dyn DavFileSystem>,
+        Arc<dyn UserFileSystem>,
+        Vec<Cache>,
+        Vec<(String, AliyunDriveFileSystem)>,
+    ) = if let Some(mounts) = opt.mounts.as_ref() {
+        let mount_table = MountTable::load(mounts).await?;
+        let fs = mount_table.build(&drive_config, make_fs).await?;
+        let dir_caches = fs.dir_caches();
+        let mounts = fs.mounts();
+        (Box::new(fs.clone()), Arc::new(fs), dir_caches, mounts)
+    } else {
+        let refresh_token_from_file = if let Some(dir) = drive_config.workdir.as_ref() {
+            read_refresh_token(dir).await.ok()
+        } else {
+            None
+        };
+        let refresh_token = if opt.refresh_token.is_none()
+            && refresh_token_from_file.is_none()
+            && atty::is(atty::Stream::Stdout)
+        {
+            login(drive_config.clone(), 30).await?
+        } else {
+            let token = opt.refresh_token.clone().unwrap_or_default();
+            if !token.is_empty() && token.split('.').count() < 3 {
+                bail!("Invalid refresh token value found in `--refresh-token` argument");
+            }
+            token
+        };
 
-    #[cfg(unix)]
-    let dir_cache = fs.dir_cache.clone();
+        let workdir = drive_config.workdir.clone();
+        let drive = AliyunDrive::new(drive_config, refresh_token).await?;
+        let fs = make_fs(drive, opt.root.clone(), workdir)?;
+        let dir_caches = vec![fs.dir_cache.clone()];
+        let mounts = vec![("/".to_string(), fs.clone())];
+        (Box::new(fs.clone()), Arc::new(fs), dir_caches, mounts)
+    };
+    debug!("aliyundrive file system initialized");
+    if opt.change_poll_interval > 0 {
+        for (_, fs) in &mounts {
+            fs.watch_changes(Duration::from_secs(opt.change_poll_interval));
+        }
+    }
+    reloadable.dir_caches = dir_caches;
 
     let mut dav_server_builder = DavHandler::builder()
-        .filesystem(Box::new(fs))
+        .filesystem(fs)
         .locksystem(MemLs::new())
-        .read_buf_size(opt.read_buffer_size)
         .autoindex(opt.auto_index)
         .redirect(opt.redirect);
     if let Some(prefix) = opt.strip_prefix {
@@ -263,20 +468,32 @@ async fn main() -> anyhow::Result<()> {
     let server = WebDavServer {
         host: opt.host,
         port: opt.port,
-        auth_user,
-        auth_password,
+        options: ServiceOptions {
+            auth,
+            user_fs: Some(user_fs),
+            live,
+            metrics: opt.metrics,
+            mounts: Arc::new(mounts),
+            admin_token: opt.admin_token.map(Arc::from),
+            web_ui: opt.web_ui,
+            search: opt.search,
+        },
         tls_config,
         handler: dav_server,
     };
 
     #[cfg(not(unix))]
-    server.serve().await?;
+    {
+        // settings are only reloaded by SIGHUP
+        drop(reloadable);
+        server.serve().await?;
+    }
 
     #[cfg(unix)]
     {
         let signals = Signals::new([SIGHUP])?;
         let handle = signals.handle();
-        let signals_task = tokio::spawn(handle_signals(signals, dir_cache));
+        let signals_task = tokio::spawn(handle_signals(signals, reloadable));
 
         server.serve().await?;
 
@@ -287,13 +504,99 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+impl Opt {
+    /// Parse the command line merged with the `--config` file again
+    fn reload() -> anyhow::Result<Self> {
+        let args = config::args_with_config(&Opt::command(), env::args_os().collect())?;
+        Ok(Opt::try_parse_from(args)?)
+    }
+}
+
+async fn build_auth(opt: &Opt) -> anyhow::Result<Option<Arc<Authenticator>>> {
+    let auth_password = match (&opt.auth_password, &opt.auth_password_hash) {
+        (Some(password), _) => Some(Password::Plain(password.clone())),
+        (None, Some(hash)) => {
+            check_password_hash(hash)?;
+            Some(Password::Hash(hash.clone()))
+        }
+        (None, None) => None,
+    };
+    let credentials = match (&opt.auth_user, auth_password) {
+        (Some(user), Some(password)) => Some(Credentials::new(
+            user.clone(),
+            password,
+            opt.auth_bearer_token.clone(),
+        )),
+        (None, None) => None,
+        _ => bail!("auth-user and auth-password must be specified together."),
+    };
+    let accounts = match (credentials, opt.users.as_ref()) {
+        (Some(_), Some(_)) => bail!("auth-user and users can not be specified together."),
+        (Some(credentials), None) => Some(Accounts::Single(credentials)),
+        (None, Some(path)) => Some(Accounts::Users(Arc::new(Users::load(path).await?))),
+        (None, None) => None,
+    };
+    let auth = match accounts {
+        Some(accounts) => Some(Arc::new(Authenticator::new(
+            opt.auth_scheme.clone(),
+            accounts,
+        )?)),
+        None => None,
+    };
+    Ok(auth)
+}
+
+/// Settings that can be changed without restarting the server
+struct Reloadable {
+    auth: SharedAuth,
+    live: Arc<LiveSettings>,
+    dir_caches: Vec<Cache>,
+}
+
+impl Reloadable {
+    fn apply(&self, opt: &Opt) {
+        self.live.set_read_only(opt.read_only);
+        self.live.set_read_buffer_size(opt.read_buffer_size);
+        self.live.set_upload_buffer_size(opt.upload_buffer_size);
+        for dir_cache in &self.dir_caches {
+            dir_cache.set_ttl(opt.cache_ttl);
+        }
+    }
+
+    #[cfg(unix)]
+    async fn reload(&self) -> anyhow::Result<()> {
+        let opt = Opt::reload()?;
+        let auth = build_auth(&opt).await?;
+        *self.auth.write().unwrap() = auth;
+        self.apply(&opt);
+        Ok(())
+    }
+}
+
+/// Read a password from the first line of stdin
+fn read_password() -> anyhow::Result<String> {
+    if atty::is(atty::Stream::Stdin) {
+        eprint!("Password: ");
+    }
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        bail!("empty password");
+    }
+    Ok(password.to_string())
+}
+
 #[cfg(unix)]
-async fn handle_signals(mut signals: Signals, dir_cache: Cache) {
+async fn handle_signals(mut signals: Signals, reloadable: Reloadable) {
     while let Some(signal) = signals.next().await {
         match signal {
             SIGHUP => {
-                dir_cache.invalidate_all();
-                info!("directory cache invalidated by SIGHUP");
+                // cached listings are kept, a new TTL applies to them on the next lookup
+                match reloadable.reload().await {
+                    Ok(()) => info!("configuration reloaded by SIGHUP"),
+                    Err(err) => error!(error = %err, "reload configuration failed"),
+                }
             }
             _ => unreachable!(),
         }
@@ -0,0 +1,150 @@
+//! On-disk copy of the directory cache, so that a restart doesn't list every directory again
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+
+use anyhow::{Context, Result};
+use redb::{Database, Durability, ReadableTable, TableDefinition, TableError};
+use serde::{Deserialize, Serialize};
+
+use crate::drive::AliyunFile;
+
+const FILE_NAME: &str = "metadata.redb";
+/// Change feed cursors by drive id
+const CURSORS: TableDefinition<&str, &str> = TableDefinition::new("cursors");
+
+/// A cached directory listing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredListing {
+    /// Seconds since the unix epoch the listing was fetched at
+    pub cached_at: u64,
+    pub dir_id: String,
+    pub files: Vec<AliyunFile>,
+}
+
+/// Directory listings of one drive, keyed by their absolute path in the drive
+#[derive(Clone)]
+pub struct MetadataStore {
+    db: Arc<Database>,
+    drive_id: String,
+    table: String,
+}
+
+impl MetadataStore {
+    /// Open the store of a drive in `dir`, drives of the same account share the database file
+    pub fn open(dir: &Path, drive_id: &str) -> Result<Self> {
+        static DATABASES: OnceLock<Mutex<HashMap<PathBuf, Weak<Database>>>> = OnceLock::new();
+        let path = dir.join(FILE_NAME);
+        let mut databases = DATABASES.get_or_init(Default::default).lock().unwrap();
+        let db = match databases.get(&path).and_then(Weak::upgrade) {
+            Some(db) => db,
+            None => {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("create directory {} failed", dir.display()))?;
+                let db = Arc::new(
+                    Database::create(&path)
+                        .with_context(|| format!("open {} failed", path.display()))?,
+                );
+                databases.insert(path, Arc::downgrade(&db));
+                db
+            }
+        };
+        Ok(Self {
+            db,
+            drive_id: drive_id.to_string(),
+            table: format!("listings:{}", drive_id),
+        })
+    }
+
+    fn listings(&self) -> TableDefinition<'_, &'static str, &'static [u8]> {
+        TableDefinition::new(&self.table)
+    }
+
+    /// Write to the listings table in a transaction that is flushed to disk by the OS
+    fn update(&self, f: impl FnOnce(&mut redb::Table<&str, &[u8]>) -> Result<()>) -> Result<()> {
+        let mut txn = self.db.begin_write()?;
+        txn.set_durability(Durability::Eventual);
+        {
+            let mut table = txn.open_table(self.listings())?;
+            f(&mut table)?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Every stored listing, listings that can't be decoded are skipped
+    pub fn load(&self) -> Result<Vec<(String, StoredListing)>> {
+        let txn = self.db.begin_read()?;
+        let table = match txn.open_table(self.listings()) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut listings = Vec::new();
+        for entry in table.iter()? {
+            let (key, value) = entry?;
+            if let Ok(listing) = serde_json::from_slice(value.value()) {
+                listings.push((key.value().to_string(), listing));
+            }
+        }
+        Ok(listings)
+    }
+
+    pub fn put(&self, key: &str, listing: &StoredListing) -> Result<()> {
+        let value = serde_json::to_vec(listing)?;
+        self.update(|table| {
+            table.insert(key, value.as_slice())?;
+            Ok(())
+        })
+    }
+
+    pub fn remove(&self, keys: &[String]) -> Result<()> {
+        self.update(|table| {
+            for key in keys {
+                table.remove(key.as_str())?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Remove the listing of `path` and of every directory below it
+    pub fn remove_subtree(&self, path: &str) -> Result<()> {
+        let prefix = path.trim_end_matches('/');
+        self.update(|table| {
+            table.retain(|key, _| {
+                !key.strip_prefix(prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })?;
+            Ok(())
+        })
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        self.update(|table| {
+            table.retain(|_, _| false)?;
+            Ok(())
+        })
+    }
+
+    /// Change feed cursor the stored listings are up to date with
+    pub fn cursor(&self) -> Result<Option<String>> {
+        let txn = self.db.begin_read()?;
+        let table = match txn.open_table(CURSORS) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let cursor = table.get(self.drive_id.as_str())?;
+        Ok(cursor.map(|cursor| cursor.value().to_string()))
+    }
+
+    pub fn set_cursor(&self, cursor: &str) -> Result<()> {
+        let mut txn = self.db.begin_write()?;
+        txn.set_durability(Durability::Eventual);
+        txn.open_table(CURSORS)?
+            .insert(self.drive_id.as_str(), cursor)?;
+        txn.commit()?;
+        Ok(())
+    }
+}
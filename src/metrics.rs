@@ -0,0 +1,114 @@
+use std::sync::OnceLock;
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+/// Prometheus metrics of the server, registered once per process
+pub struct Metrics {
+    registry: Registry,
+    /// WebDAV requests by method and response status
+    pub requests: IntCounterVec,
+    /// File content bytes uploaded to AliyunDrive
+    pub uploaded_bytes: IntCounter,
+    /// File content bytes downloaded from AliyunDrive
+    pub downloaded_bytes: IntCounter,
+    /// Latency of AliyunDrive OpenAPI calls by endpoint
+    pub upstream_duration: HistogramVec,
+    /// Access token refreshes caused by 401 responses
+    pub token_refreshes: IntCounter,
+    /// OpenAPI calls throttled with 429 responses
+    pub throttled: IntCounter,
+    pub cache_hits: IntCounter,
+    pub cache_misses: IntCounter,
+    pub cache_evictions: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("aliyundrive_webdav".to_string()), None)
+            .expect("create metrics registry");
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "WebDAV requests by method and status"),
+            &["method", "status"],
+        )
+        .unwrap();
+        let uploaded_bytes =
+            IntCounter::new("uploaded_bytes_total", "Bytes uploaded to AliyunDrive").unwrap();
+        let downloaded_bytes = IntCounter::new(
+            "downloaded_bytes_total",
+            "Bytes downloaded from AliyunDrive",
+        )
+        .unwrap();
+        let upstream_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "upstream_request_duration_seconds",
+                "AliyunDrive OpenAPI call latency by endpoint",
+            ),
+            &["endpoint"],
+        )
+        .unwrap();
+        let token_refreshes = IntCounter::new(
+            "token_refreshes_total",
+            "Access token refreshes caused by 401 responses",
+        )
+        .unwrap();
+        let throttled = IntCounter::new(
+            "upstream_throttled_total",
+            "AliyunDrive OpenAPI calls throttled with 429 responses",
+        )
+        .unwrap();
+        let cache_hits = IntCounter::new("dir_cache_hits_total", "Directory cache hits").unwrap();
+        let cache_misses =
+            IntCounter::new("dir_cache_misses_total", "Directory cache misses").unwrap();
+        let cache_evictions = IntCounter::new(
+            "dir_cache_evictions_total",
+            "Directory cache entries evicted over capacity",
+        )
+        .unwrap();
+
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(uploaded_bytes.clone())).unwrap();
+        registry
+            .register(Box::new(downloaded_bytes.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(upstream_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(token_refreshes.clone()))
+            .unwrap();
+        registry.register(Box::new(throttled.clone())).unwrap();
+        registry.register(Box::new(cache_hits.clone())).unwrap();
+        registry.register(Box::new(cache_misses.clone())).unwrap();
+        registry
+            .register(Box::new(cache_evictions.clone()))
+            .unwrap();
+        Self {
+            registry,
+            requests,
+            uploaded_bytes,
+            downloaded_bytes,
+            upstream_duration,
+            token_refreshes,
+            throttled,
+            cache_hits,
+            cache_misses,
+            cache_evictions,
+        }
+    }
+
+    /// Metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut buf = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .expect("encode metrics");
+        String::from_utf8(buf).expect("metrics are valid utf-8")
+    }
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
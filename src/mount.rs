@@ -0,0 +1,491 @@
+//! Serve several drives of one or more accounts under separate path prefixes
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use dav_server::{
+    davpath::DavPath,
+    fs::{
+        DavDirEntry, DavFile, DavFileSystem, DavMetaData, DavProp, FsError, FsFuture, FsStream,
+        OpenOptions, ReadDirMeta,
+    },
+};
+use futures_util::future::{ready, FutureExt};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::cache::Cache;
+use crate::drive::{AliyunDrive, AliyunFile, DriveConfig, DriveType};
+use crate::users::{User, UserFileSystem};
+use crate::vfs::AliyunDriveFileSystem;
+
+/// Mount table loaded from the `--mounts` TOML file
+///
+/// ```toml
+/// [accounts.alice]
+/// refresh_token = "..."
+///
+/// [[mounts]]
+/// path = "/alice/resource"
+/// account = "alice"
+/// drive_type = "resource"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MountTable {
+    #[serde(default)]
+    pub accounts: BTreeMap<String, AccountConfig>,
+    #[serde(default)]
+    pub mounts: Vec<MountConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AccountConfig {
+    /// Refresh token, can be omitted once it is stored in the account workdir
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MountConfig {
+    /// Path prefix the drive is served under
+    pub path: String,
+    pub account: String,
+    #[serde(default)]
+    pub drive_type: Option<DriveType>,
+    /// Root directory of the drive to serve
+    #[serde(default = "default_root")]
+    pub root: String,
+    #[serde(default)]
+    pub read_only: bool,
+    /// Refuse uploads that would take the drive's usage above this many bytes, overriding
+    /// `--soft-quota` for this mount
+    #[serde(default)]
+    pub soft_quota: Option<u64>,
+}
+
+fn default_root() -> String {
+    "/".to_string()
+}
+
+impl MountTable {
+    pub async fn load(path: &Path) -> Result<Self> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("read mount table {} failed", path.display()))?;
+        let table: Self = toml::from_str(&content)
+            .with_context(|| format!("parse mount table {} failed", path.display()))?;
+        table.validate()?;
+        Ok(table)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.mounts.is_empty() {
+            bail!("mount table has no mounts");
+        }
+        for name in self.accounts.keys() {
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                bail!("invalid account name `{}`", name);
+            }
+        }
+        for mount in &self.mounts {
+            if !self.accounts.contains_key(&mount.account) {
+                bail!(
+                    "mount `{}` refers to unknown account `{}`",
+                    mount.path,
+                    mount.account
+                );
+            }
+        }
+        let prefixes = self
+            .mounts
+            .iter()
+            .map(|mount| normalize_prefix(&mount.path))
+            .collect::<Result<Vec<_>>>()?;
+        for (i, a) in prefixes.iter().enumerate() {
+            for b in &prefixes[i + 1..] {
+                if a == b || is_ancestor(a, b) || is_ancestor(b, a) {
+                    bail!("mount paths `{}` and `{}` overlap", a, b);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Start a drive for every account and create a file system for every mount,
+    /// `make_fs` creates the file system of a drive given its root and workdir
+    pub async fn build(
+        self,
+        config: &DriveConfig,
+        make_fs: impl Fn(AliyunDrive, String, Option<PathBuf>) -> Result<AliyunDriveFileSystem>,
+    ) -> Result<MountFileSystem> {
+        let mut drives = BTreeMap::new();
+        for (name, account) in self.accounts {
+            if !self.mounts.iter().any(|mount| mount.account == name) {
+                warn!(account = %name, "account is not mounted");
+                continue;
+            }
+            let mut config = config.clone();
+            config.workdir = config.workdir.map(|dir| dir.join("accounts").join(&name));
+            config.drive_type = None;
+            if account.client_id.is_some() && account.client_secret.is_some() {
+                config.refresh_token_host = config.api_base_url.clone();
+                config.client_id = account.client_id;
+                config.client_secret = account.client_secret;
+            }
+            let workdir = config.workdir.clone();
+            let drive = AliyunDrive::new(config, account.refresh_token.unwrap_or_default())
+                .await
+                .with_context(|| format!("initialize account `{}` failed", name))?;
+            drives.insert(name, (drive, workdir));
+        }
+
+        let mut mounts = Vec::with_capacity(self.mounts.len());
+        for mount in self.mounts {
+            let (drive, workdir) = &drives[&mount.account];
+            let drive = drive
+                .with_drive_type(mount.drive_type)
+                .await
+                .with_context(|| format!("initialize mount `{}` failed", mount.path))?;
+            let mut fs = make_fs(drive, mount.root, workdir.clone())?;
+            if mount.read_only {
+                fs.set_read_only(true);
+            }
+            if mount.soft_quota.is_some() {
+                fs.set_soft_quota(mount.soft_quota);
+            }
+            info!(path = %mount.path, account = %mount.account, "drive mounted");
+            mounts.push((mount.path, fs));
+        }
+        MountFileSystem::new(mounts)
+    }
+}
+
+/// Normalize a mount path into `/a/b` form
+fn normalize_prefix(path: &str) -> Result<String> {
+    let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if segments.is_empty() {
+        bail!("mount path can not be the root directory");
+    }
+    if segments.iter().any(|s| *s == "." || *s == "..") {
+        bail!("invalid mount path `{}`", path);
+    }
+    Ok(format!("/{}", segments.join("/")))
+}
+
+/// Whether `ancestor` is a proper ancestor of the `/a/b` form `path`
+pub(crate) fn is_ancestor(ancestor: &str, path: &str) -> bool {
+    ancestor == "/"
+        || path
+            .strip_prefix(ancestor)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[derive(Clone)]
+struct Mount {
+    /// Decoded path prefix in `/a/b` form
+    prefix: String,
+    /// URL encoded path prefix
+    encoded_prefix: String,
+    fs: AliyunDriveFileSystem,
+}
+
+enum Route<'a> {
+    /// Path inside a mount, relative to the mount point
+    Mount(&'a Mount, DavPath),
+    /// Directory above mount points with the names of its children
+    Virtual(BTreeSet<String>),
+}
+
+/// File system dispatching to the drive mounted at the matching path prefix,
+/// directories above mount points are synthesized read-only
+#[derive(Clone)]
+pub struct MountFileSystem {
+    mounts: Vec<Mount>,
+}
+
+impl MountFileSystem {
+    pub fn new(mounts: Vec<(String, AliyunDriveFileSystem)>) -> Result<Self> {
+        let mounts = mounts
+            .into_iter()
+            .map(|(path, fs)| {
+                let prefix = normalize_prefix(&path)?;
+                let encoded_prefix = DavPath::new(&prefix)
+                    .with_context(|| format!("invalid mount path `{}`", path))?
+                    .as_url_string();
+                Ok(Mount {
+                    prefix,
+                    encoded_prefix,
+                    fs,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { mounts })
+    }
+
+    /// Directory caches of all mounts
+    pub fn dir_caches(&self) -> Vec<Cache> {
+        self.mounts
+            .iter()
+            .map(|mount| mount.fs.dir_cache.clone())
+            .collect()
+    }
+
+    /// File systems of all mounts with their path prefixes
+    pub fn mounts(&self) -> Vec<(String, AliyunDriveFileSystem)> {
+        self.mounts
+            .iter()
+            .map(|mount| (mount.prefix.clone(), mount.fs.clone()))
+            .collect()
+    }
+
+    fn route(&self, path: &DavPath) -> Result<Route<'_>, FsError> {
+        let raw = String::from_utf8_lossy(path.as_bytes());
+        let raw = match raw.trim_end_matches('/') {
+            "" => "/",
+            raw => raw,
+        };
+        for mount in &self.mounts {
+            if raw == mount.prefix || is_ancestor(&mount.prefix, raw) {
+                let url = path.as_url_string();
+                let rest = url.get(mount.encoded_prefix.len()..).unwrap_or_default();
+                let rest = if rest.is_empty() { "/" } else { rest };
+                let path = DavPath::new(rest).map_err(|_| FsError::GeneralFailure)?;
+                return Ok(Route::Mount(mount, path));
+            }
+        }
+        let children: BTreeSet<String> = self
+            .mounts
+            .iter()
+            .filter(|mount| is_ancestor(raw, &mount.prefix))
+            .filter_map(|mount| {
+                let rest = if raw == "/" {
+                    &mount.prefix[1..]
+                } else {
+                    &mount.prefix[raw.len() + 1..]
+                };
+                rest.split('/').next().map(ToString::to_string)
+            })
+            .collect();
+        if children.is_empty() {
+            return Err(FsError::NotFound);
+        }
+        Ok(Route::Virtual(children))
+    }
+
+    /// Route a pair of paths that must be on the same mount
+    fn route_pair(
+        &self,
+        from: &DavPath,
+        to: &DavPath,
+    ) -> Result<(&AliyunDriveFileSystem, DavPath, DavPath), FsError> {
+        match (self.route(from), self.route(to)) {
+            (Ok(Route::Mount(a, from)), Ok(Route::Mount(b, to))) if a.prefix == b.prefix => {
+                Ok((&a.fs, from, to))
+            }
+            (Ok(Route::Mount(..)), Ok(Route::Mount(..))) => {
+                warn!(from = %from.as_url_string(), to = %to.as_url_string(), "can not move or copy across mounts");
+                Err(FsError::Forbidden)
+            }
+            (Err(FsError::NotFound), _) => Err(FsError::NotFound),
+            _ => Err(FsError::Forbidden),
+        }
+    }
+}
+
+impl UserFileSystem for MountFileSystem {
+    fn for_user(&self, user: &User) -> Result<Box<dyn DavFileSystem>> {
+        let home = user.home.to_string_lossy();
+        let home = match home.trim_end_matches('/') {
+            "" => "/",
+            home => home,
+        };
+        // home inside a mount only sees that drive
+        for mount in &self.mounts {
+            if home == mount.prefix || is_ancestor(&mount.prefix, home) {
+                let rest = &home[mount.prefix.len()..];
+                let user = user.with_home(PathBuf::from(if rest.is_empty() { "/" } else { rest }));
+                return mount.fs.for_user(&user);
+            }
+        }
+        let mounts: Vec<_> = self
+            .mounts
+            .iter()
+            .filter(|mount| is_ancestor(home, &mount.prefix))
+            .map(|mount| {
+                let prefix = if home == "/" {
+                    mount.prefix.clone()
+                } else {
+                    mount.prefix[home.len()..].to_string()
+                };
+                let mut fs = mount.fs.clone();
+                fs.set_access(user.access.with_prefix(Path::new(&prefix)))
+                    .restrict_soft_quota(user.soft_quota);
+                (prefix, fs)
+            })
+            .collect();
+        if mounts.is_empty() {
+            bail!("home directory {} of user {} not found", home, user.name);
+        }
+        Ok(Box::new(MountFileSystem::new(mounts)?))
+    }
+}
+
+fn virtual_dir(name: &str) -> AliyunFile {
+    AliyunFile {
+        name: name.to_string(),
+        ..AliyunFile::new_root()
+    }
+}
+
+impl DavFileSystem for MountFileSystem {
+    fn open<'a>(
+        &'a self,
+        path: &'a DavPath,
+        options: OpenOptions,
+    ) -> FsFuture<'a, Box<dyn DavFile>> {
+        async move {
+            match self.route(path)? {
+                Route::Mount(mount, path) => mount.fs.open(&path, options).await,
+                Route::Virtual(_) => Err(FsError::Forbidden),
+            }
+        }
+        .boxed()
+    }
+
+    fn read_dir<'a>(
+        &'a self,
+        path: &'a DavPath,
+        meta: ReadDirMeta,
+    ) -> FsFuture<'a, FsStream<Box<dyn DavDirEntry>>> {
+        async move {
+            match self.route(path)? {
+                Route::Mount(mount, path) => mount.fs.read_dir(&path, meta).await,
+                Route::Virtual(children) => {
+                    debug!(path = %path.as_url_string(), "mount: read virtual dir");
+                    let entries: Vec<Box<dyn DavDirEntry>> = children
+                        .iter()
+                        .map(|name| Box::new(virtual_dir(name)) as Box<dyn DavDirEntry>)
+                        .collect();
+                    let stream = futures_util::stream::iter(entries);
+                    Ok(Box::pin(stream) as FsStream<Box<dyn DavDirEntry>>)
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn metadata<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, Box<dyn DavMetaData>> {
+        async move {
+            match self.route(path)? {
+                Route::Mount(mount, path) => mount.fs.metadata(&path).await,
+                Route::Virtual(_) => Ok(Box::new(AliyunFile::new_root()) as Box<dyn DavMetaData>),
+            }
+        }
+        .boxed()
+    }
+
+    fn create_dir<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+        async move {
+            match self.route(path) {
+                Ok(Route::Mount(mount, path)) => mount.fs.create_dir(&path).await,
+                Ok(Route::Virtual(_)) => Err(FsError::Exists),
+                Err(_) => Err(FsError::Forbidden),
+            }
+        }
+        .boxed()
+    }
+
+    fn remove_dir<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+        async move {
+            match self.route(path)? {
+                Route::Mount(mount, path) => mount.fs.remove_dir(&path).await,
+                Route::Virtual(_) => Err(FsError::Forbidden),
+            }
+        }
+        .boxed()
+    }
+
+    fn remove_file<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, ()> {
+        async move {
+            match self.route(path)? {
+                Route::Mount(mount, path) => mount.fs.remove_file(&path).await,
+                Route::Virtual(_) => Err(FsError::Forbidden),
+            }
+        }
+        .boxed()
+    }
+
+    fn rename<'a>(&'a self, from: &'a DavPath, to: &'a DavPath) -> FsFuture<'a, ()> {
+        async move {
+            let (fs, from, to) = self.route_pair(from, to)?;
+            fs.rename(&from, &to).await
+        }
+        .boxed()
+    }
+
+    fn copy<'a>(&'a self, from: &'a DavPath, to: &'a DavPath) -> FsFuture<'a, ()> {
+        async move {
+            let (fs, from, to) = self.route_pair(from, to)?;
+            fs.copy(&from, &to).await
+        }
+        .boxed()
+    }
+
+    fn get_quota(&self) -> FsFuture<'_, (u64, Option<u64>)> {
+        async move {
+            // the quota isn't asked for a path, so it covers all mounts, counting the space
+            // of each account once with the lowest limit of the mounts on it
+            let mut accounts: Vec<(&AliyunDrive, u64, u64)> = Vec::new();
+            for mount in &self.mounts {
+                let (used, limit) = mount.fs.get_quota().await?;
+                let limit = limit.unwrap_or(u64::MAX);
+                match accounts
+                    .iter_mut()
+                    .find(|(drive, ..)| drive.same_account(&mount.fs.drive))
+                {
+                    Some((_, _, account_limit)) => *account_limit = (*account_limit).min(limit),
+                    None => accounts.push((&mount.fs.drive, used, limit)),
+                }
+            }
+            let used = accounts.iter().map(|(_, used, _)| used).sum();
+            let limit = accounts
+                .iter()
+                .fold(0u64, |total, (_, _, limit)| total.saturating_add(*limit));
+            Ok((used, Some(limit)))
+        }
+        .boxed()
+    }
+
+    fn have_props<'a>(
+        &'a self,
+        _path: &'a DavPath,
+    ) -> std::pin::Pin<Box<dyn futures_util::Future<Output = bool> + Send + 'a>> {
+        Box::pin(ready(true))
+    }
+
+    fn get_prop(&self, path: &DavPath, prop: DavProp) -> FsFuture<'_, Vec<u8>> {
+        let route = self.route(path).map(|route| match route {
+            Route::Mount(mount, path) => Some((mount.fs.clone(), path)),
+            Route::Virtual(_) => None,
+        });
+        async move {
+            match route? {
+                Some((fs, path)) => fs.get_prop(&path, prop).await,
+                None => Err(FsError::NotImplemented),
+            }
+        }
+        .boxed()
+    }
+}
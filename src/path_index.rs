@@ -0,0 +1,206 @@
+//! Trie of the file ids of paths in a drive, filled from directory listings
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::drive::AliyunFile;
+
+#[derive(Default)]
+struct Node {
+    /// File id and when it was learned, `None` for ancestors of known paths
+    id: Option<(String, Instant)>,
+    children: HashMap<String, Node>,
+}
+
+impl Node {
+    fn child(&self, name: &str) -> Option<&Node> {
+        self.children.get(name)
+    }
+
+    fn child_mut(&mut self, name: &str) -> &mut Node {
+        self.children.entry(name.to_string()).or_default()
+    }
+
+    fn set_id(&mut self, id: &str, at: Instant) {
+        if self.id.as_ref().is_some_and(|(old, _)| old != id) {
+            // another file took the name, what was known below it is gone
+            self.children.clear();
+        }
+        self.id = Some((id.to_string(), at));
+    }
+
+    /// Drop the descendants whose ids expired and have nothing fresh below them,
+    /// returning whether this node is still worth keeping
+    fn prune(&mut self, ttl: Duration) -> bool {
+        self.children.retain(|_, child| child.prune(ttl));
+        !self.children.is_empty()
+            || self
+                .id
+                .as_ref()
+                .is_some_and(|(_, indexed)| indexed.elapsed() < ttl)
+    }
+
+    fn remove_ids(&mut self, ids: &HashSet<&str>) {
+        self.children.retain(|_, child| {
+            !child
+                .id
+                .as_ref()
+                .is_some_and(|(id, _)| ids.contains(id.as_str()))
+        });
+        for child in self.children.values_mut() {
+            child.remove_ids(ids);
+        }
+    }
+}
+
+/// File ids by absolute path in the drive, ids older than the TTL are not trusted
+#[derive(Clone)]
+pub struct PathIndex {
+    root: Arc<RwLock<Node>>,
+    /// Expiration time in seconds, shared with the directory cache
+    ttl: Arc<AtomicU64>,
+    /// When expired ids were last dropped, they are swept once per TTL to bound the trie
+    pruned: Arc<Mutex<Instant>>,
+}
+
+fn names(path: &Path) -> impl Iterator<Item = String> + '_ {
+    path.components().filter_map(|c| match c {
+        Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+        _ => None,
+    })
+}
+
+impl PathIndex {
+    pub fn new(ttl: Arc<AtomicU64>) -> Self {
+        Self {
+            root: Arc::default(),
+            ttl,
+            pruned: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl.load(Ordering::Relaxed))
+    }
+
+    fn fresh<'a>(&self, node: &'a Node) -> Option<&'a str> {
+        let ttl = self.ttl();
+        node.id
+            .as_ref()
+            .filter(|(_, indexed)| indexed.elapsed() < ttl)
+            .map(|(id, _)| id.as_str())
+    }
+
+    /// File id of `path`
+    pub fn get(&self, path: &Path) -> Option<String> {
+        let (known, id) = self.deepest(path);
+        (names(&known).count() == names(path).count()).then_some(id)
+    }
+
+    /// The longest ancestor of `path`, or `path` itself, with a known id, falling back to the root
+    pub fn deepest(&self, path: &Path) -> (PathBuf, String) {
+        let root = self.root.read().unwrap();
+        let mut node = &*root;
+        let mut known = (PathBuf::from("/"), "root".to_string());
+        let mut current = PathBuf::from("/");
+        for name in names(path) {
+            let Some(child) = node.child(&name) else {
+                break;
+            };
+            current.push(&name);
+            node = child;
+            if let Some(id) = self.fresh(node) {
+                known = (current.clone(), id.to_string());
+            }
+        }
+        known
+    }
+
+    pub fn insert(&self, path: &Path, id: &str) {
+        let mut root = self.root.write().unwrap();
+        let mut node = &mut *root;
+        for name in names(path) {
+            node = node.child_mut(&name);
+        }
+        node.set_id(id, Instant::now());
+    }
+
+    /// Record the listing of the directory `dir` fetched at `at`, forgetting children that are gone
+    pub fn insert_listing(&self, dir: &Path, dir_id: &str, files: &[AliyunFile], at: Instant) {
+        let mut root = self.root.write().unwrap();
+        let mut node = &mut *root;
+        for name in names(dir) {
+            node = node.child_mut(&name);
+        }
+        node.set_id(dir_id, at);
+        let listed: HashMap<&str, &str> = files
+            .iter()
+            .map(|f| (f.name.as_str(), f.id.as_str()))
+            .collect();
+        node.children
+            .retain(|name, _| listed.contains_key(name.as_str()));
+        for (name, id) in listed {
+            node.child_mut(name).set_id(id, at);
+        }
+        self.prune_expired(&mut root);
+    }
+
+    /// Drop expired ids from `root` if they haven't been swept for a TTL
+    fn prune_expired(&self, root: &mut Node) {
+        let ttl = self.ttl();
+        let mut pruned = self.pruned.lock().unwrap();
+        if pruned.elapsed() >= ttl {
+            root.prune(ttl);
+            *pruned = Instant::now();
+        }
+    }
+
+    /// Number of paths in the trie
+    #[cfg(test)]
+    pub fn path_count(&self) -> usize {
+        fn count(node: &Node) -> usize {
+            node.children.values().map(|child| 1 + count(child)).sum()
+        }
+        count(&self.root.read().unwrap())
+    }
+
+    /// Forget `path` and everything below it
+    pub fn remove(&self, path: &Path) {
+        take(&mut self.root.write().unwrap(), path);
+    }
+
+    /// Move what is known about `from` to `to`
+    pub fn rename(&self, from: &Path, to: &Path) {
+        let mut root = self.root.write().unwrap();
+        let moved = take(&mut root, from);
+        take(&mut root, to);
+        if let Some(moved) = moved {
+            let mut node = &mut *root;
+            for name in names(to) {
+                node = node.child_mut(&name);
+            }
+            *node = moved;
+        }
+    }
+
+    /// Forget the files with one of `ids` and everything below them
+    pub fn remove_ids(&self, ids: &HashSet<&str>) {
+        self.root.write().unwrap().remove_ids(ids);
+    }
+
+    pub fn clear(&self) {
+        *self.root.write().unwrap() = Node::default();
+    }
+}
+
+/// Remove the node of `path` from the trie
+fn take(root: &mut Node, path: &Path) -> Option<Node> {
+    let mut node = root;
+    for name in names(path.parent()?) {
+        node = node.children.get_mut(&name)?;
+    }
+    node.children.remove(&*path.file_name()?.to_string_lossy())
+}
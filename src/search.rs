@@ -0,0 +1,281 @@
+//! WebDAV SEARCH (RFC 5323) with the DAV:basicsearch grammar
+//!
+//! Conditions on names, modification times and sizes are translated to the drive's search query,
+//! the search is then answered with a PROPFIND of the virtual `/.search/<query>/` folder.
+//! The drive's search is drive wide, so the only supported scope is the root with infinite depth.
+
+use dav_server::{body::Body, DavConfig, DavHandler};
+use hyper::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Method, Request, Response, StatusCode};
+use time::format_description::well_known::{Rfc2822, Rfc3339};
+use time::{OffsetDateTime, UtcOffset};
+use tracing::debug;
+use xmltree::{Element, Namespace, XMLNode};
+
+const DAV_NS: &str = "DAV:";
+
+/// Collection searched by a request, `depth` is empty when not given
+struct Scope {
+    path: String,
+    depth: String,
+}
+
+/// Value of the `DASL` header advertising the supported grammar
+pub const DASL: &str = "<DAV:basicsearch>";
+
+pub fn is_search_request(req: &Request<hyper::Body>) -> bool {
+    req.method().as_str() == "SEARCH"
+}
+
+/// Answer a SEARCH request sent to the root of a drive
+pub async fn handle(
+    dav_server: DavHandler,
+    config: DavConfig,
+    req: Request<hyper::Body>,
+) -> Response<Body> {
+    let (parts, body) = req.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(err) => return bad_request(format!("read request body failed: {}", err)),
+    };
+    let (query, propfind, scope) = match parse(&body) {
+        Ok(search) => search,
+        Err(err) => return bad_request(err),
+    };
+    let root = parts.uri.path().trim_end_matches('/');
+    if scope.path.trim_end_matches('/') != root {
+        return unprocessable(format!(
+            "DAV:scope `{}` is not supported, only `{}/` can be searched",
+            scope.path, root
+        ));
+    }
+    if !scope.depth.is_empty() && !scope.depth.eq_ignore_ascii_case("infinity") {
+        return unprocessable(format!("DAV:depth `{}` is not supported", scope.depth));
+    }
+    debug!(query = %query, "search");
+    let mut url = url::Url::parse("http://localhost/").unwrap();
+    url.path_segments_mut().unwrap().push(&query);
+    let folder = format!(
+        "{}/.search{}/",
+        parts.uri.path().trim_end_matches('/'),
+        url.path()
+    );
+    let propfind_req = hyper::Request::builder()
+        .method(Method::from_bytes(b"PROPFIND").unwrap())
+        .uri(&folder)
+        .header("Depth", "1")
+        .header(CONTENT_TYPE, "application/xml")
+        .body(hyper::Body::from(propfind))
+        .unwrap();
+    let response = dav_server.handle_with(config, propfind_req).await;
+    if response.status() == StatusCode::NOT_FOUND {
+        // the search folder is only exposed when serving the whole drive
+        return error_response(
+            StatusCode::NOT_IMPLEMENTED,
+            "SEARCH is only supported at the root of the whole drive".to_string(),
+        );
+    }
+    if response.status() != StatusCode::MULTI_STATUS {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(err) => return bad_request(format!("search failed: {}", err)),
+    };
+    let body = match without_folder(&body) {
+        Some(body) => body,
+        None => body.to_vec(),
+    };
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(bytes::Bytes::from(body)))
+}
+
+/// Parse a `DAV:searchrequest` into the drive's search query, the PROPFIND body of its select and
+/// its scope
+fn parse(body: &[u8]) -> Result<(String, String, Scope), String> {
+    let root = Element::parse(body).map_err(|err| format!("invalid XML: {}", err))?;
+    if !is_dav(&root, "searchrequest") {
+        return Err("expected a DAV:searchrequest".to_string());
+    }
+    let search = dav_child(&root, "basicsearch").ok_or("only DAV:basicsearch is supported")?;
+    let scope = scope(search)?;
+    let condition = dav_child(search, "where").ok_or("missing DAV:where")?;
+    let condition = dav_children(condition).next().ok_or("empty DAV:where")?;
+    let query = translate(condition)?;
+    if query.contains('/') {
+        return Err("search values can not contain `/`".to_string());
+    }
+
+    let mut propfind = Element::new("propfind");
+    propfind.prefix = Some("D".to_string());
+    propfind.namespace = Some(DAV_NS.to_string());
+    propfind
+        .namespaces
+        .get_or_insert_with(Namespace::empty)
+        .put("D", DAV_NS);
+    match dav_child(search, "select") {
+        Some(select) => propfind.children = select.children.clone(),
+        None => {
+            let mut allprop = Element::new("allprop");
+            allprop.prefix = Some("D".to_string());
+            allprop.namespace = Some(DAV_NS.to_string());
+            propfind.children.push(XMLNode::Element(allprop));
+        }
+    }
+    let mut buf = Vec::new();
+    propfind
+        .write(&mut buf)
+        .map_err(|err| format!("invalid DAV:select: {}", err))?;
+    Ok((query, String::from_utf8(buf).unwrap(), scope))
+}
+
+/// The single scope of `DAV:from`
+fn scope(search: &Element) -> Result<Scope, String> {
+    let from = dav_child(search, "from").ok_or("missing DAV:from")?;
+    let mut scopes = dav_children(from).filter(|child| child.name == "scope");
+    let scope = scopes.next().ok_or("missing DAV:scope")?;
+    if scopes.next().is_some() {
+        return Err("only one DAV:scope is supported".to_string());
+    }
+    let depth = dav_child(scope, "depth")
+        .and_then(|depth| depth.get_text())
+        .unwrap_or_default();
+    let depth = depth.trim();
+    let href = dav_child(scope, "href")
+        .and_then(|href| href.get_text())
+        .ok_or("missing DAV:href in DAV:scope")?;
+    let href = href.trim();
+    // compared with the still percent-encoded path of the request
+    let path = match url::Url::parse(href) {
+        Ok(url) => url.path().to_string(),
+        Err(_) => href.to_string(),
+    };
+    Ok(Scope {
+        path,
+        depth: depth.to_string(),
+    })
+}
+
+/// Translate a condition of `DAV:where` to the drive's query syntax
+fn translate(condition: &Element) -> Result<String, String> {
+    match condition.name.as_str() {
+        op @ ("and" | "or") => {
+            let operands = dav_children(condition)
+                .map(translate)
+                .collect::<Result<Vec<_>, _>>()?;
+            match operands.len() {
+                0 => Err(format!("empty DAV:{}", op)),
+                1 => Ok(operands.into_iter().next().unwrap()),
+                _ => Ok(format!("({})", operands.join(&format!(" {} ", op)))),
+            }
+        }
+        "is-collection" => Ok("type = \"folder\"".to_string()),
+        op @ ("eq" | "like" | "gt" | "gte" | "lt" | "lte") => {
+            let prop = dav_child(condition, "prop")
+                .and_then(|prop| dav_children(prop).next())
+                .ok_or_else(|| format!("missing DAV:prop in DAV:{}", op))?;
+            let literal = dav_child(condition, "literal")
+                .or_else(|| dav_child(condition, "typed-literal"))
+                .and_then(|literal| literal.get_text())
+                .unwrap_or_default();
+            let literal = literal.trim();
+            let operator = match op {
+                "eq" => "=",
+                "gt" => ">",
+                "gte" => ">=",
+                "lt" => "<",
+                "lte" => "<=",
+                _ => "match",
+            };
+            match (prop.name.as_str(), op) {
+                ("displayname", "eq") => Ok(format!("name = {}", quote(literal))),
+                ("displayname", "like") => {
+                    let pattern: String = literal.chars().filter(|c| !"%_".contains(*c)).collect();
+                    Ok(format!("name match {}", quote(&pattern)))
+                }
+                ("getlastmodified", op) if op != "like" => Ok(format!(
+                    "updated_at {} {}",
+                    operator,
+                    quote(&timestamp(literal)?)
+                )),
+                ("getcontentlength", op) if op != "like" => {
+                    let size: u64 = literal
+                        .parse()
+                        .map_err(|_| format!("invalid size `{}`", literal))?;
+                    Ok(format!("size {} {}", operator, size))
+                }
+                (prop, op) => Err(format!("DAV:{} on DAV:{} is not supported", op, prop)),
+            }
+        }
+        op => Err(format!("DAV:{} is not supported", op)),
+    }
+}
+
+/// Date in the drive's query format from an HTTP date or an RFC 3339 timestamp
+fn timestamp(value: &str) -> Result<String, String> {
+    let date = OffsetDateTime::parse(value, &Rfc2822)
+        .or_else(|_| OffsetDateTime::parse(&value.replace(" GMT", " +0000"), &Rfc2822))
+        .or_else(|_| OffsetDateTime::parse(value, &Rfc3339))
+        .map_err(|_| format!("invalid date `{}`", value))?;
+    let date = date.to_offset(UtcOffset::UTC);
+    Ok(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        date.year(),
+        u8::from(date.month()),
+        date.day(),
+        date.hour(),
+        date.minute(),
+        date.second()
+    ))
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The multistatus `body` of a PROPFIND without its first response, which is the one of the
+/// searched folder itself, `None` if it can't be parsed
+fn without_folder(body: &[u8]) -> Option<Vec<u8>> {
+    let mut root = Element::parse(body).ok()?;
+    let folder = root.children.iter().position(
+        |node| matches!(node, XMLNode::Element(response) if is_dav(response, "response")),
+    )?;
+    root.children.remove(folder);
+    let mut buf = Vec::new();
+    root.write(&mut buf).ok()?;
+    Some(buf)
+}
+
+fn is_dav(element: &Element, name: &str) -> bool {
+    element.name == name && element.namespace.as_deref() == Some(DAV_NS)
+}
+
+fn dav_children(element: &Element) -> impl Iterator<Item = &Element> {
+    element
+        .children
+        .iter()
+        .filter_map(XMLNode::as_element)
+        .filter(|child| child.namespace.as_deref() == Some(DAV_NS))
+}
+
+fn dav_child<'a>(element: &'a Element, name: &str) -> Option<&'a Element> {
+    dav_children(element).find(|child| child.name == name)
+}
+
+fn bad_request(message: impl Into<String>) -> Response<Body> {
+    error_response(StatusCode::BAD_REQUEST, message.into())
+}
+
+fn unprocessable(message: String) -> Response<Body> {
+    error_response(StatusCode::UNPROCESSABLE_ENTITY, message)
+}
+
+fn error_response(status: StatusCode, message: String) -> Response<Body> {
+    debug!(error = %message, "invalid search request");
+    hyper::Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Body::from(message + "\n"))
+        .unwrap()
+}
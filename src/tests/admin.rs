@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use dav_server::davpath::DavPath;
+use dav_server::fs::{DavFileSystem, OpenOptions};
+use hyper::StatusCode;
+use serde_json::Value;
+
+use super::{call, call_with_headers, TestContext};
+use crate::webdav::AliyunDriveWebDav;
+
+const AUTH: [(&str, &str); 1] = [("Authorization", "Bearer admin-token")];
+
+fn service(ctx: &TestContext) -> AliyunDriveWebDav {
+    ctx.service()
+        .options(|options| options.admin_token = Some(Arc::from("admin-token")))
+        .build()
+}
+
+async fn admin(svc: &mut AliyunDriveWebDav, method: &str, path: &str) -> (StatusCode, Value) {
+    let res = call_with_headers(svc, method, path, &AUTH, "").await;
+    (res.status, serde_json::from_slice(&res.body).unwrap())
+}
+
+#[tokio::test]
+async fn admin_requires_token() {
+    let ctx = TestContext::new().await;
+    let mut svc = service(&ctx);
+
+    let (status, _) = call(&mut svc, "GET", "/_admin/status", None, "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let auth = [("Authorization", "Bearer wrong")];
+    let res = call_with_headers(&mut svc, "GET", "/_admin/status", &auth, "").await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = admin(&mut svc, "GET", "/_admin/status").await;
+    assert_eq!(status, StatusCode::OK);
+    let drive = &body["drives"][0];
+    assert_eq!(drive["path"], "/");
+    assert_eq!(drive["drive_id"], "1");
+    assert_eq!(drive["ready"], true);
+    assert!(drive["token"]["expires_in"].as_u64().unwrap() > 7000);
+    let (status, _) = admin(&mut svc, "POST", "/_admin/status").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    let (status, _) = admin(&mut svc, "GET", "/_admin/nothing").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn admin_invalidates_cache() {
+    let ctx = TestContext::new().await;
+    let sub = ctx.mock.add_folder("root", "sub");
+    let mut svc = service(&ctx);
+    call(&mut svc, "PROPFIND", "/", None, "").await;
+    call(&mut svc, "PROPFIND", "/sub/", None, "").await;
+    ctx.mock.add_file("root", "a.txt", b"a");
+    ctx.mock.add_file(&sub, "b.txt", b"b");
+
+    let (_, body) = call(&mut svc, "PROPFIND", "/", None, "").await;
+    assert!(!body.contains("a.txt"), "{}", body);
+
+    let (status, _) = admin(&mut svc, "POST", "/_admin/cache/invalidate?path=/").await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = call(&mut svc, "PROPFIND", "/", None, "").await;
+    assert!(body.contains("a.txt"), "{}", body);
+    let (_, body) = call(&mut svc, "PROPFIND", "/sub/", None, "").await;
+    assert!(!body.contains("b.txt"), "{}", body);
+
+    let path = "/_admin/cache/invalidate?path=/&recursive=true";
+    let (status, _) = admin(&mut svc, "POST", path).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = call(&mut svc, "PROPFIND", "/sub/", None, "").await;
+    assert!(body.contains("b.txt"), "{}", body);
+
+    let path = "/_admin/cache/invalidate?path=/sub&recursive=maybe";
+    let (status, _) = admin(&mut svc, "POST", path).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn admin_reports_quota_uploads_and_refreshes_token() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "a.txt", b"hello");
+    let mut svc = service(&ctx);
+
+    let (status, body) = admin(&mut svc, "GET", "/_admin/quota").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["drives"][0]["used"], 5);
+    assert_eq!(body["drives"][0]["total"], 1024 * 1024 * 1024);
+
+    let options = OpenOptions {
+        write: true,
+        create: true,
+        ..Default::default()
+    };
+    let path = DavPath::new("/b.txt").unwrap();
+    let file = ctx.fs.open(&path, options).await.unwrap();
+    let (_, body) = admin(&mut svc, "GET", "/_admin/uploads").await;
+    assert_eq!(body["uploads"][0]["name"], "b.txt");
+    assert_eq!(body["uploads"][0]["parent_file_id"], "root");
+    drop(file);
+
+    let refreshes = ctx.mock.calls("/oauth/access_token");
+    let (status, body) = admin(&mut svc, "POST", "/_admin/token/refresh").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["drives"][0]["ready"], true);
+    assert_eq!(ctx.mock.calls("/oauth/access_token"), refreshes + 1);
+}
@@ -0,0 +1,189 @@
+use hyper::StatusCode;
+use md5::{Digest, Md5};
+use sha2::Sha256;
+
+use super::{call, call_with_headers, TestContext};
+use crate::auth::{
+    check_password_hash, verify_password, Accounts, AuthScheme, Authenticator, Credentials,
+    HashAlgorithm, Password,
+};
+use crate::webdav::AliyunDriveWebDav;
+
+fn service(ctx: &TestContext, password: Password, schemes: &[AuthScheme]) -> AliyunDriveWebDav {
+    ctx.service().admin(password, schemes).build()
+}
+
+/// Answer a Digest challenge the way a client would, as its `nc`th request with the nonce
+fn digest_response(challenge: &str, method: &str, uri: &str, password: &str, nc: u32) -> String {
+    let nonce = challenge
+        .split("nonce=\"")
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap();
+    let sha256 = challenge.contains("algorithm=SHA-256");
+    let hex = |data: String| {
+        if sha256 {
+            format!("{:x}", Sha256::digest(data.as_bytes()))
+        } else {
+            format!("{:x}", Md5::digest(data.as_bytes()))
+        }
+    };
+    let ha1 = hex(format!("admin:aliyundrive-webdav:{}", password));
+    let ha2 = hex(format!("{}:{}", method, uri));
+    let nc = format!("{:08x}", nc);
+    let response = hex(format!("{}:{}:{}:0a4f113b:auth:{}", ha1, nonce, nc, ha2));
+    format!(
+        "Digest username=\"admin\", realm=\"aliyundrive-webdav\", nonce=\"{}\", uri=\"{}\", \
+         algorithm={}, qop=auth, nc={}, cnonce=\"0a4f113b\", response=\"{}\"",
+        nonce,
+        uri,
+        if sha256 { "SHA-256" } else { "MD5" },
+        nc,
+        response
+    )
+}
+
+#[test]
+fn auth_verifies_supported_hashes() {
+    let hashes = [
+        bcrypt::hash("secret", 4).unwrap(),
+        HashAlgorithm::Argon2.hash("secret").unwrap(),
+        HashAlgorithm::Sha512Crypt.hash("secret").unwrap(),
+    ];
+    for hash in &hashes {
+        check_password_hash(hash).unwrap();
+        assert!(verify_password("secret", hash), "{}", hash);
+        assert!(!verify_password("wrong", hash), "{}", hash);
+    }
+    assert!(hashes[1].starts_with("$argon2"));
+    assert!(hashes[2].starts_with("$6$"));
+
+    assert!(check_password_hash("secret").is_err());
+    assert!(check_password_hash("$1$salt$hash").is_err());
+    assert!(!verify_password("secret", "secret"));
+}
+
+#[tokio::test]
+async fn auth_accepts_password_hash() {
+    let ctx = TestContext::new().await;
+    let hash = bcrypt::hash("secret", 4).unwrap();
+    let mut svc = service(&ctx, Password::Hash(hash), &[AuthScheme::Basic]);
+
+    let (status, _) = call(&mut svc, "PROPFIND", "/", None, "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call(&mut svc, "PROPFIND", "/", Some(("admin", "wrong")), "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call(&mut svc, "PROPFIND", "/", Some(("root", "secret")), "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call(&mut svc, "PROPFIND", "/", Some(("admin", "secret")), "").await;
+    assert_eq!(status, StatusCode::MULTI_STATUS);
+}
+
+#[tokio::test]
+async fn auth_accepts_plain_password() {
+    let ctx = TestContext::new().await;
+    let mut svc = service(
+        &ctx,
+        Password::Plain("secret".to_string()),
+        &[AuthScheme::Basic],
+    );
+
+    let (status, _) = call(&mut svc, "PROPFIND", "/", Some(("admin", "secre")), "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call(&mut svc, "PROPFIND", "/", Some(("admin", "secret")), "").await;
+    assert_eq!(status, StatusCode::MULTI_STATUS);
+}
+
+#[tokio::test]
+async fn auth_accepts_digest() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "a.txt", b"hello");
+    let password = Password::Plain("secret".to_string());
+    let mut svc = service(&ctx, password, &[AuthScheme::Digest]);
+
+    let res = call_with_headers(&mut svc, "GET", "/a.txt", &[], "").await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    let challenges: Vec<_> = res
+        .headers
+        .get_all("WWW-Authenticate")
+        .iter()
+        .map(|value| value.to_str().unwrap().to_string())
+        .collect();
+    assert_eq!(challenges.len(), 2);
+    assert!(
+        challenges[0].contains("algorithm=SHA-256"),
+        "{:?}",
+        challenges
+    );
+    assert!(challenges[1].contains("algorithm=MD5"), "{:?}", challenges);
+
+    // both challenges may carry the same nonce, so the count goes on
+    for (nc, challenge) in (1..).step_by(2).zip(&challenges) {
+        let auth = digest_response(challenge, "GET", "/a.txt", "secret", nc);
+        let res =
+            call_with_headers(&mut svc, "GET", "/a.txt", &[("Authorization", &auth)], "").await;
+        assert_eq!(res.status, StatusCode::OK, "{}", challenge);
+        assert_eq!(res.text(), "hello");
+        // a captured response can not be replayed
+        let res =
+            call_with_headers(&mut svc, "GET", "/a.txt", &[("Authorization", &auth)], "").await;
+        assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+        let auth = digest_response(challenge, "GET", "/a.txt", "secret", nc + 1);
+        let res =
+            call_with_headers(&mut svc, "GET", "/a.txt", &[("Authorization", &auth)], "").await;
+        assert_eq!(res.status, StatusCode::OK, "{}", challenge);
+
+        let auth = digest_response(challenge, "GET", "/a.txt", "wrong", nc + 2);
+        let res =
+            call_with_headers(&mut svc, "GET", "/a.txt", &[("Authorization", &auth)], "").await;
+        assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+        // a response for another uri can not be replayed
+        let auth = digest_response(challenge, "GET", "/b.txt", "secret", nc + 2);
+        let res =
+            call_with_headers(&mut svc, "GET", "/a.txt", &[("Authorization", &auth)], "").await;
+        assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    }
+    let forged = digest_response(
+        &challenges[0].replace("nonce=\"", "nonce=\"0"),
+        "GET",
+        "/a.txt",
+        "secret",
+        1,
+    );
+    let res = call_with_headers(&mut svc, "GET", "/a.txt", &[("Authorization", &forged)], "").await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+
+    // basic is not enabled
+    let (status, _) = call(&mut svc, "GET", "/a.txt", Some(("admin", "secret")), "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn auth_accepts_bearer_token() {
+    let ctx = TestContext::new().await;
+    let password = Password::Plain("secret".to_string());
+    let mut svc = service(&ctx, password, &[AuthScheme::Basic, AuthScheme::Bearer]);
+
+    let auth = [("Authorization", "Bearer api-token")];
+    let res = call_with_headers(&mut svc, "PROPFIND", "/", &auth, "").await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    let auth = [("Authorization", "Bearer wrong-token")];
+    let res = call_with_headers(&mut svc, "PROPFIND", "/", &auth, "").await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call(&mut svc, "PROPFIND", "/", Some(("admin", "secret")), "").await;
+    assert_eq!(status, StatusCode::MULTI_STATUS);
+}
+
+#[test]
+fn auth_rejects_unusable_schemes() {
+    let hashed = || {
+        Credentials::new(
+            "admin".to_string(),
+            Password::Hash("$6$x".to_string()),
+            Vec::new(),
+        )
+    };
+    assert!(Authenticator::new(vec![AuthScheme::Digest], Accounts::Single(hashed())).is_err());
+    assert!(Authenticator::new(vec![AuthScheme::Bearer], Accounts::Single(hashed())).is_err());
+    assert!(Authenticator::new(vec![AuthScheme::Basic], Accounts::Single(hashed())).is_ok());
+}
@@ -0,0 +1,102 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use clap::{CommandFactory, Parser};
+use hyper::StatusCode;
+
+use super::{admin_auth, call, TestContext};
+use crate::auth::{AuthScheme, Password};
+use crate::cache::Cache;
+use crate::config::{args_with_config, LiveSettings};
+use crate::Opt;
+
+fn parse(config: &str, args: &[&str]) -> anyhow::Result<Opt> {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    file.write_all(config.as_bytes()).unwrap();
+    let path = file.path().to_str().unwrap().to_string();
+    let args = ["aliyundrive-webdav", "--config", &path]
+        .iter()
+        .chain(args)
+        .map(Into::into)
+        .collect();
+    Ok(Opt::try_parse_from(args_with_config(
+        &Opt::command(),
+        args,
+    )?)?)
+}
+
+#[test]
+fn config_file_provides_defaults() {
+    let config = r#"
+read_only = true
+no_trash = false
+upload_buffer_size = 1024
+auth_user = "admin"
+auth-password = "secret"
+auth_scheme = ["basic", "bearer"]
+"#;
+    let opt = parse(config, &[]).unwrap();
+    assert!(opt.read_only);
+    assert!(!opt.no_trash);
+    assert_eq!(opt.upload_buffer_size, 1024);
+    assert_eq!(opt.auth_user.as_deref(), Some("admin"));
+    assert_eq!(opt.auth_password.as_deref(), Some("secret"));
+    assert_eq!(opt.auth_scheme, [AuthScheme::Basic, AuthScheme::Bearer]);
+    // defaults of options missing from the file are kept
+    assert_eq!(opt.cache_ttl, 600);
+
+    let opt = parse(config, &["--upload-buffer-size", "2048", "-U", "root"]).unwrap();
+    assert_eq!(opt.upload_buffer_size, 2048);
+    assert_eq!(opt.auth_user.as_deref(), Some("root"));
+    assert!(opt.read_only);
+
+    assert!(parse("no_such_option = 1", &[]).is_err());
+    assert!(parse("config = \"other.toml\"", &[]).is_err());
+    assert!(parse("cache_ttl = \"soon\"", &[]).is_err());
+
+    // an invalid argument is reported instead of dropping the file
+    let args = vec!["aliyundrive-webdav".into(), "--no-such-flag".into()];
+    assert!(args_with_config(&Opt::command(), args).is_err());
+    let args = vec!["aliyundrive-webdav".into(), "--help".into()];
+    assert!(args_with_config(&Opt::command(), args).is_ok());
+}
+
+#[tokio::test]
+async fn config_cache_ttl_changes_apply_to_cached_entries() {
+    let cache = Cache::new(10, 0);
+    cache
+        .insert("/".to_string(), "root".to_string(), Vec::new())
+        .await;
+    assert!(cache.get("/").is_none());
+    cache.set_ttl(60);
+    assert!(cache.get("/").is_some());
+}
+
+#[tokio::test]
+async fn config_live_settings_apply_while_serving() {
+    let live = Arc::new(LiveSettings::default());
+    let fs_live = live.clone();
+    let ctx = TestContext::with_fs(move |fs| {
+        fs.set_live_settings(fs_live.clone());
+    })
+    .await;
+    let mut svc = ctx
+        .service()
+        .options(|options| options.live = live.clone())
+        .build();
+
+    live.set_read_only(true);
+    let (status, _) = call(&mut svc, "PUT", "/a.txt", None, "hello").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    live.set_read_only(false);
+    let (status, _) = call(&mut svc, "PUT", "/a.txt", None, "hello").await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let password = Password::Plain("secret".to_string());
+    *svc.options.auth.write().unwrap() = Some(Arc::new(admin_auth(password, &[AuthScheme::Basic])));
+    let (status, _) = call(&mut svc, "GET", "/a.txt", None, "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = call(&mut svc, "GET", "/a.txt", Some(("admin", "secret")), "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "hello");
+}
@@ -0,0 +1,598 @@
+use std::io::{Cursor, Read, SeekFrom};
+use std::time::Duration;
+
+use dav_server::{
+    davpath::DavPath,
+    fs::{DavFileSystem, OpenOptions},
+};
+use hyper::StatusCode;
+
+use super::TestContext;
+use crate::block_cache::BlockCache;
+use crate::vfs::UploadMemory;
+
+#[tokio::test]
+async fn propfind_lists_directory() {
+    let ctx = TestContext::new().await;
+    let docs = ctx.mock.add_folder("root", "docs");
+    ctx.mock.add_file("root", "a.txt", b"hello");
+    ctx.mock.add_file(&docs, "b.txt", b"world");
+
+    let res = ctx.propfind("/").await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    let body = res.text();
+    assert!(body.contains("/docs/"), "{}", body);
+    assert!(body.contains("/a.txt"), "{}", body);
+
+    let res = ctx.propfind("/docs/").await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    assert!(res.text().contains("/docs/b.txt"));
+
+    let res = ctx.propfind("/missing/").await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn get_downloads_file_content() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "a.txt", b"hello world");
+
+    let res = ctx.get("/a.txt").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(&res.body[..], b"hello world");
+
+    let res = ctx
+        .request("GET", "/a.txt", &[("Range", "bytes=6-10")], "")
+        .await;
+    assert_eq!(res.status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.headers["Content-Range"], "bytes 6-10/11");
+    assert_eq!(&res.body[..], b"world");
+}
+
+#[tokio::test]
+async fn get_serves_blocks_from_read_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let read_cache = BlockCache::new(dir.path().to_path_buf(), 1024, 4)
+        .await
+        .unwrap();
+    let ctx = TestContext::with_fs(move |fs| {
+        fs.set_read_cache(Some(read_cache.clone()));
+    })
+    .await;
+    let file_id = ctx.mock.add_file("root", "a.txt", b"hello world");
+    let download_path = format!("/oss/download/{}", file_id);
+
+    let res = ctx
+        .request("GET", "/a.txt", &[("Range", "bytes=6-10")], "")
+        .await;
+    assert_eq!(&res.body[..], b"world");
+    assert_eq!(ctx.mock.calls(&download_path), 2);
+
+    let res = ctx.get("/a.txt").await;
+    assert_eq!(&res.body[..], b"hello world");
+    assert_eq!(ctx.mock.calls(&download_path), 3);
+    let res = ctx
+        .request("GET", "/a.txt", &[("Range", "bytes=2-8")], "")
+        .await;
+    assert_eq!(&res.body[..], b"llo wor");
+    assert_eq!(ctx.mock.calls(&download_path), 3);
+
+    // cached blocks survive a restart
+    let read_cache = BlockCache::new(dir.path().to_path_buf(), 1024, 4)
+        .await
+        .unwrap();
+    let hash = ctx.mock.get(&file_id).unwrap().content_hash();
+    let block = read_cache.get(&file_id, &hash, 1).await.unwrap();
+    assert_eq!(&block[..], b"o wo");
+    drop(read_cache);
+
+    // blocks cut with another block size are dropped
+    let read_cache = BlockCache::new(dir.path().to_path_buf(), 1024, 5)
+        .await
+        .unwrap();
+    assert!(read_cache.get(&file_id, &hash, 1).await.is_none());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    let ctx = TestContext::with_fs(move |fs| {
+        fs.set_read_cache(Some(read_cache.clone()));
+    })
+    .await;
+    ctx.mock.add_file("root", "a.txt", b"hello world");
+    let res = ctx
+        .request("GET", "/a.txt", &[("Range", "bytes=6-10")], "")
+        .await;
+    assert_eq!(&res.body[..], b"world");
+}
+
+#[tokio::test]
+async fn read_cache_evicts_least_recently_used_blocks() {
+    let dir = tempfile::tempdir().unwrap();
+    let read_cache = BlockCache::new(dir.path().to_path_buf(), 8, 4)
+        .await
+        .unwrap();
+    let ctx = TestContext::with_fs({
+        let read_cache = read_cache.clone();
+        move |fs| {
+            fs.set_read_cache(Some(read_cache.clone()));
+        }
+    })
+    .await;
+    let file_id = ctx.mock.add_file("root", "a.txt", b"0123456789ab");
+    let hash = ctx.mock.get(&file_id).unwrap().content_hash();
+
+    let res = ctx
+        .request("GET", "/a.txt", &[("Range", "bytes=0-7")], "")
+        .await;
+    assert_eq!(&res.body[..], b"01234567");
+    // touch block 0 so that block 1 is the least recently used
+    assert!(read_cache.get(&file_id, &hash, 0).await.is_some());
+    let res = ctx
+        .request("GET", "/a.txt", &[("Range", "bytes=8-11")], "")
+        .await;
+    assert_eq!(&res.body[..], b"89ab");
+
+    assert!(read_cache.get(&file_id, &hash, 0).await.is_some());
+    assert!(read_cache.get(&file_id, &hash, 1).await.is_none());
+    assert!(read_cache.get(&file_id, &hash, 2).await.is_some());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+}
+
+#[tokio::test]
+async fn sequential_reads_are_prefetched() {
+    let ctx = TestContext::with_fs(|fs| {
+        fs.set_read_ahead(2);
+    })
+    .await;
+    let file_id = ctx.mock.add_file("root", "a.bin", b"0123456789abcdefghij");
+    let download_path = format!("/oss/download/{}", file_id);
+
+    let path = DavPath::new("/a.bin").unwrap();
+    let options = OpenOptions {
+        read: true,
+        ..Default::default()
+    };
+    let mut file = ctx.fs.open(&path, options.clone()).await.unwrap();
+    let mut content = Vec::new();
+    for _ in 0..5 {
+        content.extend_from_slice(&file.read_bytes(4).await.unwrap());
+    }
+    assert_eq!(content, b"0123456789abcdefghij");
+    // every window is downloaded exactly once, whether prefetched or not
+    assert_eq!(ctx.mock.calls(&download_path), 5);
+
+    // seeking drops the prefetched windows
+    file.seek(SeekFrom::Start(0)).await.unwrap();
+    file.read_bytes(4).await.unwrap();
+    file.read_bytes(4).await.unwrap();
+    file.seek(SeekFrom::Start(0)).await.unwrap();
+    assert_eq!(&file.read_bytes(4).await.unwrap()[..], b"0123");
+    assert_eq!(&file.read_bytes(4).await.unwrap()[..], b"4567");
+    assert_eq!(&file.read_bytes(4).await.unwrap()[..], b"89ab");
+}
+
+#[tokio::test]
+async fn get_synthesizes_livp_zip() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_livp(
+        "root",
+        "IMG_0001.livp",
+        &[("heic", b"heic-data"), ("mov", b"mov-data")],
+    );
+
+    let res = ctx.get("/IMG_0001.livp").await;
+    assert_eq!(res.status, StatusCode::OK);
+    let mut zip = zip::ZipArchive::new(Cursor::new(res.body.to_vec())).unwrap();
+    let mut names: Vec<_> = zip.file_names().map(|s| s.to_string()).collect();
+    names.sort();
+    assert_eq!(names, vec!["IMG_0001.heic", "IMG_0001.mov"]);
+    let mut content = String::new();
+    zip.by_name("IMG_0001.mov")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "mov-data");
+}
+
+#[tokio::test]
+async fn put_uploads_single_part() {
+    let ctx = TestContext::new().await;
+    let res = ctx.put("/new.txt", "some content").await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let file = ctx.mock.find("/new.txt").expect("file uploaded");
+    assert_eq!(file.content, b"some content");
+    assert_eq!(ctx.mock.calls("/adrive/v1.0/openFile/complete"), 1);
+}
+
+#[tokio::test]
+async fn put_uploads_multiple_chunks() {
+    let ctx = TestContext::with_fs(|fs| {
+        fs.set_upload_buffer_size(4);
+    })
+    .await;
+    let res = ctx.put("/chunked.bin", "0123456789").await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let file = ctx.mock.find("/chunked.bin").expect("file uploaded");
+    assert_eq!(file.content, b"0123456789");
+    assert_eq!(ctx.mock.calls("/oss/upload/upload-0000000000000001/1"), 1);
+    assert_eq!(ctx.mock.calls("/oss/upload/upload-0000000000000001/3"), 1);
+}
+
+#[tokio::test]
+async fn put_uploads_parts_concurrently() {
+    let ctx = TestContext::with_fs(|fs| {
+        fs.set_upload_buffer_size(4).set_upload_concurrency(3);
+    })
+    .await;
+    ctx.mock.set_upload_delay(Duration::from_millis(50));
+    let res = ctx.put("/parallel.bin", "0123456789abcdefghij").await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let file = ctx.mock.find("/parallel.bin").expect("file uploaded");
+    assert_eq!(file.content, b"0123456789abcdefghij");
+    assert_eq!(ctx.mock.calls_with_prefix("/oss/upload/"), 5);
+    assert_eq!(ctx.mock.max_uploads_in_flight(), 3);
+}
+
+#[tokio::test]
+async fn put_respects_upload_memory_limit() {
+    let ctx = TestContext::with_fs(|fs| {
+        fs.set_upload_buffer_size(4)
+            .set_upload_concurrency(3)
+            .set_upload_memory(UploadMemory::new(8));
+    })
+    .await;
+    ctx.mock.set_upload_delay(Duration::from_millis(50));
+    let res = ctx.put("/parallel.bin", "0123456789abcdefghij").await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let file = ctx.mock.find("/parallel.bin").expect("file uploaded");
+    assert_eq!(file.content, b"0123456789abcdefghij");
+    assert_eq!(ctx.mock.max_uploads_in_flight(), 2);
+}
+
+#[tokio::test]
+async fn put_spools_body_of_unknown_size() {
+    let spool = tempfile::tempdir().unwrap();
+    let spool_dir = spool.path().to_path_buf();
+    let ctx = TestContext::with_fs(move |fs| {
+        fs.set_upload_buffer_size(4)
+            .set_spool_dir(Some(spool_dir.clone()));
+    })
+    .await;
+
+    // chunked transfer encoding without a Content-Length
+    let (mut sender, body) = hyper::Body::channel();
+    let upload = tokio::spawn(async move {
+        sender.send_data("0123".into()).await.unwrap();
+        sender.send_data("456789ab".into()).await.unwrap();
+        sender.send_data("c".into()).await.unwrap();
+    });
+    let res = ctx.request_with_body("PUT", "/stream.bin", &[], body).await;
+    upload.await.unwrap();
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let file = ctx.mock.find("/stream.bin").expect("file uploaded");
+    assert_eq!(file.content, b"0123456789abc");
+    assert_eq!(ctx.mock.calls_with_prefix("/oss/upload/upload-"), 4);
+    assert_eq!(std::fs::read_dir(spool.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn put_rapid_uploads_spooled_body() {
+    let spool = tempfile::tempdir().unwrap();
+    let spool_dir = spool.path().to_path_buf();
+    let ctx = TestContext::with_fs(move |fs| {
+        fs.set_upload_buffer_size(4)
+            .set_spool_dir(Some(spool_dir.clone()));
+    })
+    .await;
+    ctx.mock.add_file("root", "a.bin", b"0123456789abc");
+
+    let (mut sender, body) = hyper::Body::channel();
+    let upload = tokio::spawn(async move {
+        sender.send_data("0123456".into()).await.unwrap();
+        sender.send_data("789abc".into()).await.unwrap();
+    });
+    let res = ctx.request_with_body("PUT", "/b.bin", &[], body).await;
+    upload.await.unwrap();
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let file = ctx.mock.find("/b.bin").expect("file created");
+    assert_eq!(file.content, b"0123456789abc");
+    assert_eq!(ctx.mock.calls_with_prefix("/oss/upload/"), 0);
+    assert_eq!(std::fs::read_dir(spool.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn put_rapid_uploads_known_content() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "a.txt", b"some known content");
+
+    let res = ctx.put("/b.txt", "some known content").await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let file = ctx.mock.find("/b.txt").expect("file created");
+    assert_eq!(file.content, b"some known content");
+    assert_eq!(ctx.mock.calls_with_prefix("/oss/upload/"), 0);
+    assert_eq!(ctx.mock.calls("/adrive/v1.0/openFile/complete"), 0);
+    let res = ctx.get("/b.txt").await;
+    assert_eq!(res.body, "some known content");
+}
+
+#[tokio::test]
+async fn put_rapid_uploads_with_client_checksum() {
+    let ctx = TestContext::with_fs(|fs| {
+        fs.set_upload_buffer_size(4);
+    })
+    .await;
+    ctx.mock.add_file("root", "a.bin", b"0123456789");
+
+    let res = ctx
+        .request(
+            "PUT",
+            "/b.bin",
+            &[(
+                "OC-Checksum",
+                "SHA1:87acec17cd9dcd20a716cc2cf67417b71c8a7016",
+            )],
+            "0123456789",
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let file = ctx.mock.find("/b.bin").expect("file created");
+    assert_eq!(file.content, b"0123456789");
+    assert_eq!(ctx.mock.calls_with_prefix("/oss/upload/"), 0);
+}
+
+#[tokio::test]
+async fn put_uploads_unknown_content_normally() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "a.txt", b"some known content");
+
+    let res = ctx.put("/b.txt", "some other content").await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let file = ctx.mock.find("/b.txt").expect("file uploaded");
+    assert_eq!(file.content, b"some other content");
+    assert_eq!(ctx.mock.calls_with_prefix("/oss/upload/"), 1);
+    assert_eq!(ctx.mock.calls("/adrive/v1.0/openFile/complete"), 1);
+}
+
+#[tokio::test]
+async fn put_replaces_existing_file() {
+    let ctx = TestContext::new().await;
+    let old_id = ctx.mock.add_file("root", "a.txt", b"old");
+    ctx.propfind("/").await;
+
+    let res = ctx.put("/a.txt", "new content").await;
+    assert!(res.status.is_success(), "{}", res.status);
+
+    let file = ctx.mock.find("/a.txt").unwrap();
+    assert_ne!(file.id, old_id);
+    assert_eq!(file.content, b"new content");
+    assert!(ctx.mock.get(&old_id).unwrap().trashed);
+    // directory listing must not be served from the stale cache
+    let res = ctx.propfind("/").await;
+    assert!(res.text().contains("/a.txt"));
+    let res = ctx.get("/a.txt").await;
+    assert_eq!(&res.body[..], b"new content");
+}
+
+#[tokio::test]
+async fn put_retries_expired_upload_url() {
+    let ctx = TestContext::with_fs(|fs| {
+        fs.set_upload_buffer_size(4);
+    })
+    .await;
+    ctx.mock.expire_upload_urls(1);
+    let res = ctx.put("/retry.bin", "0123456789").await;
+    assert_eq!(res.status, StatusCode::CREATED);
+
+    let file = ctx.mock.find("/retry.bin").expect("file uploaded");
+    assert_eq!(file.content, b"0123456789");
+    assert_eq!(ctx.mock.calls("/adrive/v1.0/openFile/getUploadUrl"), 1);
+}
+
+#[tokio::test]
+async fn put_ignores_macos_metadata_files() {
+    let ctx = TestContext::new().await;
+    let res = ctx.put("/._a.txt", "resource fork").await;
+    assert!(res.status.is_client_error(), "{}", res.status);
+    assert!(ctx.mock.find("/._a.txt").is_none());
+}
+
+#[tokio::test]
+async fn mkcol_creates_folder() {
+    let ctx = TestContext::new().await;
+    ctx.propfind("/").await;
+
+    let res = ctx.request("MKCOL", "/photos", &[], "").await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert!(ctx.mock.find("/photos").unwrap().is_folder);
+    assert!(ctx.propfind("/").await.text().contains("/photos/"));
+}
+
+#[tokio::test]
+async fn move_renames_and_invalidates_cache() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "a.txt", b"hello");
+    assert!(ctx.propfind("/").await.text().contains("/a.txt"));
+    assert!(ctx.fs.dir_cache.get("/").is_some());
+
+    let res = ctx
+        .request("MOVE", "/a.txt", &[("Destination", "/b.txt")], "")
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert!(ctx.mock.find("/b.txt").is_some());
+    assert!(ctx.fs.dir_cache.get("/").is_none());
+
+    let body = ctx.propfind("/").await.text();
+    assert!(body.contains("/b.txt"), "{}", body);
+    assert!(!body.contains("/a.txt"), "{}", body);
+}
+
+#[tokio::test]
+async fn move_between_directories() {
+    let ctx = TestContext::new().await;
+    let docs = ctx.mock.add_folder("root", "docs");
+    ctx.mock.add_file("root", "a.txt", b"hello");
+    ctx.propfind("/").await;
+    ctx.propfind("/docs/").await;
+
+    let res = ctx
+        .request("MOVE", "/a.txt", &[("Destination", "/docs/c.txt")], "")
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    let file = ctx.mock.find("/docs/c.txt").unwrap();
+    assert_eq!(file.parent_id, docs);
+
+    assert!(!ctx.propfind("/").await.text().contains("/a.txt"));
+    assert!(ctx.propfind("/docs/").await.text().contains("/docs/c.txt"));
+}
+
+#[tokio::test]
+async fn copy_invalidates_destination_cache() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_folder("root", "docs");
+    ctx.mock.add_file("root", "a.txt", b"hello");
+    ctx.propfind("/").await;
+    assert!(!ctx.propfind("/docs/").await.text().contains("a.txt"));
+
+    let res = ctx
+        .request("COPY", "/a.txt", &[("Destination", "/docs/a.txt")], "")
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(ctx.mock.find("/docs/a.txt").unwrap().content, b"hello");
+    assert!(ctx.mock.find("/a.txt").is_some());
+    assert!(ctx.propfind("/docs/").await.text().contains("/docs/a.txt"));
+}
+
+#[tokio::test]
+async fn delete_trashes_file() {
+    let ctx = TestContext::new().await;
+    let id = ctx.mock.add_file("root", "a.txt", b"hello");
+    ctx.propfind("/").await;
+
+    let res = ctx.request("DELETE", "/a.txt", &[], "").await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+    assert!(ctx.mock.get(&id).unwrap().trashed);
+    assert!(!ctx.propfind("/").await.text().contains("/a.txt"));
+}
+
+#[tokio::test]
+async fn delete_permanently_with_no_trash() {
+    let ctx = TestContext::with_fs(|fs| {
+        fs.set_no_trash(true);
+    })
+    .await;
+    let docs = ctx.mock.add_folder("root", "docs");
+    ctx.mock.add_file(&docs, "a.txt", b"hello");
+
+    let res = ctx.request("DELETE", "/docs/", &[], "").await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+    assert!(ctx.mock.get(&docs).is_none());
+}
+
+#[tokio::test]
+async fn read_only_rejects_writes() {
+    let ctx = TestContext::with_fs(|fs| {
+        fs.set_read_only(true);
+    })
+    .await;
+    ctx.mock.add_file("root", "a.txt", b"hello");
+
+    assert_eq!(
+        ctx.put("/b.txt", "data").await.status,
+        StatusCode::FORBIDDEN
+    );
+    let res = ctx.request("DELETE", "/a.txt", &[], "").await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert!(ctx.mock.find("/a.txt").is_some());
+}
+
+#[tokio::test]
+async fn refreshes_expired_access_token() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "a.txt", b"hello");
+    let refreshes = ctx.mock.calls("/oauth/access_token");
+
+    ctx.mock.expire_access_token();
+    let res = ctx.propfind("/").await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    assert!(res.text().contains("/a.txt"));
+    assert_eq!(ctx.mock.calls("/oauth/access_token"), refreshes + 1);
+}
+
+#[tokio::test]
+async fn put_resumes_interrupted_upload() {
+    let workdir = tempfile::tempdir().unwrap();
+    let sessions_dir = workdir.path().join("uploads");
+    let ctx = TestContext::with_fs(move |fs| {
+        // upload parts one at a time so that the interruption point is deterministic
+        fs.set_upload_buffer_size(4)
+            .set_upload_concurrency(1)
+            .set_upload_sessions_dir(Some(sessions_dir.clone()));
+    })
+    .await;
+
+    // client disconnects after sending the first two parts
+    let (mut sender, body) = hyper::Body::channel();
+    let upload = tokio::spawn(async move {
+        sender.send_data("0123".into()).await.unwrap();
+        sender.send_data("4567".into()).await.unwrap();
+        sender.abort();
+    });
+    let res = ctx
+        .request_with_body("PUT", "/big.bin", &[("Content-Length", "10")], body)
+        .await;
+    upload.await.unwrap();
+    assert!(!res.status.is_success(), "{}", res.status);
+    assert!(ctx.mock.find("/big.bin").is_none());
+    assert_eq!(ctx.mock.calls("/adrive/v1.0/openFile/create"), 1);
+
+    // the retried PUT after a restart only uploads the missing part
+    let ctx = ctx.restart().await;
+    let res = ctx.put("/big.bin", "0123456789").await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(ctx.mock.find("/big.bin").unwrap().content, b"0123456789");
+    assert_eq!(ctx.mock.calls("/adrive/v1.0/openFile/create"), 1);
+    assert_eq!(ctx.mock.calls("/adrive/v1.0/openFile/getUploadUrl"), 1);
+    assert_eq!(ctx.mock.calls("/oss/upload/upload-0000000000000001/1"), 1);
+    assert_eq!(ctx.mock.calls("/oss/upload/upload-0000000000000001/2"), 1);
+    assert_eq!(ctx.mock.calls("/oss/upload/upload-0000000000000001/3"), 1);
+    assert_eq!(
+        std::fs::read_dir(workdir.path().join("uploads"))
+            .unwrap()
+            .count(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn put_reuploads_changed_parts_when_resuming() {
+    let workdir = tempfile::tempdir().unwrap();
+    let sessions_dir = workdir.path().to_path_buf();
+    let ctx = TestContext::with_fs(move |fs| {
+        // upload parts one at a time so that the interruption point is deterministic
+        fs.set_upload_buffer_size(4)
+            .set_upload_concurrency(1)
+            .set_upload_sessions_dir(Some(sessions_dir.clone()));
+    })
+    .await;
+
+    let (mut sender, body) = hyper::Body::channel();
+    let upload = tokio::spawn(async move {
+        sender.send_data("0123".into()).await.unwrap();
+        sender.abort();
+    });
+    ctx.request_with_body("PUT", "/big.bin", &[("Content-Length", "10")], body)
+        .await;
+    upload.await.unwrap();
+
+    let res = ctx.put("/big.bin", "abcdefghij").await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(ctx.mock.find("/big.bin").unwrap().content, b"abcdefghij");
+    assert_eq!(ctx.mock.calls("/oss/upload/upload-0000000000000001/1"), 2);
+}
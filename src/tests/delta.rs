@@ -0,0 +1,56 @@
+use hyper::StatusCode;
+
+use super::TestContext;
+
+#[tokio::test]
+async fn delta_invalidates_changed_directories() {
+    let ctx = TestContext::new().await;
+    let docs = ctx.mock.add_folder("root", "docs");
+    let a = ctx.mock.add_file(&docs, "a.txt", b"a");
+    let other = ctx.mock.add_folder("root", "other");
+    ctx.mock.add_file(&other, "c.txt", b"c");
+    let cursor = ctx.fs.poll_changes(None).await.unwrap();
+
+    assert!(ctx.propfind("/docs/").await.text().contains("a.txt"));
+    assert!(ctx.propfind("/other/").await.text().contains("c.txt"));
+    let lists = ctx.mock.calls("/adrive/v1.0/openFile/list");
+
+    // changes made elsewhere are invisible until the change feed is polled
+    ctx.mock.rename(&a, "b.txt");
+    ctx.mock.add_file(&docs, "new.txt", b"new");
+    assert!(!ctx.propfind("/docs/").await.text().contains("b.txt"));
+
+    let cursor = ctx.fs.poll_changes(Some(cursor)).await.unwrap();
+    let text = ctx.propfind("/docs/").await.text();
+    assert!(
+        text.contains("b.txt") && text.contains("new.txt"),
+        "{}",
+        text
+    );
+    assert!(!text.contains("a.txt"), "{}", text);
+    // only the changed directory was listed again
+    ctx.propfind("/other/").await;
+    assert_eq!(ctx.mock.calls("/adrive/v1.0/openFile/list"), lists + 1);
+
+    // a renamed folder drops the listings below it
+    ctx.mock.rename(&docs, "papers");
+    ctx.fs.poll_changes(Some(cursor)).await.unwrap();
+    assert_eq!(ctx.propfind("/docs/").await.status, StatusCode::NOT_FOUND);
+    assert!(ctx.propfind("/papers/").await.text().contains("b.txt"));
+}
+
+#[tokio::test]
+async fn delta_failure_invalidates_everything() {
+    let ctx = TestContext::new().await;
+    let docs = ctx.mock.add_folder("root", "docs");
+    ctx.mock.add_file(&docs, "a.txt", b"a");
+    ctx.propfind("/docs/").await;
+    ctx.mock.add_file(&docs, "b.txt", b"b");
+
+    assert!(ctx
+        .fs
+        .poll_changes(Some("expired".to_string()))
+        .await
+        .is_err());
+    assert!(ctx.propfind("/docs/").await.text().contains("b.txt"));
+}
@@ -0,0 +1,90 @@
+use hyper::header::RETRY_AFTER;
+use hyper::StatusCode;
+
+use super::{call_with_headers, TestContext};
+use crate::webdav::AliyunDriveWebDav;
+
+const CREATE: &str = "/adrive/v1.0/openFile/create";
+
+fn service(ctx: &TestContext) -> AliyunDriveWebDav {
+    ctx.service().build()
+}
+
+#[tokio::test]
+async fn full_drive_is_insufficient_storage() {
+    let ctx = TestContext::new().await;
+    ctx.mock
+        .fail(CREATE, StatusCode::BAD_REQUEST, "QuotaExhausted.Drive");
+    let res = ctx.put("/a.txt", "hello").await;
+    assert_eq!(res.status, StatusCode::INSUFFICIENT_STORAGE);
+}
+
+#[tokio::test]
+async fn existing_folder_is_not_created_again() {
+    let ctx = TestContext::new().await;
+    ctx.mock
+        .fail(CREATE, StatusCode::CONFLICT, "AlreadyExist.Folder");
+    let mut svc = service(&ctx);
+    let res = call_with_headers(&mut svc, "MKCOL", "/new/", &[], "").await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn existing_destination_is_not_replaced() {
+    let ctx = TestContext::new().await;
+    let mut svc = service(&ctx);
+    ctx.mock.add_file("root", "a.txt", b"a");
+    ctx.mock.fail(
+        "/adrive/v1.0/openFile/update",
+        StatusCode::CONFLICT,
+        "AlreadyExist.File",
+    );
+    let headers = [("Destination", "/b.txt"), ("Overwrite", "F")];
+    let res = call_with_headers(&mut svc, "MOVE", "/a.txt", &headers, "").await;
+    assert_eq!(res.status, StatusCode::PRECONDITION_FAILED);
+    let res = call_with_headers(&mut svc, "MOVE", "/a.txt", &headers[..1], "").await;
+    assert_eq!(res.status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn forbidden_operations_are_forbidden() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "a.txt", b"a");
+    ctx.mock.fail(
+        "/adrive/v1.0/openFile/recyclebin/trash",
+        StatusCode::FORBIDDEN,
+        "ForbiddenFileInTheRecycleBin",
+    );
+    let res = ctx.request("DELETE", "/a.txt", &[], "").await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn missing_files_are_not_found() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "a.txt", b"a");
+    ctx.mock.fail(
+        "/adrive/v1.0/openFile/update",
+        StatusCode::NOT_FOUND,
+        "NotFound.File",
+    );
+    let res = ctx
+        .request("MOVE", "/a.txt", &[("Destination", "/b.txt")], "")
+        .await;
+    assert_eq!(res.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn throttled_drive_is_unavailable() {
+    let ctx = TestContext::new().await;
+    let mut svc = service(&ctx);
+    // more than the retries of a call
+    ctx.mock.add_file("root", "a.txt", b"a");
+    ctx.mock.throttle(10, Some(0));
+    let res = call_with_headers(&mut svc, "GET", "/a.txt", &[], "").await;
+    assert_eq!(res.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers[RETRY_AFTER], "0");
+
+    let res = call_with_headers(&mut svc, "GET", "/a.txt", &[], "").await;
+    assert_eq!(res.status, StatusCode::OK);
+}
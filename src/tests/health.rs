@@ -0,0 +1,47 @@
+use hyper::StatusCode;
+
+use super::{call, TestContext};
+use crate::webdav::AliyunDriveWebDav;
+
+fn service(ctx: &TestContext) -> AliyunDriveWebDav {
+    ctx.service().basic_auth().build()
+}
+
+#[tokio::test]
+async fn health_probes_skip_authentication() {
+    let ctx = TestContext::new().await;
+    let mut svc = service(&ctx);
+
+    let (status, body) = call(&mut svc, "GET", "/healthz", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "ok\n");
+    let (status, _) = call(&mut svc, "HEAD", "/readyz", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call(&mut svc, "PROPFIND", "/", None, "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn health_readiness_follows_token_refresh() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "a.txt", b"hello");
+    let mut svc = service(&ctx);
+    let creds = Some(("admin", "secret"));
+
+    ctx.mock.reject_refresh_token(true);
+    ctx.mock.expire_access_token();
+    let (status, _) = call(&mut svc, "GET", "/a.txt", creds, "").await;
+    assert_ne!(status, StatusCode::OK);
+    let (status, body) = call(&mut svc, "GET", "/readyz", None, "").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(body.contains("last token refresh failed"), "{}", body);
+    // the process itself is still alive
+    let (status, _) = call(&mut svc, "GET", "/healthz", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+
+    ctx.mock.reject_refresh_token(false);
+    let (status, _) = call(&mut svc, "GET", "/a.txt", creds, "").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call(&mut svc, "GET", "/readyz", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+}
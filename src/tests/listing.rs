@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use hyper::StatusCode;
+
+use super::TestContext;
+
+const LIST: &str = "/adrive/v1.0/openFile/list";
+
+#[tokio::test]
+async fn concurrent_listings_are_coalesced() {
+    let ctx = TestContext::new().await;
+    let a = ctx.mock.add_folder("root", "a");
+    for i in 0..10 {
+        ctx.mock.add_file(&a, &format!("{}.txt", i), b"x");
+    }
+    ctx.mock.set_list_delay(Duration::from_millis(100));
+
+    let responses = join_all((0..20).map(|_| ctx.propfind("/a/"))).await;
+    assert!(responses
+        .iter()
+        .all(|res| res.status == StatusCode::MULTI_STATUS));
+    // one listing of `/` and one of `/a`
+    assert_eq!(ctx.mock.calls(LIST), 2);
+
+    let paths: Vec<String> = (0..10).map(|i| format!("/a/{}.txt", i)).collect();
+    let responses = join_all(paths.iter().map(|path| ctx.get(path))).await;
+    assert!(responses.iter().all(|res| res.text() == "x"));
+    assert_eq!(ctx.mock.calls(LIST), 2);
+}
+
+#[tokio::test]
+async fn coalesced_listing_errors_are_shared() {
+    let ctx = TestContext::new().await;
+    let a = ctx.mock.add_folder("root", "a");
+    ctx.mock.add_file(&a, "b.txt", b"b");
+    assert_eq!(ctx.propfind("/a/").await.status, StatusCode::MULTI_STATUS);
+    let lists = ctx.mock.calls(LIST);
+
+    ctx.mock.trash(&a);
+    ctx.fs.invalidate_cache("/a", false).await;
+    ctx.mock.set_list_delay(Duration::from_millis(100));
+    let responses = join_all((0..10).map(|_| ctx.get("/a/b.txt"))).await;
+    assert!(responses
+        .iter()
+        .all(|res| res.status == StatusCode::NOT_FOUND));
+    assert_eq!(ctx.mock.calls(LIST), lists + 1);
+}
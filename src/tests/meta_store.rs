@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+use super::TestContext;
+use crate::meta_store::MetadataStore;
+
+async fn context(dir: PathBuf) -> TestContext {
+    TestContext::with_fs(move |fs| {
+        let store = MetadataStore::open(&dir, fs.drive.drive_id().unwrap()).unwrap();
+        fs.dir_cache.set_store(store).unwrap();
+    })
+    .await
+}
+
+#[tokio::test]
+async fn meta_store_keeps_listings_across_restarts() {
+    let dir = tempfile::tempdir().unwrap();
+    let ctx = context(dir.path().to_path_buf()).await;
+    let docs = ctx.mock.add_folder("root", "docs");
+    ctx.mock.add_file(&docs, "a.txt", b"a");
+    assert!(ctx.propfind("/docs/").await.text().contains("a.txt"));
+    let lists = ctx_lists(&ctx);
+
+    let restarted = ctx.restart().await;
+    drop(ctx);
+    assert!(restarted.propfind("/docs/").await.text().contains("a.txt"));
+    assert_eq!(ctx_lists(&restarted), lists);
+
+    // invalidated listings are dropped from the store as well
+    restarted.put("/docs/b.txt", "b").await;
+    let lists = ctx_lists(&restarted);
+    let restarted = restarted.restart().await;
+    assert!(restarted.propfind("/docs/").await.text().contains("b.txt"));
+    assert_eq!(ctx_lists(&restarted), lists + 1);
+}
+
+#[tokio::test]
+async fn meta_store_resumes_change_feed() {
+    let dir = tempfile::tempdir().unwrap();
+    let ctx = context(dir.path().to_path_buf()).await;
+    let docs = ctx.mock.add_folder("root", "docs");
+    ctx.mock.add_file(&docs, "a.txt", b"a");
+    assert_eq!(ctx.fs.dir_cache.saved_cursor(), None);
+    let cursor = ctx.fs.poll_changes(None).await.unwrap();
+    ctx.fs.dir_cache.save_cursor(&cursor).await;
+    ctx.propfind("/docs/").await;
+
+    // changed while the server was down
+    ctx.mock.add_file(&docs, "b.txt", b"b");
+    let restarted = ctx.restart().await;
+    assert!(!restarted.propfind("/docs/").await.text().contains("b.txt"));
+    let cursor = restarted.fs.dir_cache.saved_cursor();
+    assert!(cursor.is_some());
+    restarted.fs.poll_changes(cursor).await.unwrap();
+    assert!(restarted.propfind("/docs/").await.text().contains("b.txt"));
+}
+
+fn ctx_lists(ctx: &TestContext) -> usize {
+    ctx.mock.calls("/adrive/v1.0/openFile/list")
+}
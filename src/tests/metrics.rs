@@ -0,0 +1,88 @@
+use hyper::StatusCode;
+
+use super::{call, TestContext};
+use crate::metrics::metrics;
+use crate::webdav::AliyunDriveWebDav;
+
+fn service(ctx: &TestContext, metrics: bool) -> AliyunDriveWebDav {
+    ctx.service()
+        .options(|options| options.metrics = metrics)
+        .build()
+}
+
+#[tokio::test]
+async fn metrics_are_served_when_enabled() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "metrics", b"a file");
+    let mut svc = service(&ctx, true);
+
+    let (status, _) = call(&mut svc, "PROPFIND", "/", None, "").await;
+    assert_eq!(status, StatusCode::MULTI_STATUS);
+    call(&mut svc, "MADE-UP", "/", None, "").await;
+    let (status, body) = call(&mut svc, "GET", "/metrics", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        body.contains(r#"aliyundrive_webdav_requests_total{method="PROPFIND",status="207"}"#),
+        "{}",
+        body
+    );
+    assert!(
+        body.contains("aliyundrive_webdav_upstream_request_duration_seconds_bucket"),
+        "{}",
+        body
+    );
+    assert!(body.contains("aliyundrive_webdav_dir_cache_misses_total"));
+    // arbitrary methods don't add label values
+    assert!(body.contains(r#"method="OTHER""#), "{}", body);
+    assert!(!body.contains("MADE-UP"), "{}", body);
+
+    let mut svc = service(&ctx, false);
+    let (status, body) = call(&mut svc, "GET", "/metrics", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "a file");
+}
+
+#[tokio::test]
+async fn metrics_require_authentication() {
+    let ctx = TestContext::new().await;
+    let mut svc = ctx
+        .service()
+        .basic_auth()
+        .options(|options| options.metrics = true)
+        .build();
+
+    let (status, _) = call(&mut svc, "GET", "/metrics", None, "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, body) = call(&mut svc, "GET", "/metrics", Some(("admin", "secret")), "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        body.contains("aliyundrive_webdav_requests_total"),
+        "{}",
+        body
+    );
+}
+
+#[tokio::test]
+async fn metrics_count_requests_and_transfers() {
+    let ctx = TestContext::new().await;
+    let mut svc = service(&ctx, false);
+    let m = metrics();
+    let puts = m.requests.with_label_values(&["PUT", "201"]);
+    let (created, uploaded, downloaded) =
+        (puts.get(), m.uploaded_bytes.get(), m.downloaded_bytes.get());
+    let (hits, misses) = (m.cache_hits.get(), m.cache_misses.get());
+
+    let (status, _) = call(&mut svc, "PUT", "/a.txt", None, "hello").await;
+    assert_eq!(status, StatusCode::CREATED);
+    call(&mut svc, "PROPFIND", "/", None, "").await;
+    call(&mut svc, "PROPFIND", "/", None, "").await;
+    let (_, body) = call(&mut svc, "GET", "/a.txt", None, "").await;
+    assert_eq!(body, "hello");
+
+    // other tests run concurrently, so only check the counters grew
+    assert!(puts.get() > created);
+    assert!(m.uploaded_bytes.get() >= uploaded + 5);
+    assert!(m.downloaded_bytes.get() >= downloaded + 5);
+    assert!(m.cache_hits.get() > hits);
+    assert!(m.cache_misses.get() > misses);
+}
@@ -0,0 +1,906 @@
+//! An in-process fake of the AliyunDrive OpenAPI and OSS endpoints.
+//!
+//! Only the parts of the API used by `AliyunDrive` are implemented, with just
+//! enough fidelity to exercise the WebDAV file system end to end.
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use ::time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use md5::Md5;
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+
+#[derive(Debug, Clone)]
+pub struct MockFile {
+    pub id: String,
+    pub parent_id: String,
+    pub name: String,
+    pub is_folder: bool,
+    pub content: Vec<u8>,
+    /// Streams of a `.livp` live photo, keyed by stream type (`heic`, `mov`)
+    pub streams: BTreeMap<String, Vec<u8>>,
+    pub created_at: OffsetDateTime,
+    pub updated_at: OffsetDateTime,
+    pub trashed: bool,
+}
+
+impl MockFile {
+    fn extension(&self) -> String {
+        self.name
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn content_hash(&self) -> String {
+        format!("{:X}", Sha1::digest(&self.content))
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "drive_id": "1",
+            "file_id": self.id,
+            "parent_file_id": self.parent_id,
+            "name": self.name,
+            "type": if self.is_folder { "folder" } else { "file" },
+            "category": if self.is_folder { Value::Null } else { json!("others") },
+            "file_extension": self.extension(),
+            "created_at": self.created_at.format(&Rfc3339).unwrap(),
+            "updated_at": self.updated_at.format(&Rfc3339).unwrap(),
+            "size": self.content.len(),
+            "content_hash": if self.is_folder { Value::Null } else { json!(self.content_hash()) },
+            "content_hash_name": "sha1",
+        })
+    }
+}
+
+#[derive(Debug)]
+struct PendingUpload {
+    file: MockFile,
+    part_count: u64,
+    parts: BTreeMap<u64, Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub struct MockState {
+    addr: SocketAddr,
+    files: HashMap<String, MockFile>,
+    uploads: HashMap<String, PendingUpload>,
+    next_id: u64,
+    calls: HashMap<String, usize>,
+    access_token: String,
+    token_generation: u64,
+    reject_refresh_token: bool,
+    searches: Vec<String>,
+    /// Items of the delta feed, a cursor is an index into it
+    deltas: Vec<Value>,
+    expire_upload_urls: usize,
+    upload_delay: Option<Duration>,
+    list_delay: Option<Duration>,
+    /// Number of API calls still to throttle and the `Retry-After` header sent with them
+    throttle: (usize, Option<u64>),
+    /// Errors every call of an API path fails with
+    failures: HashMap<String, (StatusCode, String)>,
+    uploads_in_flight: usize,
+    max_uploads_in_flight: usize,
+    pub total_size: u64,
+}
+
+impl MockState {
+    fn next_id(&mut self) -> String {
+        self.next_id += 1;
+        format!("{:016x}", self.next_id)
+    }
+
+    fn children(&self, parent_id: &str) -> Vec<&MockFile> {
+        let mut children: Vec<_> = self
+            .files
+            .values()
+            .filter(|f| f.parent_id == parent_id && !f.trashed)
+            .collect();
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+        children
+    }
+
+    fn find_child(&self, parent_id: &str, name: &str) -> Option<&MockFile> {
+        self.children(parent_id)
+            .into_iter()
+            .find(|f| f.name == name)
+    }
+
+    fn find_by_path(&self, path: &str) -> Option<&MockFile> {
+        let mut parent_id = "root".to_string();
+        let mut found = None;
+        for part in path.split('/').filter(|p| !p.is_empty()) {
+            let file = self.find_child(&parent_id, part)?;
+            parent_id = file.id.clone();
+            found = Some(file);
+        }
+        found
+    }
+
+    fn get(&self, file_id: &str) -> Option<&MockFile> {
+        self.files.get(file_id).filter(|f| !f.trashed)
+    }
+
+    fn insert(&mut self, parent_id: &str, name: &str, is_folder: bool, content: Vec<u8>) -> String {
+        let id = self.next_id();
+        let now = OffsetDateTime::now_utc();
+        self.files.insert(
+            id.clone(),
+            MockFile {
+                id: id.clone(),
+                parent_id: parent_id.to_string(),
+                name: name.to_string(),
+                is_folder,
+                content,
+                streams: BTreeMap::new(),
+                created_at: now,
+                updated_at: now,
+                trashed: false,
+            },
+        );
+        self.record_delta("create", &id);
+        id
+    }
+
+    fn record_delta(&mut self, op: &str, file_id: &str) {
+        let file = match self.files.get(file_id) {
+            Some(file) if op != "delete" => json!({ "parent_file_id": file.parent_id }),
+            _ => Value::Null,
+        };
+        self.deltas
+            .push(json!({ "op": op, "file_id": file_id, "file": file }));
+    }
+
+    fn remove_tree(&mut self, file_id: &str) {
+        let children: Vec<String> = self
+            .files
+            .values()
+            .filter(|f| f.parent_id == file_id)
+            .map(|f| f.id.clone())
+            .collect();
+        for child in children {
+            self.remove_tree(&child);
+        }
+        self.files.remove(file_id);
+    }
+
+    fn copy_tree(&mut self, file_id: &str, to_parent_id: &str) -> String {
+        let mut file = self.files[file_id].clone();
+        let id = self.next_id();
+        file.id = id.clone();
+        file.parent_id = to_parent_id.to_string();
+        self.files.insert(id.clone(), file);
+        let children: Vec<String> = self
+            .children(file_id)
+            .into_iter()
+            .map(|f| f.id.clone())
+            .collect();
+        for child in children {
+            self.copy_tree(&child, &id);
+        }
+        id
+    }
+
+    fn upload_part_info(&self, upload_id: &str, part_count: u64) -> Value {
+        let parts: Vec<Value> = (1..=part_count)
+            .map(|part_number| {
+                json!({
+                    "part_number": part_number,
+                    "upload_url": format!("http://{}/oss/upload/{}/{}", self.addr, upload_id, part_number),
+                })
+            })
+            .collect();
+        Value::Array(parts)
+    }
+}
+
+/// A running mock server, shut down when the test runtime goes away.
+#[derive(Clone)]
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockServer {
+    pub async fn start() -> Self {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(MockState {
+            addr,
+            files: HashMap::new(),
+            uploads: HashMap::new(),
+            next_id: 0,
+            calls: HashMap::new(),
+            access_token: "mock-access-token-0".to_string(),
+            token_generation: 0,
+            reject_refresh_token: false,
+            searches: Vec::new(),
+            deltas: Vec::new(),
+            expire_upload_urls: 0,
+            upload_delay: None,
+            list_delay: None,
+            throttle: (0, None),
+            failures: HashMap::new(),
+            uploads_in_flight: 0,
+            max_uploads_in_flight: 0,
+            total_size: 1024 * 1024 * 1024,
+        }));
+        let service_state = state.clone();
+        let make_svc = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(handle(state, req).await) }
+                }))
+            }
+        });
+        let server = hyper::Server::from_tcp(listener).unwrap().serve(make_svc);
+        tokio::spawn(server);
+        Self { addr, state }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+
+    pub fn add_folder(&self, parent_id: &str, name: &str) -> String {
+        self.state().insert(parent_id, name, true, Vec::new())
+    }
+
+    pub fn add_file(&self, parent_id: &str, name: &str, content: &[u8]) -> String {
+        self.state()
+            .insert(parent_id, name, false, content.to_vec())
+    }
+
+    /// Add a `.livp` live photo made of the given streams
+    pub fn add_livp(&self, parent_id: &str, name: &str, streams: &[(&str, &[u8])]) -> String {
+        let mut state = self.state();
+        let id = state.insert(parent_id, name, false, Vec::new());
+        let file = state.files.get_mut(&id).unwrap();
+        for (typ, content) in streams {
+            file.streams.insert(typ.to_string(), content.to_vec());
+        }
+        file.content = file.streams.values().flatten().copied().collect();
+        id
+    }
+
+    pub fn find(&self, path: &str) -> Option<MockFile> {
+        self.state().find_by_path(path).cloned()
+    }
+
+    pub fn get(&self, file_id: &str) -> Option<MockFile> {
+        self.state().files.get(file_id).cloned()
+    }
+
+    /// Number of requests received on `path`, e.g. `/adrive/v1.0/openFile/list`
+    pub fn calls(&self, path: &str) -> usize {
+        self.state().calls.get(path).copied().unwrap_or_default()
+    }
+
+    /// Number of requests received on paths starting with `prefix`
+    pub fn calls_with_prefix(&self, prefix: &str) -> usize {
+        self.state()
+            .calls
+            .iter()
+            .filter(|(path, _)| path.starts_with(prefix))
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// Reject the next `n` OSS part uploads as if their upload urls had expired
+    pub fn expire_upload_urls(&self, n: usize) {
+        self.state().expire_upload_urls = n;
+    }
+
+    /// Slow down every OSS part upload by `delay`
+    pub fn set_upload_delay(&self, delay: Duration) {
+        self.state().upload_delay = Some(delay);
+    }
+
+    /// Slow down every directory listing by `delay`
+    pub fn set_list_delay(&self, delay: Duration) {
+        self.state().list_delay = Some(delay);
+    }
+
+    /// Answer the next `n` API calls with 429, with a `Retry-After` of `retry_after` seconds if any
+    pub fn throttle(&self, n: usize, retry_after: Option<u64>) {
+        self.state().throttle = (n, retry_after);
+    }
+
+    /// Fail every call of the API at `path` with `status` and the error code `code`
+    pub fn fail(&self, path: &str, status: StatusCode, code: &str) {
+        self.state()
+            .failures
+            .insert(path.to_string(), (status, code.to_string()));
+    }
+
+    /// Highest number of OSS part uploads seen running at the same time
+    pub fn max_uploads_in_flight(&self) -> usize {
+        self.state().max_uploads_in_flight
+    }
+
+    /// Invalidate the current access token, the next API call will get a 401
+    pub fn expire_access_token(&self) {
+        self.state().access_token = "expired".to_string();
+    }
+
+    /// Move a file to the recycle bin
+    pub fn trash(&self, file_id: &str) {
+        let mut state = self.state();
+        state.files.get_mut(file_id).unwrap().trashed = true;
+        state.record_delta("trash", file_id);
+    }
+
+    /// Rename a file as if it was renamed in another client
+    pub fn rename(&self, file_id: &str, name: &str) {
+        let mut state = self.state();
+        state.files.get_mut(file_id).unwrap().name = name.to_string();
+        state.record_delta("update", file_id);
+    }
+
+    /// Queries of the search API calls so far
+    pub fn searches(&self) -> Vec<String> {
+        self.state().searches.clone()
+    }
+
+    /// Fail token refreshes as if the refresh token had been revoked
+    pub fn reject_refresh_token(&self, reject: bool) {
+        self.state().reject_refresh_token = reject;
+    }
+}
+
+/// Proof code v1: 8 bytes of the content at an offset derived from the access token
+fn rapid_proof_code(access_token: &str, content: &[u8]) -> String {
+    let hash = format!("{:x}", Md5::digest(access_token.as_bytes()));
+    let n = u64::from_str_radix(&hash[..16], 16).unwrap();
+    let size = content.len() as u64;
+    let offset = (n % size) as usize;
+    let end = (offset + 8).min(content.len());
+    STANDARD.encode(&content[offset..end])
+}
+
+fn json_response(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, code: &str, message: &str) -> Response<Body> {
+    json_response(status, json!({ "code": code, "message": message }))
+}
+
+fn not_found() -> Response<Body> {
+    error_response(
+        StatusCode::NOT_FOUND,
+        "NotFound.File",
+        "The resource file cannot be found. file not exist",
+    )
+}
+
+async fn handle(state: Arc<Mutex<MockState>>, req: Request<Body>) -> Response<Body> {
+    let (parts, body) = req.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+    let path = parts.uri.path().to_string();
+    if parts.method == Method::PUT && path.starts_with("/oss/upload/") {
+        let delay = {
+            let mut state = state.lock().unwrap();
+            state.uploads_in_flight += 1;
+            state.max_uploads_in_flight = state.max_uploads_in_flight.max(state.uploads_in_flight);
+            state.upload_delay
+        };
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+        state.lock().unwrap().uploads_in_flight -= 1;
+    }
+    if path == "/adrive/v1.0/openFile/list" {
+        let delay = state.lock().unwrap().list_delay;
+        if let Some(delay) = delay {
+            tokio::time::sleep(delay).await;
+        }
+    }
+    let mut state = state.lock().unwrap();
+    *state.calls.entry(path.clone()).or_default() += 1;
+
+    if let Some(rest) = path.strip_prefix("/oss/") {
+        return handle_oss(&mut state, &parts.method, rest, &parts.headers, body);
+    }
+    if parts.method != Method::POST {
+        return error_response(StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed", "");
+    }
+    let req: Value = if body.is_empty() {
+        json!({})
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(req) => req,
+            Err(_) => return error_response(StatusCode::BAD_REQUEST, "BadRequest", "bad json"),
+        }
+    };
+    if path == "/oauth/access_token" {
+        if state.reject_refresh_token {
+            return error_response(
+                StatusCode::BAD_REQUEST,
+                "InvalidParameter.RefreshToken",
+                "The input parameter refresh_token is not valid.",
+            );
+        }
+        state.token_generation += 1;
+        state.access_token = format!("mock-access-token-{}", state.token_generation);
+        return json_response(
+            StatusCode::OK,
+            json!({
+                "access_token": state.access_token,
+                "refresh_token": format!("mock.refresh.token{}", state.token_generation),
+                "expires_in": 7200,
+                "token_type": "Bearer",
+            }),
+        );
+    }
+
+    let authorized = parts
+        .headers
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v == format!("Bearer {}", state.access_token))
+        .unwrap_or(false);
+    if !authorized {
+        return error_response(
+            StatusCode::UNAUTHORIZED,
+            "AccessTokenInvalid",
+            "AccessToken is invalid",
+        );
+    }
+    if state.throttle.0 > 0 {
+        state.throttle.0 -= 1;
+        let mut res = error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            "TooManyRequests",
+            "Request was denied due to flow control.",
+        );
+        if let Some(retry_after) = state.throttle.1 {
+            res.headers_mut()
+                .insert("Retry-After", retry_after.to_string().parse().unwrap());
+        }
+        return res;
+    }
+    if let Some((status, code)) = state.failures.get(&path) {
+        return error_response(*status, code, "injected failure");
+    }
+    handle_api(&mut state, &path, &req)
+}
+
+fn handle_api(state: &mut MockState, path: &str, req: &Value) -> Response<Body> {
+    let str_field = |name: &str| req[name].as_str().unwrap_or_default().to_string();
+    match path {
+        "/adrive/v1.0/user/getDriveInfo" => json_response(
+            StatusCode::OK,
+            json!({ "default_drive_id": "1", "resource_drive_id": "2" }),
+        ),
+        "/adrive/v1.0/user/getSpaceInfo" => {
+            let used: usize = state
+                .files
+                .values()
+                .filter(|f| !f.trashed)
+                .map(|f| f.content.len())
+                .sum();
+            json_response(
+                StatusCode::OK,
+                json!({
+                    "personal_space_info": {
+                        "used_size": used,
+                        "total_size": state.total_size,
+                    }
+                }),
+            )
+        }
+        "/adrive/v1.0/openFile/get" => match state.get(&str_field("file_id")) {
+            Some(file) => {
+                let mut res = file.to_json();
+                let streams_info: serde_json::Map<String, Value> = file
+                    .streams
+                    .iter()
+                    .map(|(typ, content)| (typ.clone(), json!({ "size": content.len() })))
+                    .collect();
+                res["streams_info"] = Value::Object(streams_info);
+                json_response(StatusCode::OK, res)
+            }
+            None => not_found(),
+        },
+        "/adrive/v1.0/openFile/list" => {
+            let parent_id = str_field("parent_file_id");
+            if parent_id != "root" && state.get(&parent_id).is_none() {
+                return not_found();
+            }
+            let limit = req["limit"].as_u64().unwrap_or(100) as usize;
+            let offset: usize = req["marker"]
+                .as_str()
+                .and_then(|m| m.parse().ok())
+                .unwrap_or_default();
+            let children = state.children(&parent_id);
+            let items: Vec<Value> = children
+                .iter()
+                .skip(offset)
+                .take(limit)
+                .map(|f| f.to_json())
+                .collect();
+            let next_marker = if offset + limit < children.len() {
+                (offset + limit).to_string()
+            } else {
+                String::new()
+            };
+            json_response(
+                StatusCode::OK,
+                json!({ "items": items, "next_marker": next_marker }),
+            )
+        }
+        "/adrive/v1.0/openFile/getDownloadUrl" => {
+            let Some(file) = state.get(&str_field("file_id")) else {
+                return not_found();
+            };
+            let expires = OffsetDateTime::now_utc().unix_timestamp() + 14400;
+            let (url, streams_url) = if file.streams.is_empty() {
+                let url = format!(
+                    "http://{}/oss/download/{}?x-oss-expires={}",
+                    state.addr, file.id, expires
+                );
+                (url, json!({}))
+            } else {
+                let streams: serde_json::Map<String, Value> = file
+                    .streams
+                    .keys()
+                    .map(|typ| {
+                        let url = format!("http://{}/oss/stream/{}/{}", state.addr, file.id, typ);
+                        (typ.clone(), json!(url))
+                    })
+                    .collect();
+                (String::new(), Value::Object(streams))
+            };
+            json_response(
+                StatusCode::OK,
+                json!({
+                    "url": url,
+                    "streams_url": streams_url,
+                    "expiration": "",
+                    "method": "GET",
+                }),
+            )
+        }
+        "/adrive/v1.0/openFile/recyclebin/trash" => {
+            let file_id = str_field("file_id");
+            match state.files.get_mut(&file_id) {
+                Some(file) if !file.trashed => {
+                    file.trashed = true;
+                    json_response(StatusCode::ACCEPTED, json!({ "file_id": file_id }))
+                }
+                _ => not_found(),
+            }
+        }
+        "/adrive/v1.0/openFile/recyclebin/list" => {
+            let mut trashed: Vec<&MockFile> = state.files.values().filter(|f| f.trashed).collect();
+            trashed.sort_by(|a, b| a.id.cmp(&b.id));
+            let items: Vec<Value> = trashed.iter().map(|f| f.to_json()).collect();
+            json_response(StatusCode::OK, json!({ "items": items, "next_marker": "" }))
+        }
+        "/adrive/v1.0/openFile/recyclebin/restore" => {
+            let file_id = str_field("file_id");
+            match state.files.get_mut(&file_id) {
+                Some(file) if file.trashed => {
+                    file.trashed = false;
+                    json_response(StatusCode::OK, json!({ "file_id": file_id }))
+                }
+                _ => not_found(),
+            }
+        }
+        "/adrive/v1.0/openFile/get_last_cursor" => json_response(
+            StatusCode::OK,
+            json!({ "cursor": state.deltas.len().to_string() }),
+        ),
+        "/adrive/v1.0/openFile/list_delta" => {
+            let Ok(cursor) = str_field("cursor").parse::<usize>() else {
+                return error_response(StatusCode::BAD_REQUEST, "InvalidParameter", "bad cursor");
+            };
+            let end = state.deltas.len().min(cursor + 100);
+            json_response(
+                StatusCode::OK,
+                json!({
+                    "items": state.deltas[cursor.min(end)..end],
+                    "cursor": end.to_string(),
+                    "has_more": end < state.deltas.len(),
+                }),
+            )
+        }
+        "/adrive/v1.0/openFile/search" => {
+            // only the name conditions are evaluated, a match is a substring
+            let query = str_field("query");
+            state.searches.push(query.clone());
+            let condition = |prefix: &str| {
+                query
+                    .split_once(prefix)
+                    .and_then(|(_, rest)| rest.split('"').next())
+                    .map(str::to_string)
+            };
+            let (contains, equals) = (condition("name match \""), condition("name = \""));
+            let mut found: Vec<&MockFile> = state
+                .files
+                .values()
+                .filter(|f| f.id != "root" && !f.trashed)
+                .filter(|f| {
+                    contains
+                        .as_ref()
+                        .is_none_or(|x| f.name.contains(x.as_str()))
+                })
+                .filter(|f| equals.as_ref().is_none_or(|x| &f.name == x))
+                .collect();
+            found.sort_by(|a, b| a.id.cmp(&b.id));
+            let items: Vec<Value> = found.iter().map(|f| f.to_json()).collect();
+            json_response(StatusCode::OK, json!({ "items": items, "next_marker": "" }))
+        }
+        "/adrive/v1.0/openFile/delete" => {
+            let file_id = str_field("file_id");
+            if !state.files.contains_key(&file_id) {
+                return not_found();
+            }
+            state.remove_tree(&file_id);
+            json_response(StatusCode::OK, json!({ "file_id": file_id }))
+        }
+        "/adrive/v1.0/openFile/create" => {
+            let parent_id = str_field("parent_file_id");
+            let name = str_field("name");
+            if parent_id != "root" && state.get(&parent_id).is_none() {
+                return not_found();
+            }
+            if let Some(existing) = state.find_child(&parent_id, &name) {
+                return json_response(
+                    StatusCode::OK,
+                    json!({
+                        "file_id": existing.id,
+                        "file_name": existing.name,
+                        "parent_file_id": parent_id,
+                        "exist": true,
+                    }),
+                );
+            }
+            if str_field("type") == "folder" {
+                let id = state.insert(&parent_id, &name, true, Vec::new());
+                return json_response(
+                    StatusCode::CREATED,
+                    json!({ "file_id": id, "file_name": name, "parent_file_id": parent_id }),
+                );
+            }
+            if str_field("content_hash_name") == "sha1" {
+                let size = req["size"].as_u64().unwrap_or_default();
+                let content_hash = str_field("content_hash");
+                let proof_code = str_field("proof_code");
+                let same = state
+                    .files
+                    .values()
+                    .find(|f| {
+                        !f.is_folder
+                            && f.content.len() as u64 == size
+                            && f.content_hash() == content_hash
+                    })
+                    .map(|f| f.content.clone());
+                if let Some(content) = same {
+                    if proof_code != rapid_proof_code(&state.access_token, &content) {
+                        return json_response(
+                            StatusCode::BAD_REQUEST,
+                            json!({ "code": "InvalidParameter.ProofCode", "message": "proof code mismatch" }),
+                        );
+                    }
+                    let id = state.insert(&parent_id, &name, false, content);
+                    return json_response(
+                        StatusCode::CREATED,
+                        json!({
+                            "file_id": id,
+                            "file_name": name,
+                            "parent_file_id": parent_id,
+                            "rapid_upload": true,
+                        }),
+                    );
+                }
+            }
+            let part_count = req["part_info_list"]
+                .as_array()
+                .map(|parts| parts.len() as u64)
+                .unwrap_or_default();
+            let id = state.next_id();
+            let upload_id = format!("upload-{}", id);
+            let now = OffsetDateTime::now_utc();
+            let file = MockFile {
+                id: id.clone(),
+                parent_id: parent_id.clone(),
+                name: name.clone(),
+                is_folder: false,
+                content: Vec::new(),
+                streams: BTreeMap::new(),
+                created_at: now,
+                updated_at: now,
+                trashed: false,
+            };
+            state.uploads.insert(
+                upload_id.clone(),
+                PendingUpload {
+                    file,
+                    part_count,
+                    parts: BTreeMap::new(),
+                },
+            );
+            json_response(
+                StatusCode::CREATED,
+                json!({
+                    "file_id": id,
+                    "file_name": name,
+                    "parent_file_id": parent_id,
+                    "upload_id": upload_id,
+                    "part_info_list": state.upload_part_info(&upload_id, part_count),
+                }),
+            )
+        }
+        "/adrive/v1.0/openFile/getUploadUrl" => {
+            let upload_id = str_field("upload_id");
+            let Some(upload) = state.uploads.get(&upload_id) else {
+                return not_found();
+            };
+            let part_count = upload.part_count;
+            json_response(
+                StatusCode::OK,
+                json!({
+                    "file_id": str_field("file_id"),
+                    "upload_id": upload_id,
+                    "file_name": upload.file.name,
+                    "part_info_list": state.upload_part_info(&upload_id, part_count),
+                }),
+            )
+        }
+        "/adrive/v1.0/openFile/complete" => {
+            let upload_id = str_field("upload_id");
+            let Some(upload) = state.uploads.remove(&upload_id) else {
+                return not_found();
+            };
+            let mut file = upload.file;
+            if upload.parts.len() as u64 != upload.part_count {
+                return error_response(
+                    StatusCode::BAD_REQUEST,
+                    "InvalidParameter.PartNumber",
+                    "not all parts have been uploaded",
+                );
+            }
+            file.content = upload.parts.into_values().flatten().collect();
+            let res = file.to_json();
+            state.files.insert(file.id.clone(), file);
+            json_response(StatusCode::OK, res)
+        }
+        "/adrive/v1.0/openFile/update" => {
+            let name = str_field("name");
+            match state.files.get_mut(&str_field("file_id")) {
+                Some(file) if !file.trashed => {
+                    file.name = name;
+                    file.updated_at = OffsetDateTime::now_utc();
+                    let res = file.to_json();
+                    json_response(StatusCode::OK, res)
+                }
+                _ => not_found(),
+            }
+        }
+        "/adrive/v1.0/openFile/move" => {
+            let to_parent_id = str_field("to_parent_file_id");
+            let new_name = req["new_name"].as_str().map(|s| s.to_string());
+            match state.files.get_mut(&str_field("file_id")) {
+                Some(file) if !file.trashed => {
+                    file.parent_id = to_parent_id;
+                    if let Some(new_name) = new_name {
+                        file.name = new_name;
+                    }
+                    let res = json!({ "file_id": file.id });
+                    json_response(StatusCode::OK, res)
+                }
+                _ => not_found(),
+            }
+        }
+        "/adrive/v1.0/openFile/copy" => {
+            let file_id = str_field("file_id");
+            if state.get(&file_id).is_none() {
+                return not_found();
+            }
+            let id = state.copy_tree(&file_id, &str_field("to_parent_file_id"));
+            json_response(StatusCode::CREATED, json!({ "file_id": id }))
+        }
+        _ => error_response(StatusCode::NOT_FOUND, "NotFound", "unknown api"),
+    }
+}
+
+fn handle_oss(
+    state: &mut MockState,
+    method: &Method,
+    path: &str,
+    headers: &hyper::HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let parts: Vec<&str> = path.split('/').collect();
+    match (method, parts.as_slice()) {
+        (&Method::PUT, ["upload", upload_id, part_number]) => {
+            if state.expire_upload_urls > 0 {
+                state.expire_upload_urls -= 1;
+                return Response::builder()
+                    .status(StatusCode::FORBIDDEN)
+                    .body(Body::from(
+                        "<Error><Code>AccessDenied</Code><Message>Request has expired.</Message></Error>",
+                    ))
+                    .unwrap();
+            }
+            let Some(upload) = state.uploads.get_mut(*upload_id) else {
+                return Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::from("<Error><Code>NoSuchUpload</Code></Error>"))
+                    .unwrap();
+            };
+            let part_number: u64 = part_number.parse().unwrap();
+            upload.parts.insert(part_number, body.to_vec());
+            Response::new(Body::empty())
+        }
+        (&Method::GET, ["download", file_id]) => match state.get(file_id) {
+            Some(file) => ranged_response(&file.content, headers),
+            None => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap(),
+        },
+        (&Method::GET, ["stream", file_id, typ]) => {
+            match state.get(file_id).and_then(|f| f.streams.get(*typ)) {
+                Some(content) => ranged_response(content, headers),
+                None => Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body(Body::empty())
+                    .unwrap(),
+            }
+        }
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap(),
+    }
+}
+
+fn ranged_response(content: &[u8], headers: &hyper::HeaderMap) -> Response<Body> {
+    let range = headers
+        .get("Range")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("bytes="))
+        .and_then(|v| v.split_once('-'))
+        .and_then(|(start, end)| Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?)));
+    match range {
+        Some((start, end)) if start < content.len() => {
+            let end = end.min(content.len() - 1);
+            Response::builder()
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", start, end, content.len()),
+                )
+                .body(Body::from(content[start..=end].to_vec()))
+                .unwrap()
+        }
+        Some(_) => Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .body(Body::empty())
+            .unwrap(),
+        None => Response::new(Body::from(content.to_vec())),
+    }
+}
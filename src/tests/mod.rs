@@ -0,0 +1,280 @@
+//! End-to-end tests driving `DavHandler` against a mock AliyunDrive server.
+
+use std::sync::{Arc, RwLock};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use dav_server::{fs::DavFileSystem, memls::MemLs, DavHandler};
+use hyper::{header::HeaderValue, service::Service, HeaderMap, Method, Request, StatusCode};
+
+use crate::auth::{Accounts, AuthScheme, Authenticator, Credentials, Password};
+use crate::drive::{AliyunDrive, DriveConfig, RateLimits};
+use crate::vfs::AliyunDriveFileSystem;
+use crate::webdav::{AliyunDriveWebDav, ServiceOptions};
+
+mod admin;
+mod auth;
+mod config;
+mod dav;
+mod delta;
+mod errors;
+mod health;
+mod listing;
+mod meta_store;
+mod metrics;
+mod mock;
+mod mount;
+mod path_index;
+mod quota;
+mod rate_limit;
+mod search;
+mod trash;
+mod users;
+mod web;
+
+use mock::MockServer;
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl TestResponse {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+type Configure = Arc<dyn Fn(&mut AliyunDriveFileSystem) + Send + Sync>;
+
+pub struct TestContext {
+    pub mock: MockServer,
+    pub fs: AliyunDriveFileSystem,
+    pub handler: DavHandler,
+    configure: Configure,
+}
+
+impl TestContext {
+    pub async fn new() -> Self {
+        Self::with_fs(|_| {}).await
+    }
+
+    /// Set up a context, giving the caller a chance to configure the file system
+    pub async fn with_fs(
+        configure: impl Fn(&mut AliyunDriveFileSystem) + Send + Sync + 'static,
+    ) -> Self {
+        let mock = MockServer::start().await;
+        Self::build(mock, Arc::new(configure)).await
+    }
+
+    async fn build(mock: MockServer, configure: Configure) -> Self {
+        let drive = AliyunDrive::new(drive_config(&mock), "mock.refresh.token".to_string())
+            .await
+            .expect("create drive");
+        let mut fs = AliyunDriveFileSystem::new(drive, "/".to_string(), 1000, 600).unwrap();
+        configure(&mut fs);
+        let handler = DavHandler::builder()
+            .filesystem(Box::new(fs.clone()))
+            .locksystem(MemLs::new())
+            .build_handler();
+        Self {
+            mock,
+            fs,
+            handler,
+            configure,
+        }
+    }
+
+    /// Simulate a server restart, keeping the state of the mock drive
+    pub async fn restart(&self) -> Self {
+        Self::build(self.mock.clone(), self.configure.clone()).await
+    }
+
+    /// Build the authenticating service around `handler`, serving `fs` at the root
+    pub fn service(&self) -> ServiceBuilder {
+        ServiceBuilder {
+            options: ServiceOptions {
+                mounts: Arc::new(vec![("/".to_string(), self.fs.clone())]),
+                ..Default::default()
+            },
+            handler: self.handler.clone(),
+        }
+    }
+
+    /// Serve requests from another file system, e.g. one wrapping `self.fs`
+    pub fn set_filesystem(&mut self, fs: Box<dyn DavFileSystem>) {
+        self.handler = DavHandler::builder()
+            .filesystem(fs)
+            .locksystem(MemLs::new())
+            .build_handler();
+    }
+
+    pub async fn request(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: impl Into<Bytes>,
+    ) -> TestResponse {
+        let body = body.into();
+        let len = body.len().to_string();
+        let mut headers = headers.to_vec();
+        if !body.is_empty() {
+            headers.push(("Content-Length", &len));
+        }
+        self.request_with_body(method, path, &headers, hyper::Body::from(body))
+            .await
+    }
+
+    pub async fn request_with_body(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: hyper::Body,
+    ) -> TestResponse {
+        let mut builder = Request::builder()
+            .method(Method::from_bytes(method.as_bytes()).unwrap())
+            .uri(path);
+        for (name, value) in headers {
+            builder = builder.header(*name, HeaderValue::from_str(value).unwrap());
+        }
+        let req = builder.body(body).unwrap();
+        let res = self.handler.handle(req).await;
+        let (parts, body) = res.into_parts();
+        let body = hyper::body::to_bytes(body).await.expect("read body");
+        TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        }
+    }
+
+    pub async fn get(&self, path: &str) -> TestResponse {
+        self.request("GET", path, &[], Bytes::new()).await
+    }
+
+    pub async fn put(&self, path: &str, body: impl Into<Bytes>) -> TestResponse {
+        self.request("PUT", path, &[], body).await
+    }
+
+    pub async fn propfind(&self, path: &str) -> TestResponse {
+        self.request("PROPFIND", path, &[("Depth", "1")], Bytes::new())
+            .await
+    }
+}
+
+pub fn drive_config(mock: &MockServer) -> DriveConfig {
+    DriveConfig {
+        api_base_url: mock.url(),
+        refresh_token_host: mock.url(),
+        workdir: None,
+        client_id: None,
+        client_secret: None,
+        drive_type: None,
+        // tests are not paced, see `tests::rate_limit` for the limits
+        rate_limits: RateLimits {
+            list: 0.0,
+            metadata: 0.0,
+            download_url: 0.0,
+        },
+    }
+}
+
+/// Authentication of the "admin" account with `password`, or its bearer token "api-token"
+pub fn admin_auth(password: Password, schemes: &[AuthScheme]) -> Authenticator {
+    let credentials =
+        Credentials::new("admin".to_string(), password, vec!["api-token".to_string()]);
+    Authenticator::new(schemes.to_vec(), Accounts::Single(credentials)).unwrap()
+}
+
+/// Service of a test context, without authentication and optional features unless set
+pub struct ServiceBuilder {
+    options: ServiceOptions,
+    handler: DavHandler,
+}
+
+impl ServiceBuilder {
+    pub fn auth(mut self, auth: Authenticator) -> Self {
+        self.options.auth = Arc::new(RwLock::new(Some(Arc::new(auth))));
+        self
+    }
+
+    /// Require the "admin" account, see [`admin_auth`]
+    pub fn admin(self, password: Password, schemes: &[AuthScheme]) -> Self {
+        self.auth(admin_auth(password, schemes))
+    }
+
+    /// Require Basic authentication of "admin" with the password "secret"
+    pub fn basic_auth(self) -> Self {
+        self.admin(Password::Plain("secret".to_string()), &[AuthScheme::Basic])
+    }
+
+    pub fn options(mut self, configure: impl FnOnce(&mut ServiceOptions)) -> Self {
+        configure(&mut self.options);
+        self
+    }
+
+    pub fn build(self) -> AliyunDriveWebDav {
+        AliyunDriveWebDav {
+            options: self.options,
+            handler: self.handler,
+        }
+    }
+}
+
+/// Send a request through the authenticating service instead of the bare handler
+pub async fn call(
+    service: &mut AliyunDriveWebDav,
+    method: &str,
+    path: &str,
+    credentials: Option<(&str, &str)>,
+    body: &'static str,
+) -> (StatusCode, String) {
+    let authorization = credentials.map(|(name, password)| {
+        format!(
+            "Basic {}",
+            STANDARD.encode(format!("{}:{}", name, password))
+        )
+    });
+    let headers: Vec<_> = authorization
+        .iter()
+        .map(|value| ("Authorization", value.as_str()))
+        .collect();
+    let res = call_with_headers(service, method, path, &headers, body).await;
+    (res.status, res.text())
+}
+
+pub async fn call_with_headers(
+    service: &mut AliyunDriveWebDav,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: impl Into<Bytes>,
+) -> TestResponse {
+    let body = body.into();
+    let mut builder = Request::builder()
+        .method(Method::from_bytes(method.as_bytes()).unwrap())
+        .uri(path);
+    if method == "PROPFIND" {
+        builder = builder.header("Depth", "1");
+    }
+    if !body.is_empty() {
+        builder = builder.header("Content-Length", body.len());
+    }
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    let res = service
+        .call(builder.body(hyper::Body::from(body)).unwrap())
+        .await
+        .unwrap();
+    let (parts, body) = res.into_parts();
+    let body = hyper::body::to_bytes(body).await.expect("read body");
+    TestResponse {
+        status: parts.status,
+        headers: parts.headers,
+        body,
+    }
+}
@@ -0,0 +1,307 @@
+use hyper::StatusCode;
+
+use super::{drive_config, TestContext};
+use crate::mount::{MountFileSystem, MountTable};
+use crate::vfs::{AliyunDriveFileSystem, UploadMemory};
+
+async fn mounted() -> (TestContext, TestContext) {
+    let mut alice = TestContext::new().await;
+    let bob = TestContext::new().await;
+    let fs = MountFileSystem::new(vec![
+        ("/alice/resource".to_string(), alice.fs.clone()),
+        ("/bob/".to_string(), bob.fs.clone()),
+    ])
+    .unwrap();
+    alice.set_filesystem(Box::new(fs));
+    (alice, bob)
+}
+
+#[tokio::test]
+async fn mount_lists_virtual_parent_dirs() {
+    let (ctx, _bob) = mounted().await;
+
+    let res = ctx.propfind("/").await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    let body = res.text();
+    assert!(body.contains("/alice/"), "{}", body);
+    assert!(body.contains("/bob/"), "{}", body);
+
+    let body = ctx.propfind("/alice/").await.text();
+    assert!(body.contains("/alice/resource/"), "{}", body);
+    assert_eq!(ctx.propfind("/carol/").await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn mount_routes_requests_to_drives() {
+    let (alice, bob) = mounted().await;
+    alice.mock.add_file("root", "a.txt", b"from alice");
+    bob.mock.add_file("root", "b.txt", b"from bob");
+
+    let body = alice.propfind("/alice/resource/").await.text();
+    assert!(body.contains("/alice/resource/a.txt"), "{}", body);
+    assert!(!body.contains("b.txt"), "{}", body);
+    assert_eq!(
+        &alice.get("/alice/resource/a.txt").await.body[..],
+        b"from alice"
+    );
+    assert_eq!(&alice.get("/bob/b.txt").await.body[..], b"from bob");
+
+    let res = alice.put("/bob/new.txt", "hello bob").await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(bob.mock.find("/new.txt").unwrap().content, b"hello bob");
+    assert!(alice.mock.find("/new.txt").is_none());
+
+    let res = alice
+        .request("MOVE", "/bob/b.txt", &[("Destination", "/bob/c.txt")], "")
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert!(bob.mock.find("/c.txt").is_some());
+}
+
+#[tokio::test]
+async fn mount_rejects_writes_outside_drives() {
+    let (alice, bob) = mounted().await;
+    bob.mock.add_file("root", "b.txt", b"from bob");
+
+    let res = alice
+        .request(
+            "MOVE",
+            "/bob/b.txt",
+            &[("Destination", "/alice/resource/b.txt")],
+            "",
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert!(bob.mock.find("/b.txt").is_some());
+
+    assert!(!alice.put("/new.txt", "hello").await.status.is_success());
+    assert!(!alice
+        .request("DELETE", "/alice/", &[], "")
+        .await
+        .status
+        .is_success());
+}
+
+#[tokio::test]
+async fn mount_table_rejects_invalid_config() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("mounts.toml");
+
+    std::fs::write(
+        &file,
+        r#"
+[accounts.alice]
+refresh_token = "a.b.c"
+
+[[mounts]]
+path = "/alice"
+account = "alice"
+drive_type = "resource"
+
+[[mounts]]
+path = "/alice/backup/"
+account = "alice"
+drive_type = "backup"
+"#,
+    )
+    .unwrap();
+    let err = MountTable::load(&file).await.unwrap_err();
+    assert!(err.to_string().contains("overlap"), "{}", err);
+
+    std::fs::write(
+        &file,
+        r#"
+[[mounts]]
+path = "/bob"
+account = "bob"
+"#,
+    )
+    .unwrap();
+    let err = MountTable::load(&file).await.unwrap_err();
+    assert!(err.to_string().contains("unknown account"), "{}", err);
+
+    std::fs::write(
+        &file,
+        r#"
+[accounts.alice]
+
+[[mounts]]
+path = "/alice/resource"
+account = "alice"
+drive_type = "resource"
+read_only = true
+soft_quota = 1024
+"#,
+    )
+    .unwrap();
+    let table = MountTable::load(&file).await.unwrap();
+    assert_eq!(table.mounts.len(), 1);
+    assert!(table.mounts[0].read_only);
+    assert_eq!(table.mounts[0].soft_quota, Some(1024));
+}
+
+#[tokio::test]
+async fn mount_quota_covers_each_account_once() {
+    let mut alice = TestContext::new().await;
+    let bob = TestContext::new().await;
+    alice.mock.state().total_size = 100;
+    alice.mock.add_file("root", "a.txt", b"1234");
+    bob.mock.state().total_size = 50;
+    bob.mock.add_file("root", "b.txt", b"123456");
+    let mut limited = bob.fs.clone();
+    limited.set_soft_quota(Some(20));
+    let fs = MountFileSystem::new(vec![
+        ("/alice".to_string(), alice.fs.clone()),
+        ("/bob".to_string(), bob.fs.clone()),
+        ("/limited".to_string(), limited),
+    ])
+    .unwrap();
+    alice.set_filesystem(Box::new(fs));
+
+    // the soft quota only applies to its mount
+    let content = "123456789012345";
+    assert_eq!(
+        alice.put("/limited/c.txt", content).await.status,
+        StatusCode::INSUFFICIENT_STORAGE
+    );
+    assert_eq!(
+        alice.put("/bob/c.txt", content).await.status,
+        StatusCode::CREATED
+    );
+
+    let body = r#"<?xml version="1.0"?>
+<D:propfind xmlns:D="DAV:"><D:prop><D:quota-used-bytes/><D:quota-available-bytes/></D:prop></D:propfind>"#;
+    let res = alice
+        .request("PROPFIND", "/", &[("Depth", "0")], body)
+        .await;
+    let text = res.text();
+    // bob's space is counted once, with the limit of the limited mount
+    assert!(text.contains("quota-used-bytes>25<"), "{}", text);
+    assert!(text.contains("quota-available-bytes>95<"), "{}", text);
+}
+
+#[tokio::test]
+async fn mount_table_shares_account_between_drives() {
+    let mut ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "a.txt", b"hello");
+    let workdir = tempfile::tempdir().unwrap();
+    let file = workdir.path().join("mounts.toml");
+    std::fs::write(
+        &file,
+        r#"
+[accounts.alice]
+refresh_token = "mock.refresh.token"
+
+[[mounts]]
+path = "/alice/default"
+account = "alice"
+
+[[mounts]]
+path = "/alice/resource"
+account = "alice"
+drive_type = "resource"
+"#,
+    )
+    .unwrap();
+    let mut config = drive_config(&ctx.mock);
+    config.workdir = Some(workdir.path().to_path_buf());
+    let calls = ctx.mock.calls("/oauth/access_token");
+
+    let table = MountTable::load(&file).await.unwrap();
+    let fs = table
+        .build(&config, |drive, root, _| {
+            AliyunDriveFileSystem::new(drive, root, 100, 60)
+        })
+        .await
+        .unwrap();
+    assert_eq!(fs.dir_caches().len(), 2);
+    ctx.set_filesystem(Box::new(fs));
+
+    // one token refresh loop for both drives of the account
+    assert_eq!(ctx.mock.calls("/oauth/access_token"), calls + 1);
+    assert!(workdir.path().join("accounts/alice/refresh_token").exists());
+    assert_eq!(&ctx.get("/alice/default/a.txt").await.body[..], b"hello");
+    assert_eq!(&ctx.get("/alice/resource/a.txt").await.body[..], b"hello");
+}
+
+#[tokio::test]
+async fn mount_soft_quota_overrides_the_global_one() {
+    let mut ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "a.txt", b"hello");
+    let file = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(
+        file.path(),
+        r#"
+[accounts.alice]
+refresh_token = "mock.refresh.token"
+
+[[mounts]]
+path = "/default"
+account = "alice"
+
+[[mounts]]
+path = "/raised"
+account = "alice"
+soft_quota = 100
+"#,
+    )
+    .unwrap();
+
+    let table = MountTable::load(file.path()).await.unwrap();
+    let fs = table
+        .build(&drive_config(&ctx.mock), |drive, root, _| {
+            let mut fs = AliyunDriveFileSystem::new(drive, root, 100, 60)?;
+            fs.set_soft_quota(Some(6));
+            Ok(fs)
+        })
+        .await
+        .unwrap();
+    ctx.set_filesystem(Box::new(fs));
+
+    assert_eq!(
+        ctx.put("/default/b.txt", "123").await.status,
+        StatusCode::INSUFFICIENT_STORAGE
+    );
+    assert_eq!(
+        ctx.put("/raised/b.txt", "123").await.status,
+        StatusCode::CREATED
+    );
+}
+
+#[tokio::test]
+async fn mounts_share_the_upload_memory_budget() {
+    let upload_memory = UploadMemory::new(8);
+    let mut ctx = TestContext::with_fs({
+        let upload_memory = upload_memory.clone();
+        move |fs| {
+            fs.set_upload_buffer_size(4)
+                .set_upload_concurrency(3)
+                .set_upload_memory(upload_memory.clone());
+        }
+    })
+    .await;
+    ctx.mock.add_folder("root", "a");
+    ctx.mock.add_folder("root", "b");
+    // a separate file system of the same drive, as each mount has
+    let mut other =
+        AliyunDriveFileSystem::new(ctx.fs.drive.clone(), "/".to_string(), 100, 60).unwrap();
+    other
+        .set_upload_buffer_size(4)
+        .set_upload_concurrency(3)
+        .set_upload_memory(upload_memory);
+    let fs = MountFileSystem::new(vec![
+        ("/a".to_string(), ctx.fs.clone()),
+        ("/b".to_string(), other),
+    ])
+    .unwrap();
+    ctx.set_filesystem(Box::new(fs));
+    ctx.mock
+        .set_upload_delay(std::time::Duration::from_millis(50));
+
+    let content = "0123456789abcdefghij";
+    let (a, b) = tokio::join!(ctx.put("/a/a.bin", content), ctx.put("/b/b.bin", content));
+    assert_eq!(a.status, StatusCode::CREATED);
+    assert_eq!(b.status, StatusCode::CREATED);
+    // 8 bytes hold two parts of 4 bytes across both mounts
+    assert_eq!(ctx.mock.max_uploads_in_flight(), 2);
+}
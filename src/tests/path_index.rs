@@ -0,0 +1,100 @@
+use hyper::StatusCode;
+
+use super::TestContext;
+
+const LIST: &str = "/adrive/v1.0/openFile/list";
+
+#[tokio::test]
+async fn path_index_lists_each_level_once() {
+    let ctx = TestContext::new().await;
+    let a = ctx.mock.add_folder("root", "a");
+    let b = ctx.mock.add_folder(&a, "b b");
+    let c = ctx.mock.add_folder(&b, "c");
+    ctx.mock.add_file(&c, "d.txt", b"d");
+    ctx.mock.add_file(&c, "e.txt", b"e");
+
+    assert_eq!(ctx.get("/a/b%20b/c/d.txt").await.text(), "d");
+    assert_eq!(ctx.mock.calls(LIST), 4);
+    assert_eq!(ctx.get("/a/b%20b/c/e.txt").await.text(), "e");
+    assert_eq!(ctx.mock.calls(LIST), 4);
+
+    // a moved folder keeps the ids known below it
+    let res = ctx
+        .request("MOVE", "/a/b%20b/", &[("Destination", "/x/")], "")
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    let lists = ctx.mock.calls(LIST);
+    assert_eq!(ctx.get("/x/c/d.txt").await.text(), "d");
+    assert_eq!(ctx.mock.calls(LIST), lists + 1);
+    assert_eq!(
+        ctx.get("/a/b%20b/c/d.txt").await.status,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn path_index_follows_writes() {
+    let ctx = TestContext::new().await;
+    assert_eq!(
+        ctx.request("MKCOL", "/new/", &[], "").await.status,
+        StatusCode::CREATED
+    );
+    let id = ctx.mock.find("/new").unwrap().id;
+    assert_eq!(ctx.fs.dir_cache.index().get("/new".as_ref()), Some(id));
+    assert_eq!(
+        ctx.put("/new/a.txt", "hello").await.status,
+        StatusCode::CREATED
+    );
+    let file = ctx.mock.find("/new/a.txt").unwrap();
+    assert_eq!(
+        ctx.fs.dir_cache.index().get("/new/a.txt".as_ref()),
+        Some(file.id)
+    );
+    let res = ctx
+        .request("COPY", "/new/a.txt", &[("Destination", "/a.txt")], "")
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    let copy = ctx.mock.find("/a.txt").unwrap();
+    assert_eq!(
+        ctx.fs.dir_cache.index().get("/a.txt".as_ref()),
+        Some(copy.id)
+    );
+
+    ctx.request("DELETE", "/new/", &[], "").await;
+    assert_eq!(ctx.fs.dir_cache.index().get("/new".as_ref()), None);
+    assert_eq!(ctx.fs.dir_cache.index().get("/new/a.txt".as_ref()), None);
+}
+
+#[tokio::test]
+async fn path_index_forgets_removed_folders() {
+    let ctx = TestContext::new().await;
+    let a = ctx.mock.add_folder("root", "a");
+    let b = ctx.mock.add_folder(&a, "b");
+    ctx.mock.add_file(&b, "c.txt", b"c");
+    assert_eq!(ctx.get("/a/b/c.txt").await.text(), "c");
+
+    // removed in another client while its id is still indexed
+    ctx.mock.trash(&b);
+    ctx.fs.invalidate_cache("/a/b", false).await;
+    assert_eq!(ctx.get("/a/b/c.txt").await.status, StatusCode::NOT_FOUND);
+    assert_eq!(ctx.fs.dir_cache.index().get("/a/b".as_ref()), None);
+}
+
+#[tokio::test]
+async fn path_index_drops_expired_paths() {
+    let ctx = TestContext::with_fs(|fs| fs.dir_cache.set_ttl(1)).await;
+    let a = ctx.mock.add_folder("root", "a");
+    ctx.mock.add_file(&a, "1.txt", b"1");
+    ctx.mock.add_file(&a, "2.txt", b"2");
+    let b = ctx.mock.add_folder("root", "b");
+    ctx.mock.add_file(&b, "3.txt", b"3");
+
+    assert_eq!(ctx.propfind("/a/").await.status, StatusCode::MULTI_STATUS);
+    // a, b, a/1.txt and a/2.txt
+    assert_eq!(ctx.fs.dir_cache.index().path_count(), 4);
+
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    assert_eq!(ctx.propfind("/b/").await.status, StatusCode::MULTI_STATUS);
+    // only the fresh listings of the root and b are left
+    assert_eq!(ctx.fs.dir_cache.index().path_count(), 3);
+}
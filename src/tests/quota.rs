@@ -0,0 +1,82 @@
+use hyper::StatusCode;
+
+use super::TestContext;
+
+const CREATE: &str = "/adrive/v1.0/openFile/create";
+const SPACE_INFO: &str = "/adrive/v1.0/user/getSpaceInfo";
+
+#[tokio::test]
+async fn uploads_over_quota_are_refused_up_front() {
+    let ctx = TestContext::new().await;
+    ctx.mock.state().total_size = 10;
+    ctx.mock.add_file("root", "a.txt", b"1234");
+
+    assert_eq!(
+        ctx.put("/b.txt", "12345678").await.status,
+        StatusCode::INSUFFICIENT_STORAGE
+    );
+    assert_eq!(ctx.mock.calls(CREATE), 0);
+    assert_eq!(ctx.put("/b.txt", "12345").await.status, StatusCode::CREATED);
+    // overwriting only needs room for the difference
+    assert_eq!(
+        ctx.put("/a.txt", "123456").await.status,
+        StatusCode::INSUFFICIENT_STORAGE
+    );
+    assert_eq!(
+        ctx.put("/a.txt", "12345").await.status,
+        StatusCode::NO_CONTENT
+    );
+}
+
+#[tokio::test]
+async fn soft_quota_limits_uploads_below_the_drive_size() {
+    let ctx = TestContext::with_fs(|fs| {
+        fs.set_soft_quota(Some(6));
+    })
+    .await;
+    ctx.mock.add_file("root", "a.txt", b"1234");
+
+    assert_eq!(
+        ctx.put("/b.txt", "123").await.status,
+        StatusCode::INSUFFICIENT_STORAGE
+    );
+    assert_eq!(ctx.put("/b.txt", "12").await.status, StatusCode::CREATED);
+    // the usage is cached and counts the upload
+    assert_eq!(
+        ctx.put("/c.txt", "1").await.status,
+        StatusCode::INSUFFICIENT_STORAGE
+    );
+    assert_eq!(ctx.mock.calls(SPACE_INFO), 1);
+
+    let body = r#"<?xml version="1.0"?>
+<D:propfind xmlns:D="DAV:"><D:prop><D:quota-available-bytes/></D:prop></D:propfind>"#;
+    let res = ctx.request("PROPFIND", "/", &[("Depth", "0")], body).await;
+    assert!(
+        res.text().contains("quota-available-bytes>0<"),
+        "{}",
+        res.text()
+    );
+}
+
+#[tokio::test]
+async fn spooled_uploads_over_quota_are_refused() {
+    let spool = tempfile::tempdir().unwrap();
+    let spool_dir = spool.path().to_path_buf();
+    let ctx = TestContext::with_fs(move |fs| {
+        fs.set_spool_dir(Some(spool_dir.clone()))
+            .set_soft_quota(Some(6));
+    })
+    .await;
+    ctx.mock.add_file("root", "a.txt", b"1234");
+
+    // chunked transfer encoding, the size is only known once the body is spooled
+    let (mut sender, body) = hyper::Body::channel();
+    let upload = tokio::spawn(async move {
+        sender.send_data("123".into()).await.unwrap();
+    });
+    let res = ctx.request_with_body("PUT", "/b.txt", &[], body).await;
+    upload.await.unwrap();
+    assert_eq!(res.status, StatusCode::INSUFFICIENT_STORAGE);
+    assert_eq!(ctx.mock.calls(CREATE), 0);
+    assert_eq!(std::fs::read_dir(spool.path()).unwrap().count(), 0);
+}
@@ -0,0 +1,63 @@
+use std::time::{Duration, Instant};
+
+use hyper::StatusCode;
+
+use super::{drive_config, mock::MockServer, TestContext};
+use crate::drive::{AliyunDrive, RateLimits};
+
+const LIST: &str = "/adrive/v1.0/openFile/list";
+
+#[tokio::test]
+async fn throttled_calls_wait_for_retry_after() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_folder("root", "a");
+    ctx.mock.throttle(1, Some(1));
+
+    let start = Instant::now();
+    assert_eq!(ctx.propfind("/").await.status, StatusCode::MULTI_STATUS);
+    assert!(start.elapsed() >= Duration::from_secs(1));
+    assert_eq!(ctx.mock.calls(LIST), 2);
+}
+
+#[tokio::test]
+async fn throttled_calls_back_off_without_retry_after() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_folder("root", "a");
+    ctx.mock.throttle(2, None);
+
+    let start = Instant::now();
+    assert_eq!(ctx.propfind("/").await.status, StatusCode::MULTI_STATUS);
+    // 0.5s then 1s
+    assert!(start.elapsed() >= Duration::from_millis(1500));
+    assert_eq!(ctx.mock.calls(LIST), 3);
+}
+
+#[tokio::test]
+async fn calls_are_rate_limited_per_endpoint() {
+    let mock = MockServer::start().await;
+    let a = mock.add_folder("root", "a");
+    mock.add_file(&a, "b.txt", b"b");
+    let mut config = drive_config(&mock);
+    config.rate_limits = RateLimits {
+        list: 20.0,
+        metadata: 0.0,
+        download_url: 0.0,
+    };
+    let drive = AliyunDrive::new(config, "mock.refresh.token".to_string())
+        .await
+        .unwrap();
+
+    // the burst is free, the rest is spread at 20 per second
+    let start = Instant::now();
+    for _ in 0..30 {
+        drive.list_all(&a).await.unwrap();
+    }
+    assert!(start.elapsed() >= Duration::from_millis(450));
+
+    // other endpoints have their own budget
+    let start = Instant::now();
+    for _ in 0..30 {
+        drive.get_file(&a).await.unwrap();
+    }
+    assert!(start.elapsed() < Duration::from_millis(450));
+}
@@ -0,0 +1,165 @@
+use hyper::StatusCode;
+
+use super::{call_with_headers, TestContext};
+use crate::vfs::AliyunDriveFileSystem;
+use crate::webdav::AliyunDriveWebDav;
+
+async fn context() -> TestContext {
+    TestContext::with_fs(|fs| {
+        fs.set_search_folder(true);
+    })
+    .await
+}
+
+fn service(ctx: &TestContext) -> AliyunDriveWebDav {
+    ctx.service()
+        .options(|options| options.search = true)
+        .build()
+}
+
+fn search_request(condition: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<D:searchrequest xmlns:D="DAV:">
+  <D:basicsearch>
+    <D:select><D:prop><D:displayname/><D:getcontentlength/></D:prop></D:select>
+    <D:from><D:scope><D:href>/</D:href><D:depth>infinity</D:depth></D:scope></D:from>
+    <D:where>{}</D:where>
+  </D:basicsearch>
+</D:searchrequest>"#,
+        condition
+    )
+}
+
+#[tokio::test]
+async fn search_folder_lists_matching_files() {
+    let ctx = context().await;
+    let docs = ctx.mock.add_folder("root", "docs");
+    ctx.mock.add_file(&docs, "report.txt", b"report");
+    ctx.mock.add_file("root", "report.txt", b"other");
+    ctx.mock.add_file("root", "notes.txt", b"notes");
+
+    let res = ctx.propfind("/.search/report/").await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    let text = res.text();
+    assert!(
+        text.contains("/.search/report/report.txt%20%28"),
+        "{}",
+        text
+    );
+    assert!(!text.contains("notes.txt"), "{}", text);
+    assert_eq!(ctx.mock.searches(), vec!["name match \"report\""]);
+    // the virtual folder is not listed in the root
+    assert!(!ctx.propfind("/").await.text().contains(".search"));
+
+    let res = ctx.propfind("/.search/notes/").await;
+    assert!(res.text().contains("/.search/notes/notes.txt"));
+    assert_eq!(ctx.get("/.search/notes/notes.txt").await.text(), "notes");
+    assert_eq!(
+        ctx.put("/.search/notes/notes.txt", "changed").await.status,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        ctx.request("DELETE", "/.search/notes/notes.txt", &[], "")
+            .await
+            .status,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(ctx.mock.find("/notes.txt").unwrap().content, b"notes");
+}
+
+#[tokio::test]
+async fn search_request_is_translated_to_drive_query() {
+    let ctx = context().await;
+    ctx.mock.add_file("root", "photo.jpg", b"jpg");
+    ctx.mock.add_file("root", "notes.txt", b"notes");
+    let mut svc = service(&ctx);
+
+    let condition = r#"<D:and>
+        <D:like><D:prop><D:displayname/></D:prop><D:literal>%photo%</D:literal></D:like>
+        <D:gt><D:prop><D:getlastmodified/></D:prop><D:literal>Mon, 01 Jan 2024 08:00:00 GMT</D:literal></D:gt>
+      </D:and>"#;
+    let res = call_with_headers(&mut svc, "SEARCH", "/", &[], search_request(condition)).await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    let text = res.text();
+    assert!(text.contains("photo.jpg"), "{}", text);
+    assert!(!text.contains("notes.txt"), "{}", text);
+    // the search folder itself is not part of the results
+    assert_eq!(text.matches("<D:response>").count(), 1, "{}", text);
+    assert_eq!(
+        ctx.mock.searches(),
+        vec![r#"(name match "photo" and updated_at > "2024-01-01T08:00:00")"#]
+    );
+
+    let condition = r#"<D:or>
+        <D:eq><D:prop><D:displayname/></D:prop><D:literal>notes.txt</D:literal></D:eq>
+        <D:gte><D:prop><D:getcontentlength/></D:prop><D:literal>1024</D:literal></D:gte>
+      </D:or>"#;
+    let res = call_with_headers(&mut svc, "SEARCH", "/", &[], search_request(condition)).await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    assert!(res.text().contains("notes.txt"), "{}", res.text());
+    assert_eq!(
+        ctx.mock.searches().last().unwrap(),
+        r#"(name = "notes.txt" or size >= 1024)"#
+    );
+
+    let condition = r#"<D:not><D:is-collection/></D:not>"#;
+    let res = call_with_headers(&mut svc, "SEARCH", "/", &[], search_request(condition)).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    let res = call_with_headers(&mut svc, "SEARCH", "/", &[], "<D:propfind/>").await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    let res = call_with_headers(&mut svc, "OPTIONS", "/", &[], "").await;
+    assert_eq!(res.headers["DASL"], "<DAV:basicsearch>");
+}
+
+#[tokio::test]
+async fn search_request_scope_must_be_the_whole_drive() {
+    let ctx = context().await;
+    ctx.mock.add_folder("root", "docs");
+    let mut svc = service(&ctx);
+    let condition = "<D:is-collection/>";
+
+    let scoped = search_request(condition).replace("<D:href>/</D:href>", "<D:href>/docs/</D:href>");
+    let res = call_with_headers(&mut svc, "SEARCH", "/", &[], scoped).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let shallow = search_request(condition).replace(">infinity<", ">1<");
+    let res = call_with_headers(&mut svc, "SEARCH", "/", &[], shallow).await;
+    assert_eq!(res.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let unscoped = search_request(condition).replace(
+        "<D:from><D:scope><D:href>/</D:href><D:depth>infinity</D:depth></D:scope></D:from>",
+        "",
+    );
+    let res = call_with_headers(&mut svc, "SEARCH", "/", &[], unscoped).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert!(ctx.mock.searches().is_empty());
+    let absolute = search_request(condition).replace(
+        "<D:href>/</D:href>",
+        "<D:href>http://localhost:8080/</D:href>",
+    );
+    let res = call_with_headers(&mut svc, "SEARCH", "/", &[], absolute).await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    assert_eq!(ctx.mock.searches(), vec![r#"type = "folder""#]);
+}
+
+#[tokio::test]
+async fn search_is_not_implemented_below_the_drive_root() {
+    let mut ctx = context().await;
+    ctx.mock.add_folder("root", "docs");
+    let mut fs =
+        AliyunDriveFileSystem::new(ctx.fs.drive.clone(), "/docs".to_string(), 100, 60).unwrap();
+    fs.set_search_folder(true);
+    ctx.set_filesystem(Box::new(fs));
+    let mut svc = service(&ctx);
+
+    let res = call_with_headers(
+        &mut svc,
+        "SEARCH",
+        "/",
+        &[],
+        search_request("<D:is-collection/>"),
+    )
+    .await;
+    assert_eq!(res.status, StatusCode::NOT_IMPLEMENTED);
+    assert!(ctx.mock.searches().is_empty());
+}
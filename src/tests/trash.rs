@@ -0,0 +1,121 @@
+use hyper::StatusCode;
+
+use super::TestContext;
+
+async fn context() -> TestContext {
+    TestContext::with_fs(|fs| {
+        fs.set_trash_folder(true);
+    })
+    .await
+}
+
+#[tokio::test]
+async fn trash_folder_lists_and_restores_trashed_files() {
+    let ctx = context().await;
+    let id = ctx.mock.add_file("root", "a.txt", b"hello");
+    ctx.mock.add_folder("root", "docs");
+
+    assert_eq!(
+        ctx.request("DELETE", "/a.txt", &[], "").await.status,
+        StatusCode::NO_CONTENT
+    );
+    assert!(ctx.mock.get(&id).unwrap().trashed);
+    let res = ctx.propfind("/.trash/").await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    assert!(res.text().contains("/.trash/a.txt"), "{}", res.text());
+    // the virtual folder is not listed, so sync clients don't descend into it
+    assert!(!ctx.propfind("/").await.text().contains(".trash"));
+
+    let res = ctx
+        .request("MOVE", "/.trash/a.txt", &[("Destination", "/a.txt")], "")
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert!(!ctx.mock.get(&id).unwrap().trashed);
+    assert_eq!(ctx.get("/a.txt").await.text(), "hello");
+    assert!(!ctx.propfind("/.trash/").await.text().contains("a.txt"));
+
+    // restoring somewhere else moves the file there
+    ctx.request("DELETE", "/a.txt", &[], "").await;
+    let res = ctx
+        .request(
+            "MOVE",
+            "/.trash/a.txt",
+            &[("Destination", "/docs/b.txt")],
+            "",
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::CREATED);
+    assert_eq!(ctx.mock.find("/docs/b.txt").unwrap().id, id);
+    assert!(ctx.mock.find("/a.txt").is_none());
+}
+
+#[tokio::test]
+async fn trash_folder_purges_and_is_read_only() {
+    let ctx = context().await;
+    let folder = ctx.mock.add_folder("root", "dir");
+    let child = ctx.mock.add_folder(&folder, "sub");
+    let first = ctx.mock.add_file("root", "b.txt", b"b");
+    ctx.request("DELETE", "/dir/", &[], "").await;
+    ctx.request("DELETE", "/b.txt", &[], "").await;
+    let second = ctx.mock.add_file("root", "b.txt", b"b");
+    ctx.request("DELETE", "/b.txt", &[], "").await;
+
+    // files trashed with the same name are told apart by their ids
+    let listing = ctx.propfind("/.trash/").await.text();
+    assert!(
+        listing.contains(&format!("b.txt%20%28{}%29", first)),
+        "{}",
+        listing
+    );
+    assert!(
+        listing.contains(&format!("b.txt%20%28{}%29", second)),
+        "{}",
+        listing
+    );
+    // trashed folders are listed without their children
+    let res = ctx.propfind("/.trash/dir/").await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    assert!(!res.text().contains("sub"), "{}", res.text());
+
+    assert_eq!(
+        ctx.put("/.trash/c.txt", "c").await.status,
+        StatusCode::FORBIDDEN
+    );
+    let res = ctx.request("MKCOL", "/.trash/new/", &[], "").await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    assert_ne!(
+        ctx.get(&format!("/.trash/b.txt%20%28{}%29", first))
+            .await
+            .status,
+        StatusCode::OK
+    );
+
+    let res = ctx.request("DELETE", "/.trash/dir/", &[], "").await;
+    assert_eq!(res.status, StatusCode::NO_CONTENT);
+    assert!(ctx.mock.get(&folder).is_none());
+    assert!(ctx.mock.get(&child).is_none());
+    let path = format!("/.trash/b.txt%20%28{}%29", first);
+    assert_eq!(
+        ctx.request("DELETE", &path, &[], "").await.status,
+        StatusCode::NO_CONTENT
+    );
+    assert!(ctx.mock.get(&first).is_none());
+    assert!(ctx.mock.get(&second).unwrap().trashed);
+}
+
+#[tokio::test]
+async fn trash_folder_is_opt_in() {
+    let ctx = TestContext::new().await;
+    assert_eq!(ctx.propfind("/.trash/").await.status, StatusCode::NOT_FOUND);
+
+    let ctx = TestContext::with_fs(|fs| {
+        fs.set_trash_folder(true);
+        fs.set_read_only(true);
+    })
+    .await;
+    let id = ctx.mock.add_file("root", "a.txt", b"a");
+    ctx.mock.trash(&id);
+    assert!(ctx.propfind("/.trash/").await.text().contains("a.txt"));
+    let res = ctx.request("DELETE", "/.trash/a.txt", &[], "").await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+}
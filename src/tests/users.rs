@@ -0,0 +1,168 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use hyper::StatusCode;
+
+use super::{call, call_with_headers, TestContext};
+use crate::auth::{Accounts, AuthScheme, Authenticator};
+use crate::mount::MountFileSystem;
+use crate::users::{UserFileSystem, Users};
+use crate::webdav::AliyunDriveWebDav;
+
+async fn load_users() -> Users {
+    let hash = bcrypt::hash("secret", 4).unwrap();
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    write!(
+        file,
+        r#"
+[users.alice]
+password_hash = "{hash}"
+home = "/alice"
+read_only = true
+rules = [{{ path = "/upload", access = "rw" }}]
+bearer_tokens = ["alice-token"]
+
+[users.bob]
+password_hash = "{hash}"
+"#
+    )
+    .unwrap();
+    Users::load(file.path()).await.unwrap()
+}
+
+fn service(ctx: &TestContext, users: Users, fs: Arc<dyn UserFileSystem>) -> AliyunDriveWebDav {
+    let schemes = vec![AuthScheme::Basic, AuthScheme::Bearer];
+    let auth = Authenticator::new(schemes, Accounts::Users(Arc::new(users))).unwrap();
+    ctx.service()
+        .auth(auth)
+        .options(|options| options.user_fs = Some(fs))
+        .build()
+}
+
+#[tokio::test]
+async fn users_authenticate_with_password_hash() {
+    let ctx = TestContext::new().await;
+    let users = load_users().await;
+    let mut svc = service(&ctx, users, Arc::new(ctx.fs.clone()));
+
+    let (status, _) = call(&mut svc, "PROPFIND", "/", None, "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call(&mut svc, "PROPFIND", "/", Some(("alice", "wrong")), "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call(&mut svc, "PROPFIND", "/", Some(("carol", "secret")), "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, _) = call(&mut svc, "PUT", "/b.txt", Some(("bob", "secret")), "hi").await;
+    assert_eq!(status, StatusCode::CREATED);
+    // verified passwords are cached
+    let (status, _) = call(&mut svc, "PROPFIND", "/", Some(("bob", "secret")), "").await;
+    assert_eq!(status, StatusCode::MULTI_STATUS);
+    let (status, _) = call(&mut svc, "PROPFIND", "/", Some(("bob", "wrong")), "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn users_are_restricted_to_home_and_rules() {
+    let ctx = TestContext::new().await;
+    let alice = ctx.mock.add_folder("root", "alice");
+    ctx.mock.add_file(&alice, "a.txt", b"alice");
+    ctx.mock.add_folder(&alice, "upload");
+    ctx.mock.add_file("root", "secret.txt", b"top secret");
+    let users = load_users().await;
+    let mut svc = service(&ctx, users, Arc::new(ctx.fs.clone()));
+    let creds = Some(("alice", "secret"));
+
+    let (status, body) = call(&mut svc, "PROPFIND", "/", creds, "").await;
+    assert_eq!(status, StatusCode::MULTI_STATUS);
+    assert!(body.contains("/a.txt"), "{}", body);
+    assert!(!body.contains("secret.txt"), "{}", body);
+    let (_, body) = call(&mut svc, "GET", "/a.txt", creds, "").await;
+    assert_eq!(body, "alice");
+
+    let (status, _) = call(&mut svc, "PUT", "/b.txt", creds, "hi").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call(&mut svc, "DELETE", "/a.txt", creds, "").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(ctx.mock.find("/alice/a.txt").is_some());
+
+    let (status, _) = call(&mut svc, "PUT", "/upload/b.txt", creds, "hi").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(ctx.mock.find("/alice/upload/b.txt").unwrap().content, b"hi");
+}
+
+#[tokio::test]
+async fn users_home_above_mounts_sees_mounts_below() {
+    let ctx = TestContext::new().await;
+    let other = TestContext::new().await;
+    ctx.mock.add_folder("root", "upload");
+    let fs = MountFileSystem::new(vec![
+        ("/alice/".to_string(), ctx.fs.clone()),
+        ("/shared/".to_string(), other.fs.clone()),
+    ])
+    .unwrap();
+    let users = load_users().await;
+    let mut svc = service(&ctx, users, Arc::new(fs));
+    let creds = Some(("alice", "secret"));
+
+    // alice's home is a whole mount, so the rules are relative to the drive root
+    let (status, _) = call(&mut svc, "PUT", "/upload/a.txt", creds, "hi").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(ctx.mock.find("/upload/a.txt").is_some());
+    let (status, _) = call(&mut svc, "PUT", "/a.txt", creds, "hi").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let creds = Some(("bob", "secret"));
+    let (_, body) = call(&mut svc, "PROPFIND", "/", creds, "").await;
+    assert!(body.contains("/alice/"), "{}", body);
+    assert!(body.contains("/shared/"), "{}", body);
+    let (status, _) = call(&mut svc, "PUT", "/shared/b.txt", creds, "hi").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(other.mock.find("/b.txt").is_some());
+}
+
+#[tokio::test]
+async fn users_authenticate_with_bearer_token() {
+    let ctx = TestContext::new().await;
+    let alice = ctx.mock.add_folder("root", "alice");
+    ctx.mock.add_file(&alice, "a.txt", b"alice");
+    let users = load_users().await;
+    let mut svc = service(&ctx, users, Arc::new(ctx.fs.clone()));
+
+    let auth = [("Authorization", "Bearer alice-token")];
+    let res = call_with_headers(&mut svc, "GET", "/a.txt", &auth, "").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.text(), "alice");
+    let auth = [("Authorization", "Bearer bob-token")];
+    let res = call_with_headers(&mut svc, "GET", "/a.txt", &auth, "").await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    let challenges: Vec<_> = res.headers.get_all("WWW-Authenticate").iter().collect();
+    assert_eq!(challenges.len(), 2);
+}
+
+#[tokio::test]
+async fn users_soft_quota_limits_their_uploads() {
+    let ctx = TestContext::new().await;
+    ctx.mock.add_file("root", "a.txt", b"1234");
+    let hash = bcrypt::hash("secret", 4).unwrap();
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    write!(
+        file,
+        r#"
+[users.carol]
+password_hash = "{hash}"
+soft_quota = 6
+bearer_tokens = ["carol-token"]
+
+[users.dave]
+password_hash = "{hash}"
+"#
+    )
+    .unwrap();
+    let users = Users::load(file.path()).await.unwrap();
+    let mut svc = service(&ctx, users, Arc::new(ctx.fs.clone()));
+
+    let (status, _) = call(&mut svc, "PUT", "/b.txt", Some(("carol", "secret")), "123").await;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    let (status, _) = call(&mut svc, "PUT", "/b.txt", Some(("dave", "secret")), "123").await;
+    assert_eq!(status, StatusCode::CREATED);
+}
@@ -0,0 +1,60 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hyper::StatusCode;
+
+use super::{call_with_headers, TestContext};
+use crate::webdav::AliyunDriveWebDav;
+
+const HTML: (&str, &str) = ("Accept", "text/html,application/xhtml+xml,*/*;q=0.8");
+
+fn service(ctx: &TestContext, web_ui: bool) -> AliyunDriveWebDav {
+    ctx.service()
+        .basic_auth()
+        .options(|options| options.web_ui = web_ui)
+        .build()
+}
+
+#[tokio::test]
+async fn web_ui_is_served_to_browsers() {
+    let ctx = TestContext::new().await;
+    let sub = ctx.mock.add_folder("root", "sub");
+    ctx.mock.add_file(&sub, "a.txt", b"hello");
+    let mut svc = service(&ctx, true);
+    let basic = format!("Basic {}", STANDARD.encode("admin:secret"));
+    let auth = ("Authorization", basic.as_str());
+
+    let res = call_with_headers(&mut svc, "GET", "/", &[HTML], "").await;
+    assert_eq!(res.status, StatusCode::UNAUTHORIZED);
+    for path in ["/", "/sub/"] {
+        let res = call_with_headers(&mut svc, "GET", path, &[HTML, auth], "").await;
+        assert_eq!(res.status, StatusCode::OK);
+        assert!(res.headers["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        assert!(res.text().contains("PROPFIND"));
+    }
+    // files are still downloaded
+    let res = call_with_headers(&mut svc, "GET", "/sub/a.txt", &[HTML, auth], "").await;
+    assert_eq!(res.text(), "hello");
+
+    // the page reads the quota of the drive
+    let body = r#"<?xml version="1.0"?><D:propfind xmlns:D="DAV:"><D:prop><D:quota-used-bytes/><D:quota-available-bytes/></D:prop></D:propfind>"#;
+    let res = call_with_headers(&mut svc, "PROPFIND", "/", &[auth], body).await;
+    assert_eq!(res.status, StatusCode::MULTI_STATUS);
+    assert!(res.text().contains("quota-used-bytes>5<"), "{}", res.text());
+}
+
+#[tokio::test]
+async fn web_ui_is_opt_in() {
+    let ctx = TestContext::new().await;
+    let mut svc = service(&ctx, false);
+    let basic = format!("Basic {}", STANDARD.encode("admin:secret"));
+    let auth = ("Authorization", basic.as_str());
+
+    let res = call_with_headers(&mut svc, "GET", "/", &[HTML, auth], "").await;
+    assert!(!res.text().contains("PROPFIND"));
+
+    let mut svc = service(&ctx, true);
+    let res = call_with_headers(&mut svc, "GET", "/", &[auth], "").await;
+    assert!(!res.text().contains("PROPFIND"));
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use bytes::BytesMut;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tracing::{debug, warn};
+
+/// Upload sessions older than this are considered expired by the drive
+const SESSION_TTL: u64 = 24 * 60 * 60;
+
+/// A multipart upload that was started but not completed yet
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadSession {
+    pub file_id: String,
+    pub upload_id: String,
+    pub chunk_size: u64,
+    pub chunk_count: u64,
+    /// sha1 of every uploaded part, keyed by part number
+    #[serde(default)]
+    pub parts: BTreeMap<u64, String>,
+    pub created_at: u64,
+}
+
+impl UploadSession {
+    pub fn new(file_id: String, upload_id: String, chunk_size: u64, chunk_count: u64) -> Self {
+        Self {
+            file_id,
+            upload_id,
+            chunk_size,
+            chunk_count,
+            parts: BTreeMap::new(),
+            created_at: unix_timestamp(),
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        unix_timestamp().saturating_sub(self.created_at) >= SESSION_TTL
+    }
+}
+
+/// Upload sessions persisted on disk so that interrupted uploads
+/// can be resumed by a retried PUT, even after a restart
+#[derive(Debug, Clone, Default)]
+pub struct UploadSessions {
+    dir: Option<PathBuf>,
+}
+
+impl UploadSessions {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+
+    fn session_file(&self, parent_file_id: &str, name: &str, size: u64) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        let key = format!("{}/{}/{}", parent_file_id, name, size);
+        let hash = Sha1::digest(key.as_bytes());
+        Some(dir.join(format!("{:x}.json", hash)))
+    }
+
+    pub async fn load(&self, parent_file_id: &str, name: &str, size: u64) -> Option<UploadSession> {
+        let file = self.session_file(parent_file_id, name, size)?;
+        let content = tokio::fs::read(&file).await.ok()?;
+        match serde_json::from_slice::<UploadSession>(&content) {
+            Ok(session) if !session.is_expired() => Some(session),
+            Ok(_) => {
+                debug!(file = %file.display(), "upload session expired");
+                let _ = tokio::fs::remove_file(&file).await;
+                None
+            }
+            Err(err) => {
+                warn!(file = %file.display(), error = %err, "invalid upload session");
+                let _ = tokio::fs::remove_file(&file).await;
+                None
+            }
+        }
+    }
+
+    pub async fn save(
+        &self,
+        parent_file_id: &str,
+        name: &str,
+        size: u64,
+        session: &UploadSession,
+    ) -> Result<()> {
+        if let Some(file) = self.session_file(parent_file_id, name, size) {
+            if let Some(dir) = file.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            let content = serde_json::to_vec(session)?;
+            // write to a temporary file first so that a crash never leaves a truncated session
+            let tmp_file = file.with_extension("json.tmp");
+            tokio::fs::write(&tmp_file, content).await?;
+            tokio::fs::rename(&tmp_file, &file).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn remove(&self, parent_file_id: &str, name: &str, size: u64) {
+        if let Some(file) = self.session_file(parent_file_id, name, size) {
+            let _ = tokio::fs::remove_file(&file).await;
+        }
+    }
+}
+
+/// Body of an upload of unknown size, written to local disk first
+/// so that it can be measured and hashed before uploading
+pub struct Spool {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    hasher: Sha1,
+}
+
+impl Spool {
+    pub async fn create(dir: &Path) -> Result<Self> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+        tokio::fs::create_dir_all(dir).await?;
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_nanos();
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!("{:x}-{}.spool", nanos, id));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await?;
+        debug!(path = %path.display(), "spool created");
+        Ok(Self {
+            path,
+            file,
+            size: 0,
+            hasher: Sha1::new(),
+        })
+    }
+
+    pub async fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.file.write_all(data).await?;
+        self.hasher.update(data);
+        self.size += data.len() as u64;
+        Ok(())
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Finish writing and rewind for reading, returns the upper case sha1 hex of the content
+    pub async fn finish(&mut self) -> Result<String> {
+        self.file.flush().await?;
+        self.file.seek(SeekFrom::Start(0)).await?;
+        Ok(format!("{:X}", self.hasher.finalize_reset()))
+    }
+
+    /// Read up to `len` bytes at `offset` without moving the read position
+    pub async fn read_at(&mut self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let pos = self.file.stream_position().await?;
+        self.file.seek(SeekFrom::Start(offset)).await?;
+        let mut buf = Vec::with_capacity(len);
+        (&mut self.file)
+            .take(len as u64)
+            .read_to_end(&mut buf)
+            .await?;
+        self.file.seek(SeekFrom::Start(pos)).await?;
+        Ok(buf)
+    }
+
+    /// Read the next bytes into `buf`, returns 0 at the end of the file
+    pub async fn read_buf(&mut self, buf: &mut BytesMut) -> Result<usize> {
+        Ok(self.file.read_buf(buf).await?)
+    }
+}
+
+impl fmt::Debug for Spool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spool")
+            .field("path", &self.path)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            warn!(path = %self.path.display(), error = %err, "remove spool failed");
+        }
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
@@ -0,0 +1,209 @@
+//! WebDAV user accounts with a home directory and write permissions per path prefix
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use dav_server::fs::DavFileSystem;
+use serde::Deserialize;
+
+use crate::auth::{check_password_hash, constant_time_eq, VerifiedPasswords};
+
+/// Users file loaded from `--users`
+///
+/// ```toml
+/// [users.alice]
+/// password_hash = "$2b$12$..."
+/// home = "/alice"
+/// read_only = true
+/// rules = [{ path = "/upload", access = "rw" }]
+/// bearer_tokens = ["..."]
+/// soft_quota = 500000000000
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UsersFile {
+    #[serde(default)]
+    users: BTreeMap<String, UserConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UserConfig {
+    password_hash: String,
+    /// Directory the user sees as `/`
+    #[serde(default = "default_home")]
+    home: String,
+    /// Access to paths not matched by any rule
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default)]
+    rules: Vec<PathRule>,
+    /// Static API tokens accepted with bearer authentication
+    #[serde(default)]
+    bearer_tokens: Vec<String>,
+    /// Usage of the whole drive in bytes the user's uploads may take it to, only lowers the
+    /// limit of the served drive
+    soft_quota: Option<u64>,
+}
+
+fn default_home() -> String {
+    "/".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PathRule {
+    /// Path prefix as seen by the user
+    path: String,
+    access: Access,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    /// Read only
+    Ro,
+    /// Read and write
+    Rw,
+}
+
+/// Write permissions by path prefix, the longest matching prefix wins
+#[derive(Debug, Clone, Default)]
+pub struct AccessRules {
+    /// Path of the file system root as seen by the user
+    prefix: PathBuf,
+    read_only: bool,
+    rules: Vec<(PathBuf, Access)>,
+}
+
+impl AccessRules {
+    pub fn new(read_only: bool, rules: Vec<(PathBuf, Access)>) -> Self {
+        Self {
+            prefix: PathBuf::from("/"),
+            read_only,
+            rules,
+        }
+    }
+
+    /// Rules for a file system mounted at `prefix` of the user's view
+    pub fn with_prefix(&self, prefix: &Path) -> Self {
+        Self {
+            prefix: prefix.to_path_buf(),
+            ..self.clone()
+        }
+    }
+
+    pub fn can_write(&self, path: &Path) -> bool {
+        let path = self.prefix.join(path.strip_prefix("/").unwrap_or(path));
+        let access = self
+            .rules
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.components().count())
+            .map(|(_, access)| *access);
+        match access {
+            Some(access) => access == Access::Rw,
+            None => !self.read_only,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct User {
+    pub name: String,
+    password_hash: String,
+    bearer_tokens: Vec<String>,
+    /// Home directory in the served file system
+    pub home: PathBuf,
+    pub access: AccessRules,
+    pub soft_quota: Option<u64>,
+}
+
+impl User {
+    /// The same user with a different home directory
+    pub fn with_home(&self, home: PathBuf) -> Self {
+        Self {
+            home,
+            ..self.clone()
+        }
+    }
+}
+
+/// File systems that can be restricted to the home directory and permissions of a user
+pub trait UserFileSystem: Send + Sync {
+    fn for_user(&self, user: &User) -> Result<Box<dyn DavFileSystem>>;
+}
+
+pub struct Users {
+    users: BTreeMap<String, User>,
+    verified: VerifiedPasswords,
+}
+
+impl Users {
+    pub async fn load(path: &Path) -> Result<Self> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("read users file {} failed", path.display()))?;
+        Self::parse(&content).with_context(|| format!("parse users file {} failed", path.display()))
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let file: UsersFile = toml::from_str(content)?;
+        if file.users.is_empty() {
+            bail!("no users defined");
+        }
+        let users = file
+            .users
+            .into_iter()
+            .map(|(name, config)| {
+                if !config.home.starts_with('/') {
+                    bail!("home of user `{}` must be an absolute path", name);
+                }
+                check_password_hash(&config.password_hash)
+                    .with_context(|| format!("password hash of user `{}`", name))?;
+                let rules = config
+                    .rules
+                    .into_iter()
+                    .map(|rule| (Path::new("/").join(rule.path), rule.access))
+                    .collect();
+                let user = User {
+                    name: name.clone(),
+                    password_hash: config.password_hash,
+                    bearer_tokens: config.bearer_tokens,
+                    home: PathBuf::from(config.home),
+                    access: AccessRules::new(config.read_only, rules),
+                    soft_quota: config.soft_quota,
+                };
+                Ok((name, user))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            users,
+            verified: VerifiedPasswords::default(),
+        })
+    }
+
+    /// Find the user with the given name and password
+    pub fn authenticate(&self, name: &str, password: &str) -> Option<&User> {
+        let user = self.users.get(name)?;
+        self.verified
+            .verify(name, password, &user.password_hash)
+            .then_some(user)
+    }
+
+    /// Find the user owning a bearer token
+    pub fn authenticate_token(&self, token: &str) -> Option<&User> {
+        self.users.values().find(|user| {
+            user.bearer_tokens.iter().fold(false, |found, expected| {
+                found | constant_time_eq(expected, token)
+            })
+        })
+    }
+
+    pub fn has_bearer_tokens(&self) -> bool {
+        self.users
+            .values()
+            .any(|user| !user.bearer_tokens.is_empty())
+    }
+}
@@ -1,13 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::{Debug, Formatter};
 use std::io::{Cursor, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::Result;
-use bytes::{Buf, BufMut, Bytes, BytesMut};
-use dashmap::DashMap;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::{Buf, Bytes, BytesMut};
+use dashmap::{mapref::entry::Entry, DashMap};
 use dav_server::{
     davpath::DavPath,
     fs::{
@@ -15,31 +16,178 @@ use dav_server::{
         ReadDirMeta,
     },
 };
-use futures_util::future::{ready, FutureExt};
+use futures_util::future::{ready, BoxFuture, FutureExt, Shared};
 use path_slash::PathBufExt;
-use tracing::{debug, error, trace, warn};
+use sha1::{Digest, Sha1};
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    task::{JoinHandle, JoinSet},
+};
+use tracing::{debug, error, info, trace, warn};
 use zip::write::{FileOptions, ZipWriter};
 
 use crate::{
-    cache::Cache,
-    drive::{model::GetFileDownloadUrlResponse, AliyunDrive, AliyunFile, DateTime, FileType},
+    block_cache::BlockCache,
+    cache::{Cache, Change},
+    config::LiveSettings,
+    drive::{
+        fs_error,
+        model::{ContentHash, GetFileDownloadUrlResponse, ListFileItem},
+        AliyunDrive, AliyunFile, DateTime, FileType,
+    },
+    upload::{Spool, UploadSession, UploadSessions},
+    users::{AccessRules, User, UserFileSystem},
 };
 
+/// Name of the virtual folder listing the recycle bin
+const TRASH_DIR: &str = ".trash";
+/// Name of the virtual folder whose subfolders list the results of their names as queries
+const SEARCH_DIR: &str = ".search";
+/// Most results listed for a search
+const SEARCH_MAX_RESULTS: usize = 500;
+/// How long the drive's usage is trusted before uploads fetch it again
+const QUOTA_TTL: Duration = Duration::from_secs(60);
+
+/// A path inside one of the virtual folders
+enum VirtualPath {
+    TrashRoot,
+    TrashItem(String),
+    SearchRoot,
+    SearchQuery(String),
+    SearchResult(String, String),
+    /// Below a listed folder, which is listed without children
+    Nested,
+}
+
+/// A file listed in a virtual folder
+struct ListedItem {
+    /// The file named as listed, items sharing a name get their file id appended
+    file: AliyunFile,
+    /// Actual name of the file
+    name: String,
+    parent_file_id: Option<String>,
+}
+
+impl ListedItem {
+    fn list(items: Vec<ListFileItem>) -> Vec<Self> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for item in &items {
+            *counts.entry(item.name.clone()).or_default() += 1;
+        }
+        items
+            .into_iter()
+            .map(|item| {
+                let name = item.name.clone();
+                let parent_file_id = item.parent_file_id.clone();
+                let mut file = AliyunFile::from(item);
+                if counts[&name] > 1 {
+                    file.name = format!("{} ({})", name, file.id);
+                }
+                Self {
+                    file,
+                    name,
+                    parent_file_id,
+                }
+            })
+            .collect()
+    }
+
+    fn find(items: Vec<Self>, name: &str) -> Result<Self, FsError> {
+        items
+            .into_iter()
+            .find(|item| item.file.name == name)
+            .ok_or(FsError::NotFound)
+    }
+}
+
+/// Query in the drive's search syntax for the name of a `/.search` subfolder,
+/// names without quotes or comparisons are matched against file names
+fn search_query(name: &str) -> String {
+    if name.contains(['"', '=', '<', '>']) {
+        name.to_string()
+    } else {
+        format!("name match \"{}\"", name)
+    }
+}
+
+/// A folder of a virtual folder
+fn virtual_dir(name: &str) -> AliyunFile {
+    let mut dir = AliyunFile::new_root();
+    dir.name = name.to_string();
+    dir
+}
+
+/// Used and total bytes of a drive and when they were fetched
+type CachedQuota = Arc<Mutex<Option<(Instant, u64, u64)>>>;
+
+type Listing = Shared<BoxFuture<'static, Result<Vec<AliyunFile>, Arc<anyhow::Error>>>>;
+
+/// Memory budget in bytes for buffered parts of uploads, shared by all file systems it is given to
+#[derive(Debug, Clone)]
+pub struct UploadMemory {
+    semaphore: Arc<Semaphore>,
+    limit: usize,
+}
+
+impl UploadMemory {
+    pub fn new(limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            limit,
+        }
+    }
+
+    async fn acquire(&self, size: usize) -> OwnedSemaphorePermit {
+        // a single part larger than the budget must not wait forever
+        let permits = size.clamp(1, self.limit).min(u32::MAX as usize) as u32;
+        self.semaphore
+            .clone()
+            .acquire_many_owned(permits)
+            .await
+            .expect("upload memory semaphore closed")
+    }
+}
+
+impl Default for UploadMemory {
+    fn default() -> Self {
+        Self::new(256 * 1024 * 1024)
+    }
+}
+
 #[derive(Clone)]
 pub struct AliyunDriveFileSystem {
-    drive: AliyunDrive,
+    pub(crate) drive: AliyunDrive,
     pub(crate) dir_cache: Cache,
     uploading: Arc<DashMap<String, Vec<AliyunFile>>>,
+    /// In-flight directory listings by directory id
+    listings: Arc<DashMap<String, Listing>>,
+    quota: CachedQuota,
+    /// Highest usage of the whole drive in bytes uploads may reach, below its size
+    soft_quota: Option<u64>,
+    upload_sessions: UploadSessions,
+    spool_dir: Option<PathBuf>,
+    read_cache: Option<BlockCache>,
+    /// Number of windows downloaded ahead of sequential reads
+    read_ahead: usize,
     root: PathBuf,
     no_trash: bool,
+    /// Expose the recycle bin as the virtual `/.trash` folder
+    trash_folder: bool,
+    /// Expose search results as the virtual `/.search/<query>` folders
+    search_folder: bool,
     read_only: bool,
-    upload_buffer_size: usize,
+    /// Write permissions of the WebDAV user
+    access: AccessRules,
+    live: Arc<LiveSettings>,
+    upload_concurrency: usize,
+    /// Memory budget shared by part uploads of all open files
+    upload_memory: UploadMemory,
     skip_upload_same_size: bool,
     prefer_http_download: bool,
 }
 
 impl AliyunDriveFileSystem {
-    #[allow(clippy::too_many_arguments)]
     pub fn new(drive: AliyunDrive, root: String, cache_size: u64, cache_ttl: u64) -> Result<Self> {
         let dir_cache = Cache::new(cache_size, cache_ttl);
         debug!("dir cache initialized");
@@ -52,10 +200,22 @@ impl AliyunDriveFileSystem {
             drive,
             dir_cache,
             uploading: Arc::new(DashMap::new()),
+            listings: Arc::new(DashMap::new()),
+            quota: CachedQuota::default(),
+            soft_quota: None,
+            upload_sessions: UploadSessions::default(),
+            spool_dir: None,
+            read_cache: None,
+            read_ahead: 2,
             root,
             no_trash: false,
+            trash_folder: false,
+            search_folder: false,
             read_only: false,
-            upload_buffer_size: 16 * 1024 * 1024,
+            access: AccessRules::default(),
+            live: Arc::new(LiveSettings::default()),
+            upload_concurrency: 4,
+            upload_memory: UploadMemory::default(),
             skip_upload_same_size: false,
             prefer_http_download: false,
         })
@@ -66,13 +226,65 @@ impl AliyunDriveFileSystem {
         self
     }
 
+    pub fn set_access(&mut self, access: AccessRules) -> &mut Self {
+        self.access = access;
+        self
+    }
+
     pub fn set_no_trash(&mut self, no_trash: bool) -> &mut Self {
         self.no_trash = no_trash;
         self
     }
 
+    pub fn set_trash_folder(&mut self, trash_folder: bool) -> &mut Self {
+        self.trash_folder = trash_folder;
+        self
+    }
+
+    pub fn set_search_folder(&mut self, search_folder: bool) -> &mut Self {
+        self.search_folder = search_folder;
+        self
+    }
+
+    /// Share settings changed while serving, e.g. by a config reload
+    pub fn set_live_settings(&mut self, live: Arc<LiveSettings>) -> &mut Self {
+        self.live = live;
+        self
+    }
+
+    #[cfg(test)]
     pub fn set_upload_buffer_size(&mut self, upload_buffer_size: usize) -> &mut Self {
-        self.upload_buffer_size = upload_buffer_size;
+        self.live.set_upload_buffer_size(upload_buffer_size);
+        self
+    }
+
+    pub fn set_upload_concurrency(&mut self, upload_concurrency: usize) -> &mut Self {
+        self.upload_concurrency = upload_concurrency.max(1);
+        self
+    }
+
+    pub fn set_upload_memory(&mut self, upload_memory: UploadMemory) -> &mut Self {
+        self.upload_memory = upload_memory;
+        self
+    }
+
+    pub fn set_spool_dir(&mut self, dir: Option<PathBuf>) -> &mut Self {
+        self.spool_dir = dir;
+        self
+    }
+
+    pub fn set_read_cache(&mut self, read_cache: Option<BlockCache>) -> &mut Self {
+        self.read_cache = read_cache;
+        self
+    }
+
+    pub fn set_read_ahead(&mut self, read_ahead: usize) -> &mut Self {
+        self.read_ahead = read_ahead;
+        self
+    }
+
+    pub fn set_upload_sessions_dir(&mut self, dir: Option<PathBuf>) -> &mut Self {
+        self.upload_sessions = UploadSessions::new(dir);
         self
     }
 
@@ -81,11 +293,68 @@ impl AliyunDriveFileSystem {
         self
     }
 
+    /// Limit the usage uploads may take the whole drive to, replacing the current limit
+    pub fn set_soft_quota(&mut self, soft_quota: Option<u64>) -> &mut Self {
+        self.soft_quota = soft_quota;
+        self
+    }
+
+    /// Lower the soft quota to `soft_quota`, a lower existing limit is kept
+    pub fn restrict_soft_quota(&mut self, soft_quota: Option<u64>) -> &mut Self {
+        self.soft_quota = match (self.soft_quota, soft_quota) {
+            (Some(current), Some(limit)) => Some(current.min(limit)),
+            (current, limit) => current.or(limit),
+        };
+        self
+    }
+
     pub fn set_prefer_http_download(&mut self, prefer_http_download: bool) -> &mut Self {
         self.prefer_http_download = prefer_http_download;
         self
     }
 
+    /// Used and total bytes of the drive, fetched at most every [`QUOTA_TTL`]
+    async fn quota(&self) -> Result<(u64, u64)> {
+        if let Some((fetched, used, total)) = *self.quota.lock().unwrap() {
+            if fetched.elapsed() < QUOTA_TTL {
+                return Ok((used, total));
+            }
+        }
+        let (used, total) = self.drive.get_quota().await?;
+        *self.quota.lock().unwrap() = Some((Instant::now(), used, total));
+        Ok((used, total))
+    }
+
+    /// Bytes uploads may take the drive to
+    fn quota_limit(&self, total: u64) -> u64 {
+        self.soft_quota.map_or(total, |limit| limit.min(total))
+    }
+
+    /// Refuse an upload of `size` more bytes that doesn't fit below the quota
+    async fn admit_upload(&self, path: &Path, size: u64) -> Result<(), FsError> {
+        let (used, total) = match self.quota().await {
+            Ok(quota) => quota,
+            Err(err) => {
+                // the drive has the last word
+                warn!(error = %err, "get quota failed");
+                return Ok(());
+            }
+        };
+        let limit = self.quota_limit(total);
+        if used.saturating_add(size) > limit {
+            info!(path = %path.display(), size = size, used = used, limit = limit, "upload refused, quota exceeded");
+            return Err(FsError::InsufficientStorage);
+        }
+        Ok(())
+    }
+
+    /// Count an upload of `size` bytes in the cached usage
+    fn add_quota_used(&self, size: u64) {
+        if let Some((_, used, _)) = self.quota.lock().unwrap().as_mut() {
+            *used += size;
+        }
+    }
+
     fn find_in_cache(&self, path: &Path) -> Result<Option<AliyunFile>, FsError> {
         if let Some(parent) = path.parent() {
             let parent_str = parent.to_string_lossy();
@@ -110,80 +379,73 @@ impl AliyunDriveFileSystem {
     }
 
     async fn get_file(&self, path: PathBuf) -> Result<Option<AliyunFile>, FsError> {
-        let path_str = path.to_slash_lossy();
         let file = self.find_in_cache(&path)?;
         if let Some(file) = file {
             trace!(path = %path.display(), file_id = %file.id, "file found in cache");
-            Ok(Some(file))
+            return Ok(Some(file));
+        }
+        trace!(path = %path.display(), "file not found in cache");
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Ok(None);
+        };
+        let files = match self.read_dir_and_cache(parent.to_path_buf()).await {
+            Ok(files) => files,
+            Err(FsError::NotFound) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        Ok(files.into_iter().find(|f| f.name.as_str() == name))
+    }
+
+    /// File id of the directory at `path`, listing each level below the deepest known ancestor once
+    async fn resolve_dir_id(&self, path: &Path) -> Result<String, FsError> {
+        let index = self.dir_cache.index();
+        if let Some(id) = index.get(path) {
+            return Ok(id);
+        }
+        let (mut dir, mut dir_id) = index.deepest(path);
+        let rest = path.strip_prefix(&dir).map_err(|_| FsError::NotFound)?;
+        for name in rest.iter() {
+            let dir_str = dir.to_slash_lossy().into_owned();
+            let files = match self.dir_cache.get(&dir_str) {
+                Some(files) => files,
+                None => self
+                    .list_files_and_cache(dir_str.clone(), dir_id)
+                    .await
+                    .map_err(|err| self.list_error(&dir, &err))?,
+            };
+            let child = files
+                .into_iter()
+                .find(|f| f.name.as_str() == name && matches!(f.r#type, FileType::Folder))
+                .ok_or(FsError::NotFound)?;
+            dir.push(name);
+            dir_id = child.id;
+        }
+        Ok(dir_id)
+    }
+
+    /// Map an error listing the directory at `path`, forgetting its id if it is gone
+    fn list_error(&self, path: &Path, err: &anyhow::Error) -> FsError {
+        let fs_err = fs_error(err);
+        if fs_err == FsError::NotFound {
+            debug!(path = %path.display(), "read_dir not found");
+            self.dir_cache.index().remove(path);
         } else {
-            trace!(path = %path.display(), "file not found in cache");
-            if let Ok(Some(file)) = self.drive.get_by_path(&path_str).await {
-                return Ok(Some(file));
-            }
-
-            // path may contain whitespaces which get_by_path can't handle
-            // so we try to find it in directory
-            let parts: Vec<&str> = path_str.split('/').collect();
-            let parts_len = parts.len();
-            let filename = parts[parts_len - 1];
-            let mut prefix = PathBuf::from("/");
-            for part in &parts[0..parts_len - 1] {
-                let parent = prefix.join(part);
-                prefix = parent.clone();
-                let files = self.read_dir_and_cache(parent).await?;
-                if let Some(file) = files.iter().find(|f| f.name == filename) {
-                    trace!(path = %path.display(), file_id = %file.id, "file found in cache");
-                    return Ok(Some(file.clone()));
-                }
-            }
-            Ok(None)
+            error!(path = %path.display(), error = %err, "list_files_and_cache failed");
         }
+        fs_err
     }
 
     async fn read_dir_and_cache(&self, path: PathBuf) -> Result<Vec<AliyunFile>, FsError> {
         let path_str = path.to_slash_lossy();
-        let parent_file_id = if path_str == "/" {
-            "root".to_string()
-        } else {
-            match self.find_in_cache(&path) {
-                Ok(Some(file)) => file.id,
-                _ => match self.drive.get_by_path(&path_str).await {
-                    Ok(Some(file)) => file.id,
-                    Ok(None) => return Err(FsError::NotFound),
-                    Err(err) => {
-                        error!(path = %path_str, error = %err, "get_by_path failed");
-                        return Err(FsError::GeneralFailure);
-                    }
-                },
-            }
-        };
+        let parent_file_id = self.resolve_dir_id(&path).await?;
         let mut files = if let Some(files) = self.dir_cache.get(&path_str) {
             debug!(path = %path_str, "read_dir cache hit");
             files
         } else {
-            let res = self
-                .list_files_and_cache(path_str.to_string(), parent_file_id.clone())
-                .await;
-            match res {
-                Ok(files) => {
-                    debug!(path = %path_str, "read_dir cache miss");
-                    files
-                }
-                Err(err) => {
-                    if let Some(req_err) = err.downcast_ref::<reqwest::Error>() {
-                        if matches!(req_err.status(), Some(reqwest::StatusCode::NOT_FOUND)) {
-                            debug!(path = %path_str, "read_dir not found");
-                            return Err(FsError::NotFound);
-                        } else {
-                            error!(path = %path_str, error = %err, "list_files_and_cache failed");
-                            return Err(FsError::GeneralFailure);
-                        }
-                    } else {
-                        error!(path = %path_str, error = %err, "list_files_and_cache failed");
-                        return Err(FsError::GeneralFailure);
-                    }
-                }
-            }
+            debug!(path = %path_str, "read_dir cache miss");
+            self.list_files_and_cache(path_str.to_string(), parent_file_id.clone())
+                .await
+                .map_err(|err| self.list_error(&path, &err))?
         };
         let uploading_files = self.list_uploading_files(&parent_file_id);
         if !uploading_files.is_empty() {
@@ -200,6 +462,85 @@ impl AliyunDriveFileSystem {
             .unwrap_or_default()
     }
 
+    /// Files being uploaded with the ids of their parent directories
+    pub(crate) fn uploads(&self) -> Vec<(String, AliyunFile)> {
+        self.uploading
+            .iter()
+            .flat_map(|entry| {
+                let parent_file_id = entry.key().clone();
+                entry
+                    .value()
+                    .iter()
+                    .map(move |file| (parent_file_id.clone(), file.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Invalidate the cached listing of `path`, relative to the root, or of every directory below it
+    pub(crate) async fn invalidate_cache(&self, path: &str, recursive: bool) {
+        let path = match path.trim_start_matches('/') {
+            "" => self.root.clone(),
+            rel_path => self.root.join(rel_path),
+        };
+        if recursive {
+            self.dir_cache.invalidate_subtree(&path).await;
+        } else {
+            self.dir_cache.invalidate(&path).await;
+        }
+    }
+
+    /// Poll the drive's change feed every `interval` in the background,
+    /// invalidating the cached listings of changed directories
+    pub fn watch_changes(&self, interval: Duration) {
+        let fs = self.clone();
+        tokio::spawn(async move {
+            // resume from the changes the stored listings are up to date with
+            let mut cursor = fs.dir_cache.saved_cursor();
+            loop {
+                match fs.poll_changes(cursor.take()).await {
+                    Ok(next) => {
+                        fs.dir_cache.save_cursor(&next).await;
+                        cursor = Some(next);
+                    }
+                    Err(err) => warn!(error = %err, "poll drive changes failed"),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    /// Apply the changes after `cursor` to the directory cache and return the cursor to continue from,
+    /// without a cursor only the latest one is fetched
+    pub(crate) async fn poll_changes(&self, cursor: Option<String>) -> Result<String> {
+        let Some(cursor) = cursor else {
+            return self.drive.get_last_cursor().await;
+        };
+        let (items, cursor) = match self.drive.list_delta_all(&cursor).await {
+            Ok(res) => res,
+            Err(err) => {
+                // changes may have been missed, e.g. when the cursor expired
+                self.dir_cache.invalidate_all();
+                return Err(err);
+            }
+        };
+        if !items.is_empty() {
+            debug!(count = items.len(), "drive changed");
+            let changes: Vec<Change> = items
+                .into_iter()
+                .map(|item| {
+                    trace!(op = %item.op, file_id = %item.file_id, "drive change");
+                    Change {
+                        file_id: item.file_id,
+                        parent_file_id: item.file.map(|file| file.parent_file_id),
+                    }
+                })
+                .collect();
+            self.dir_cache.invalidate_changes(&changes).await;
+        }
+        Ok(cursor)
+    }
+
     fn remove_uploading_file(&self, parent_file_id: &str, name: &str) {
         if let Some(mut files) = self.uploading.get_mut(parent_file_id) {
             if let Some(index) = files.iter().position(|x| x.name == name) {
@@ -208,19 +549,219 @@ impl AliyunDriveFileSystem {
         }
     }
 
+    /// List a directory and cache it, concurrent callers for the same directory share one listing
     async fn list_files_and_cache(
         &self,
         path_str: String,
         parent_file_id: String,
-    ) -> Result<Vec<AliyunFile>> {
-        let files = self.drive.list_all(&parent_file_id).await?;
-        self.cache_dir(path_str, files.clone()).await;
-        Ok(files)
+    ) -> Result<Vec<AliyunFile>, Arc<anyhow::Error>> {
+        let listing = match self.listings.entry(parent_file_id.clone()) {
+            Entry::Occupied(entry) => {
+                debug!(path = %path_str, "join in-flight listing");
+                entry.get().clone()
+            }
+            Entry::Vacant(entry) => {
+                let fs = self.clone();
+                let listing = async move {
+                    let res = fs.drive.list_all(&parent_file_id).await;
+                    if let Ok(files) = &res {
+                        fs.cache_dir(path_str, parent_file_id.clone(), files.clone())
+                            .await;
+                    }
+                    fs.listings.remove(&parent_file_id);
+                    res.map_err(Arc::new)
+                }
+                .boxed()
+                .shared();
+                entry.insert(listing.clone());
+                listing
+            }
+        };
+        listing.await
     }
 
-    async fn cache_dir(&self, dir_path: String, files: Vec<AliyunFile>) {
+    async fn cache_dir(&self, dir_path: String, dir_id: String, files: Vec<AliyunFile>) {
         trace!(path = %dir_path, count = files.len(), "cache dir");
-        self.dir_cache.insert(dir_path, files).await;
+        self.dir_cache.insert(dir_path, dir_id, files).await;
+    }
+
+    /// Download `count` bytes of a file at `pos`, through the read cache when enabled
+    async fn download_range(
+        &self,
+        file_id: &str,
+        content_hash: Option<&str>,
+        size: u64,
+        url: reqwest::Url,
+        pos: u64,
+        count: usize,
+    ) -> Result<Bytes, FsError> {
+        let (Some(read_cache), Some(content_hash)) = (self.read_cache.as_ref(), content_hash)
+        else {
+            return self
+                .drive
+                .download(url.clone(), Some((pos, count)))
+                .await
+                .map_err(|err| {
+                    error!(url = %url, error = %err, "download file failed");
+                    FsError::NotFound
+                });
+        };
+        // serve whole blocks from the read cache
+        let block_size = read_cache.block_size();
+        let end = (pos + count as u64).min(size);
+        let mut pos = pos;
+        let mut content = BytesMut::with_capacity(end.saturating_sub(pos) as usize);
+        while pos < end {
+            let index = pos / block_size;
+            let block_start = index * block_size;
+            let block = match read_cache.get(file_id, content_hash, index).await {
+                Some(block) => block,
+                None => {
+                    let len = (size - block_start).min(block_size) as usize;
+                    let block = self
+                        .drive
+                        .download(url.clone(), Some((block_start, len)))
+                        .await
+                        .map_err(|err| {
+                            error!(url = %url, error = %err, "download file failed");
+                            FsError::NotFound
+                        })?;
+                    if let Err(err) = read_cache
+                        .insert(file_id, content_hash, index, &block)
+                        .await
+                    {
+                        warn!(file_id = %file_id, error = %err, "cache file block failed");
+                    }
+                    block
+                }
+            };
+            let from = (pos - block_start) as usize;
+            let to = ((end - block_start) as usize).min(block.len());
+            if from >= to {
+                break;
+            }
+            content.extend_from_slice(&block[from..to]);
+            pos = block_start + to as u64;
+        }
+        Ok(content.freeze())
+    }
+
+    fn is_writable(&self, dav_path: &DavPath) -> bool {
+        !self.read_only && !self.live.read_only() && self.access.can_write(&dav_path.as_pathbuf())
+    }
+
+    /// Where `dav_path` points inside the virtual folders, `None` if it is outside of them
+    ///
+    /// The recycle bin and search are drive wide, so they are only exposed when serving the whole drive.
+    fn virtual_path(&self, dav_path: &DavPath) -> Option<VirtualPath> {
+        if self.root.parent().is_some() {
+            return None;
+        }
+        let path = dav_path.as_rel_ospath();
+        let mut components = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned());
+        let dir = components.next()?;
+        let (a, b, c) = (components.next(), components.next(), components.next());
+        if dir == TRASH_DIR && self.trash_folder {
+            return Some(match (a, b) {
+                (None, _) => VirtualPath::TrashRoot,
+                (Some(name), None) => VirtualPath::TrashItem(name),
+                _ => VirtualPath::Nested,
+            });
+        }
+        if dir == SEARCH_DIR && self.search_folder {
+            return Some(match (a, b, c) {
+                (None, _, _) => VirtualPath::SearchRoot,
+                (Some(query), None, _) => VirtualPath::SearchQuery(query),
+                (Some(query), Some(name), None) => VirtualPath::SearchResult(query, name),
+                _ => VirtualPath::Nested,
+            });
+        }
+        None
+    }
+
+    async fn trash_items(&self) -> Result<Vec<ListedItem>, FsError> {
+        let items = self.drive.list_recyclebin_all().await.map_err(|err| {
+            error!(error = %err, "list recyclebin failed");
+            fs_error(&err)
+        })?;
+        Ok(ListedItem::list(items))
+    }
+
+    async fn trash_item(&self, name: &str) -> Result<ListedItem, FsError> {
+        ListedItem::find(self.trash_items().await?, name)
+    }
+
+    async fn search_results(&self, query: &str) -> Result<Vec<ListedItem>, FsError> {
+        let query = search_query(query);
+        let items = self
+            .drive
+            .search_all(&query, SEARCH_MAX_RESULTS)
+            .await
+            .map_err(|err| {
+                error!(query = %query, error = %err, "search failed");
+                fs_error(&err)
+            })?;
+        Ok(ListedItem::list(items))
+    }
+
+    async fn search_result(&self, query: &str, name: &str) -> Result<ListedItem, FsError> {
+        ListedItem::find(self.search_results(query).await?, name)
+    }
+
+    /// Purge an item of the recycle bin for good
+    async fn purge(&self, dav_path: &DavPath, name: &str, is_dir: bool) -> Result<(), FsError> {
+        if !self.is_writable(dav_path) {
+            return Err(FsError::Forbidden);
+        }
+        let item = self.trash_item(name).await?;
+        if is_dir != matches!(item.file.r#type, FileType::Folder) {
+            return Err(FsError::Forbidden);
+        }
+        self.drive
+            .remove_file(&item.file.id, false)
+            .await
+            .map_err(|err| {
+                error!(name = %name, error = %err, "purge trashed file failed");
+                fs_error(&err)
+            })
+    }
+
+    /// Restore an item of the recycle bin and move it to `to` if it was trashed elsewhere
+    async fn restore(&self, name: &str, to_dav: &DavPath) -> Result<(), FsError> {
+        let to = self.normalize_dav_path(to_dav);
+        let item = self.trash_item(name).await?;
+        let to_parent = to.parent().ok_or(FsError::Forbidden)?;
+        let to_parent_file = self
+            .get_file(to_parent.to_path_buf())
+            .await?
+            .ok_or(FsError::NotFound)?;
+        let new_name = to_dav.file_name().ok_or(FsError::Forbidden)?;
+        let file_id = &item.file.id;
+        self.drive.restore_file(file_id).await.map_err(|err| {
+            error!(name = %name, error = %err, "restore trashed file failed");
+            fs_error(&err)
+        })?;
+        let res = if item.parent_file_id.as_deref() != Some(to_parent_file.id.as_str()) {
+            self.drive
+                .move_file(file_id, &to_parent_file.id, Some(new_name))
+                .await
+        } else if item.name != new_name {
+            self.drive.rename_file(file_id, new_name).await
+        } else {
+            Ok(())
+        };
+        res.map_err(|err| {
+            error!(name = %name, to = %to.display(), error = %err, "move restored file failed");
+            fs_error(&err)
+        })?;
+        self.dir_cache.index().insert(&to, file_id);
+        if matches!(item.file.r#type, FileType::Folder) {
+            self.dir_cache.invalidate(&to).await;
+        }
+        self.dir_cache.invalidate_parent(&to).await;
+        Ok(())
     }
 
     fn normalize_dav_path(&self, dav_path: &DavPath) -> PathBuf {
@@ -236,26 +777,56 @@ impl AliyunDriveFileSystem {
     }
 }
 
+impl UserFileSystem for AliyunDriveFileSystem {
+    fn for_user(&self, user: &User) -> Result<Box<dyn DavFileSystem>> {
+        let mut fs = self.clone();
+        fs.root = self
+            .root
+            .join(user.home.strip_prefix("/").unwrap_or(&user.home));
+        fs.access = user.access.clone();
+        fs.restrict_soft_quota(user.soft_quota);
+        Ok(Box::new(fs))
+    }
+}
+
 impl DavFileSystem for AliyunDriveFileSystem {
     fn open<'a>(
         &'a self,
         dav_path: &'a DavPath,
         options: OpenOptions,
-    ) -> FsFuture<Box<dyn DavFile>> {
+    ) -> FsFuture<'a, Box<dyn DavFile>> {
         let path = self.normalize_dav_path(dav_path);
         let mode = if options.write { "write" } else { "read" };
         debug!(path = %path.display(), mode = %mode, "fs: open");
         async move {
+            match self.virtual_path(dav_path) {
+                Some(VirtualPath::SearchResult(query, name)) if !options.write => {
+                    let item = self.search_result(&query, &name).await?;
+                    if matches!(item.file.r#type, FileType::Folder) {
+                        return Err(FsError::Forbidden);
+                    }
+                    let parent_path = path.parent().ok_or(FsError::NotFound)?.to_path_buf();
+                    let mut dav_file = AliyunDavFile::new(
+                        self.clone(),
+                        item.file,
+                        item.parent_file_id.unwrap_or_default(),
+                        parent_path,
+                        0,
+                        None,
+                    );
+                    dav_file.http_download = self.prefer_http_download;
+                    return Ok(Box::new(dav_file) as Box<dyn DavFile>);
+                }
+                Some(_) => return Err(FsError::Forbidden),
+                None => {}
+            }
             if options.append {
                 // Can't support open in write-append mode
                 error!(path = %path.display(), "unsupported write-append mode");
                 return Err(FsError::NotImplemented);
             }
             let parent_path = path.parent().ok_or(FsError::NotFound)?;
-            let parent_file = self
-                .get_file(parent_path.to_path_buf())
-                .await?
-                .ok_or(FsError::NotFound)?;
+            let parent_file_id = self.resolve_dir_id(parent_path).await?;
             let sha1 = options.checksum.and_then(|c| {
                 if let Some((algo, hash)) = c.split_once(':') {
                     if algo.eq_ignore_ascii_case("sha1") {
@@ -271,19 +842,24 @@ impl DavFileSystem for AliyunDriveFileSystem {
                 if options.write && options.create_new {
                     return Err(FsError::Exists);
                 }
-                if options.write && self.read_only {
+                if options.write && !self.is_writable(dav_path) {
                     return Err(FsError::Forbidden);
                 }
+                if let (true, Some(size)) = (options.write, options.size) {
+                    // the new content replaces the old one
+                    self.admit_upload(&path, size.saturating_sub(file.size))
+                        .await?;
+                }
                 AliyunDavFile::new(
                     self.clone(),
                     file,
-                    parent_file.id,
+                    parent_file_id.clone(),
                     parent_path.to_path_buf(),
                     options.size.unwrap_or_default(),
                     sha1,
                 )
             } else if options.write && (options.create || options.create_new) {
-                if self.read_only {
+                if !self.is_writable(dav_path) {
                     return Err(FsError::Forbidden);
                 }
 
@@ -297,6 +873,9 @@ impl DavFileSystem for AliyunDriveFileSystem {
                 if name == ".DS_Store" || name.starts_with("._") {
                     return Err(FsError::NotFound);
                 }
+                if let Some(size) = size {
+                    self.admit_upload(&path, size).await?;
+                }
 
                 let now = SystemTime::now();
                 let file = AliyunFile {
@@ -309,19 +888,37 @@ impl DavFileSystem for AliyunDriveFileSystem {
                     url: None,
                     content_hash: None,
                 };
-                let mut uploading = self.uploading.entry(parent_file.id.clone()).or_default();
-                uploading.push(file.clone());
-                AliyunDavFile::new(
+                let mut uploading = self.uploading.entry(parent_file_id.clone()).or_default();
+                // a retried upload replaces the one that was interrupted
+                if let Some(existing) = uploading.iter_mut().find(|f| f.name == file.name) {
+                    *existing = file.clone();
+                } else {
+                    uploading.push(file.clone());
+                }
+                let mut dav_file = AliyunDavFile::new(
                     self.clone(),
                     file,
-                    parent_file.id,
+                    parent_file_id,
                     parent_path.to_path_buf(),
                     size.unwrap_or(0),
                     sha1,
-                )
+                );
+                dav_file.uploading = true;
+                dav_file
             } else {
                 return Err(FsError::NotFound);
             };
+            if options.write && options.size.is_none() {
+                if let Some(dir) = self.spool_dir.as_ref() {
+                    let spool = Spool::create(dir).await.map_err(|err| {
+                        error!(dir = %dir.display(), error = %err, "create spool failed");
+                        FsError::GeneralFailure
+                    })?;
+                    dav_file.upload_state.spool = Some(spool);
+                } else {
+                    warn!(path = %path.display(), "upload size unknown, set a spool directory to support it");
+                }
+            }
             dav_file.http_download = self.prefer_http_download;
             Ok(Box::new(dav_file) as Box<dyn DavFile>)
         }
@@ -330,13 +927,34 @@ impl DavFileSystem for AliyunDriveFileSystem {
 
     fn read_dir<'a>(
         &'a self,
-        path: &'a DavPath,
+        dav_path: &'a DavPath,
         _meta: ReadDirMeta,
-    ) -> FsFuture<FsStream<Box<dyn DavDirEntry>>> {
-        let path = self.normalize_dav_path(path);
+    ) -> FsFuture<'a, FsStream<Box<dyn DavDirEntry>>> {
+        let path = self.normalize_dav_path(dav_path);
         debug!(path = %path.display(), "fs: read_dir");
         async move {
-            let files = self.read_dir_and_cache(path.clone()).await?;
+            let files = match self.virtual_path(dav_path) {
+                Some(VirtualPath::TrashRoot) => self
+                    .trash_items()
+                    .await?
+                    .into_iter()
+                    .map(|item| item.file)
+                    .collect(),
+                Some(VirtualPath::SearchQuery(query)) => self
+                    .search_results(&query)
+                    .await?
+                    .into_iter()
+                    .map(|item| item.file)
+                    .collect(),
+                // listed folders are listed without their children
+                Some(
+                    VirtualPath::TrashItem(_)
+                    | VirtualPath::SearchRoot
+                    | VirtualPath::SearchResult(..),
+                ) => Vec::new(),
+                Some(VirtualPath::Nested) => return Err(FsError::NotFound),
+                None => self.read_dir_and_cache(path.clone()).await?,
+            };
             let mut v: Vec<Box<dyn DavDirEntry>> = Vec::with_capacity(files.len());
             for file in files {
                 v.push(Box::new(file));
@@ -347,21 +965,31 @@ impl DavFileSystem for AliyunDriveFileSystem {
         .boxed()
     }
 
-    fn metadata<'a>(&'a self, path: &'a DavPath) -> FsFuture<Box<dyn DavMetaData>> {
-        let path = self.normalize_dav_path(path);
+    fn metadata<'a>(&'a self, dav_path: &'a DavPath) -> FsFuture<'a, Box<dyn DavMetaData>> {
+        let path = self.normalize_dav_path(dav_path);
         debug!(path = %path.display(), "fs: metadata");
         async move {
-            let file = self.get_file(path).await?.ok_or(FsError::NotFound)?;
+            let file = match self.virtual_path(dav_path) {
+                Some(VirtualPath::TrashRoot) => virtual_dir(TRASH_DIR),
+                Some(VirtualPath::TrashItem(name)) => self.trash_item(&name).await?.file,
+                Some(VirtualPath::SearchRoot) => virtual_dir(SEARCH_DIR),
+                Some(VirtualPath::SearchQuery(query)) => virtual_dir(&query),
+                Some(VirtualPath::SearchResult(query, name)) => {
+                    self.search_result(&query, &name).await?.file
+                }
+                Some(VirtualPath::Nested) => return Err(FsError::NotFound),
+                None => self.get_file(path).await?.ok_or(FsError::NotFound)?,
+            };
             Ok(Box::new(file) as Box<dyn DavMetaData>)
         }
         .boxed()
     }
 
-    fn create_dir<'a>(&'a self, dav_path: &'a DavPath) -> FsFuture<()> {
+    fn create_dir<'a>(&'a self, dav_path: &'a DavPath) -> FsFuture<'a, ()> {
         let path = self.normalize_dav_path(dav_path);
         debug!(path = %path.display(), "fs: create_dir");
         async move {
-            if self.read_only {
+            if !self.is_writable(dav_path) || self.virtual_path(dav_path).is_some() {
                 return Err(FsError::Forbidden);
             }
 
@@ -375,13 +1003,15 @@ impl DavFileSystem for AliyunDriveFileSystem {
             }
             if let Some(name) = path.file_name() {
                 let name = name.to_string_lossy().into_owned();
-                self.drive
+                let id = self
+                    .drive
                     .create_folder(&parent_file.id, &name)
                     .await
                     .map_err(|err| {
                         error!(path = %path.display(), error = %err, "create folder failed");
-                        FsError::GeneralFailure
+                        fs_error(&err)
                     })?;
+                self.dir_cache.index().insert(&path, &id);
                 self.dir_cache.invalidate(parent_path).await;
                 Ok(())
             } else {
@@ -391,11 +1021,18 @@ impl DavFileSystem for AliyunDriveFileSystem {
         .boxed()
     }
 
-    fn remove_dir<'a>(&'a self, dav_path: &'a DavPath) -> FsFuture<()> {
+    fn remove_dir<'a>(&'a self, dav_path: &'a DavPath) -> FsFuture<'a, ()> {
         let path = self.normalize_dav_path(dav_path);
         debug!(path = %path.display(), "fs: remove_dir");
         async move {
-            if self.read_only {
+            match self.virtual_path(dav_path) {
+                Some(VirtualPath::TrashItem(name)) => {
+                    return self.purge(dav_path, &name, true).await
+                }
+                Some(_) => return Err(FsError::Forbidden),
+                None => {}
+            }
+            if !self.is_writable(dav_path) {
                 return Err(FsError::Forbidden);
             }
 
@@ -411,8 +1048,9 @@ impl DavFileSystem for AliyunDriveFileSystem {
                 .await
                 .map_err(|err| {
                     error!(path = %path.display(), error = %err, "remove directory failed");
-                    FsError::GeneralFailure
+                    fs_error(&err)
                 })?;
+            self.dir_cache.index().remove(&path);
             self.dir_cache.invalidate(&path).await;
             self.dir_cache.invalidate_parent(&path).await;
             Ok(())
@@ -420,11 +1058,18 @@ impl DavFileSystem for AliyunDriveFileSystem {
         .boxed()
     }
 
-    fn remove_file<'a>(&'a self, dav_path: &'a DavPath) -> FsFuture<()> {
+    fn remove_file<'a>(&'a self, dav_path: &'a DavPath) -> FsFuture<'a, ()> {
         let path = self.normalize_dav_path(dav_path);
         debug!(path = %path.display(), "fs: remove_file");
         async move {
-            if self.read_only {
+            match self.virtual_path(dav_path) {
+                Some(VirtualPath::TrashItem(name)) => {
+                    return self.purge(dav_path, &name, false).await
+                }
+                Some(_) => return Err(FsError::Forbidden),
+                None => {}
+            }
+            if !self.is_writable(dav_path) {
                 return Err(FsError::Forbidden);
             }
 
@@ -440,20 +1085,24 @@ impl DavFileSystem for AliyunDriveFileSystem {
                 .await
                 .map_err(|err| {
                     error!(path = %path.display(), error = %err, "remove file failed");
-                    FsError::GeneralFailure
+                    fs_error(&err)
                 })?;
+            self.dir_cache.index().remove(&path);
             self.dir_cache.invalidate_parent(&path).await;
             Ok(())
         }
         .boxed()
     }
 
-    fn copy<'a>(&'a self, from_dav: &'a DavPath, to_dav: &'a DavPath) -> FsFuture<()> {
+    fn copy<'a>(&'a self, from_dav: &'a DavPath, to_dav: &'a DavPath) -> FsFuture<'a, ()> {
         let from = self.normalize_dav_path(from_dav);
         let to = self.normalize_dav_path(to_dav);
         debug!(from = %from.display(), to = %to.display(), "fs: copy");
         async move {
-            if self.read_only {
+            if !self.is_writable(to_dav)
+                || self.virtual_path(from_dav).is_some()
+                || self.virtual_path(to_dav).is_some()
+            {
                 return Err(FsError::Forbidden);
             }
 
@@ -465,13 +1114,17 @@ impl DavFileSystem for AliyunDriveFileSystem {
                 .get_file(to.parent().unwrap().to_path_buf())
                 .await?
                 .ok_or(FsError::NotFound)?;
-            self.drive
+            let id = self
+                .drive
                 .copy_file(&file.id, &to_parent_file.id)
                 .await
                 .map_err(|err| {
                     error!(from = %from.display(), to = %to.display(), error = %err, "copy file failed");
-                    FsError::GeneralFailure
+                    fs_error(&err)
                 })?;
+            // forget what was known below an overwritten destination
+            self.dir_cache.index().remove(&to);
+            self.dir_cache.index().insert(&to, &id);
 
             self.dir_cache.invalidate(&to).await;
             self.dir_cache.invalidate_parent(&to).await;
@@ -480,14 +1133,21 @@ impl DavFileSystem for AliyunDriveFileSystem {
         .boxed()
     }
 
-    fn rename<'a>(&'a self, from_dav: &'a DavPath, to_dav: &'a DavPath) -> FsFuture<()> {
+    fn rename<'a>(&'a self, from_dav: &'a DavPath, to_dav: &'a DavPath) -> FsFuture<'a, ()> {
         let from = self.normalize_dav_path(from_dav);
         let to = self.normalize_dav_path(to_dav);
         debug!(from = %from.display(), to = %to.display(), "fs: rename");
         async move {
-            if self.read_only {
+            if !self.is_writable(from_dav) || !self.is_writable(to_dav) {
                 return Err(FsError::Forbidden);
             }
+            match (self.virtual_path(from_dav), self.virtual_path(to_dav)) {
+                (Some(VirtualPath::TrashItem(name)), None) => {
+                    return self.restore(&name, to_dav).await
+                }
+                (None, None) => {}
+                _ => return Err(FsError::Forbidden),
+            }
 
             let is_dir;
             if from.parent() == to.parent() {
@@ -504,7 +1164,7 @@ impl DavFileSystem for AliyunDriveFileSystem {
                         .await
                         .map_err(|err| {
                             error!(from = %from.display(), to = %to.display(), error = %err, "rename file failed");
-                            FsError::GeneralFailure
+                            fs_error(&err)
                         })?;
                 } else {
                     return Err(FsError::Forbidden);
@@ -526,10 +1186,11 @@ impl DavFileSystem for AliyunDriveFileSystem {
                     .await
                     .map_err(|err| {
                         error!(from = %from.display(), to = %to.display(), error = %err, "move file failed");
-                        FsError::GeneralFailure
+                        fs_error(&err)
                     })?;
             }
 
+            self.dir_cache.index().rename(&from, &to);
             if is_dir {
                 self.dir_cache.invalidate(&from).await;
             }
@@ -540,14 +1201,14 @@ impl DavFileSystem for AliyunDriveFileSystem {
         .boxed()
     }
 
-    fn get_quota(&self) -> FsFuture<(u64, Option<u64>)> {
+    fn get_quota(&self) -> FsFuture<'_, (u64, Option<u64>)> {
         debug!("fs: get_quota");
         async move {
-            let (used, total) = self.drive.get_quota().await.map_err(|err| {
+            let (used, total) = self.quota().await.map_err(|err| {
                 error!(error = %err, "get quota failed");
-                FsError::GeneralFailure
+                fs_error(&err)
             })?;
-            Ok((used, Some(total)))
+            Ok((used, Some(self.quota_limit(total))))
         }
         .boxed()
     }
@@ -559,7 +1220,7 @@ impl DavFileSystem for AliyunDriveFileSystem {
         Box::pin(ready(true))
     }
 
-    fn get_prop(&self, dav_path: &DavPath, prop: dav_server::fs::DavProp) -> FsFuture<Vec<u8>> {
+    fn get_prop(&self, dav_path: &DavPath, prop: dav_server::fs::DavProp) -> FsFuture<'_, Vec<u8>> {
         let path = self.normalize_dav_path(dav_path);
         let prop_name = match prop.prefix.as_ref() {
             Some(prefix) => format!("{}:{}", prefix, prop.name),
@@ -589,27 +1250,115 @@ impl DavFileSystem for AliyunDriveFileSystem {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 struct UploadState {
     size: u64,
+    /// Part size, fixed when the file is opened
+    buffer_size: usize,
     buffer: BytesMut,
     chunk_count: u64,
     chunk: u64,
     upload_id: String,
     upload_urls: Vec<String>,
     sha1: Option<String>,
+    session: Option<UploadSession>,
+    /// Upload skipped because the same file already exists
+    skipped: bool,
+    /// File created by rapid upload, no data needs to be uploaded
+    rapid_upload: bool,
+    /// Body of unknown size spooled to local disk
+    spool: Option<Spool>,
+    /// Proof code computed from the spooled body
+    proof_code: Option<String>,
+    /// Parts being uploaded in the background
+    parts_in_flight: JoinSet<Result<UploadedPart, FsError>>,
+}
+
+/// Upload of a single part, running in the background
+struct PartUpload {
+    drive: AliyunDrive,
+    file_id: String,
+    file_name: String,
+    upload_id: String,
+    chunk_count: u64,
+    part_number: u64,
+    upload_url: String,
+    data: Bytes,
+    part_hash: Option<String>,
+    /// Memory budget held until the part is uploaded
+    _permit: OwnedSemaphorePermit,
+}
+
+#[derive(Debug)]
+struct UploadedPart {
+    part_number: u64,
+    part_hash: Option<String>,
+    /// Refreshed upload urls if the original one expired
+    upload_urls: Option<Vec<String>>,
+}
+
+impl PartUpload {
+    async fn run(self) -> Result<UploadedPart, FsError> {
+        let mut upload_url = self.upload_url;
+        let mut upload_urls = None;
+        let mut res = self.drive.upload(&upload_url, self.data.clone()).await;
+        if let Err(ref err) = res {
+            if err.to_string().contains("expired") {
+                warn!(
+                    file_id = %self.file_id,
+                    file_name = %self.file_name,
+                    upload_url = %upload_url,
+                    "upload url expired"
+                );
+                if let Ok(part_info_list) = self
+                    .drive
+                    .get_upload_url(&self.file_id, &self.upload_id, self.chunk_count)
+                    .await
+                {
+                    let urls: Vec<_> = part_info_list.into_iter().map(|x| x.upload_url).collect();
+                    upload_url = urls[self.part_number as usize - 1].clone();
+                    upload_urls = Some(urls);
+                    // retry upload
+                    res = self.drive.upload(&upload_url, self.data).await;
+                }
+            }
+        }
+        res.map_err(|err| {
+            error!(
+                file_id = %self.file_id,
+                file_name = %self.file_name,
+                upload_url = %upload_url,
+                error = %err,
+                "upload file chunk {} failed",
+                self.part_number
+            );
+            FsError::GeneralFailure
+        })?;
+        Ok(UploadedPart {
+            part_number: self.part_number,
+            part_hash: self.part_hash,
+            upload_urls,
+        })
+    }
 }
 
 impl Default for UploadState {
     fn default() -> Self {
         Self {
             size: 0,
+            buffer_size: 16 * 1024 * 1024,
             buffer: BytesMut::new(),
             chunk_count: 0,
             chunk: 1,
             upload_id: String::new(),
             upload_urls: Vec::new(),
             sha1: None,
+            session: None,
+            skipped: false,
+            rapid_upload: false,
+            spool: None,
+            proof_code: None,
+            parts_in_flight: JoinSet::new(),
         }
     }
 }
@@ -622,6 +1371,56 @@ struct AliyunDavFile {
     current_pos: u64,
     upload_state: UploadState,
     http_download: bool,
+    /// Whether the file is listed in the uploading files of its parent
+    uploading: bool,
+    prefetcher: Prefetcher,
+}
+
+/// Windows of a file downloading ahead of sequential reads
+#[derive(Debug, Default)]
+struct Prefetcher {
+    windows: VecDeque<PrefetchWindow>,
+    /// End of the last read, used to detect sequential access
+    last_read_end: Option<u64>,
+}
+
+#[derive(Debug)]
+struct PrefetchWindow {
+    start: u64,
+    len: usize,
+    handle: JoinHandle<Result<Bytes, FsError>>,
+}
+
+impl Prefetcher {
+    /// Take the window for a read of `count` bytes at `pos`,
+    /// prefetched windows are dropped if the read doesn't match
+    fn take(
+        &mut self,
+        pos: u64,
+        count: usize,
+        size: u64,
+    ) -> Option<JoinHandle<Result<Bytes, FsError>>> {
+        let window = self.windows.front()?;
+        if window.start == pos && (window.len == count || window.start + window.len as u64 == size)
+        {
+            return self.windows.pop_front().map(|window| window.handle);
+        }
+        self.cancel();
+        None
+    }
+
+    fn cancel(&mut self) {
+        for window in self.windows.drain(..) {
+            window.handle.abort();
+        }
+        self.last_read_end = None;
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        self.cancel();
+    }
 }
 
 impl Debug for AliyunDavFile {
@@ -644,6 +1443,7 @@ impl AliyunDavFile {
         size: u64,
         sha1: Option<String>,
     ) -> Self {
+        let buffer_size = fs.live.upload_buffer_size();
         Self {
             fs,
             file,
@@ -652,35 +1452,73 @@ impl AliyunDavFile {
             current_pos: 0,
             upload_state: UploadState {
                 size,
+                buffer_size,
                 sha1,
                 ..Default::default()
             },
             http_download: false,
+            uploading: false,
+            prefetcher: Prefetcher::default(),
+        }
+    }
+
+    /// Keep the next windows after the current position downloading in the background
+    fn read_ahead(&mut self, url: reqwest::Url, count: usize) {
+        let size = self.file.size;
+        let mut next = self
+            .prefetcher
+            .windows
+            .back()
+            .map(|window| window.start + window.len as u64)
+            .unwrap_or(self.current_pos);
+        while self.prefetcher.windows.len() < self.fs.read_ahead && next < size {
+            let len = (size - next).min(count as u64) as usize;
+            let fs = self.fs.clone();
+            let file_id = self.file.id.clone();
+            let content_hash = self.file.content_hash.clone();
+            let url = url.clone();
+            trace!(file_id = %file_id, start = next, len = len, "read ahead");
+            let handle = tokio::spawn(async move {
+                fs.download_range(&file_id, content_hash.as_deref(), size, url, next, len)
+                    .await
+            });
+            self.prefetcher.windows.push_back(PrefetchWindow {
+                start: next,
+                len,
+                handle,
+            });
+            next += len as u64;
         }
     }
 
     async fn get_download_url(&self) -> Result<GetFileDownloadUrlResponse, FsError> {
         self.fs.drive.get_download_url(&self.file.id).await.map_err(|err| {
             error!(file_id = %self.file.id, file_name = %self.file.name, error = %err, "get download url failed");
-            FsError::GeneralFailure
+            fs_error(&err)
         })
     }
 
     async fn prepare_for_upload(&mut self) -> Result<bool, FsError> {
+        if self.upload_state.skipped || self.upload_state.rapid_upload {
+            return Ok(false);
+        }
         if self.upload_state.chunk_count == 0 {
             let size = self.upload_state.size;
             debug!(file_name = %self.file.name, size = size, "prepare for upload");
+            let sha1 = self.content_sha1();
             if !self.file.id.is_empty() {
                 if let Some(content_hash) = self.file.content_hash.as_ref() {
-                    if let Some(sha1) = self.upload_state.sha1.as_ref() {
+                    if let Some(sha1) = sha1.as_ref() {
                         if content_hash.eq_ignore_ascii_case(sha1) {
                             debug!(file_name = %self.file.name, sha1 = %sha1, "skip uploading same content hash file");
+                            self.upload_state.skipped = true;
                             return Ok(false);
                         }
                     }
                 }
                 if self.fs.skip_upload_same_size && self.file.size == size {
                     debug!(file_name = %self.file.name, size = size, "skip uploading same size file");
+                    self.upload_state.skipped = true;
                     return Ok(false);
                 }
                 // existing file, delete before upload
@@ -694,20 +1532,57 @@ impl AliyunDavFile {
                 }
             }
             // TODO: create parent folders?
-            let upload_buffer_size = self.fs.upload_buffer_size as u64;
-            let chunk_count =
-                size / upload_buffer_size + if size % upload_buffer_size != 0 { 1 } else { 0 };
+            let upload_buffer_size = self.upload_state.buffer_size as u64;
+            let chunk_count = size.div_ceil(upload_buffer_size);
             self.upload_state.chunk_count = chunk_count;
-            let res = self
-                .fs
-                .drive
-                .create_file_with_proof(&self.file.name, &self.parent_file_id, size, chunk_count)
-                .await
-                .map_err(|err| {
-                    error!(file_name = %self.file.name, error = %err, "create file with proof failed");
-                    FsError::GeneralFailure
-                })?;
+            if self.resume_upload(upload_buffer_size).await {
+                return Ok(true);
+            }
+            let mut res = None;
+            if let Some(content_hash) = self.content_hash(sha1).await {
+                match self
+                    .fs
+                    .drive
+                    .create_file_with_proof(
+                        &self.file.name,
+                        &self.parent_file_id,
+                        size,
+                        chunk_count,
+                        Some(&content_hash),
+                    )
+                    .await
+                {
+                    Ok(r) => res = Some(r),
+                    Err(err) => {
+                        warn!(file_name = %self.file.name, error = %err, "create file with content hash failed");
+                    }
+                }
+            }
+            let res = match res {
+                Some(res) => res,
+                None => self
+                    .fs
+                    .drive
+                    .create_file_with_proof(
+                        &self.file.name,
+                        &self.parent_file_id,
+                        size,
+                        chunk_count,
+                        None,
+                    )
+                    .await
+                    .map_err(|err| {
+                        error!(file_name = %self.file.name, error = %err, "create file with proof failed");
+                        fs_error(&err)
+                    })?,
+            };
             self.file.id = res.file_id.clone();
+            if res.rapid_upload {
+                info!(file_id = %self.file.id, file_name = %self.file.name, size = size, "rapid upload succeeded");
+                self.upload_state.rapid_upload = true;
+                self.upload_state.buffer.clear();
+                return Ok(false);
+            }
             let Some(upload_id) = res.upload_id else {
                 error!("create file with proof failed: missing upload_id");
                 return Err(FsError::GeneralFailure);
@@ -723,22 +1598,135 @@ impl AliyunDavFile {
                 return Err(FsError::GeneralFailure);
             }
             self.upload_state.upload_urls = upload_urls;
+            if chunk_count > 1 {
+                let session = UploadSession::new(
+                    self.file.id.clone(),
+                    self.upload_state.upload_id.clone(),
+                    upload_buffer_size,
+                    chunk_count,
+                );
+                self.upload_state.session = Some(session);
+                self.save_upload_session().await;
+            }
         }
         Ok(true)
     }
 
-    async fn maybe_upload_chunk(&mut self, remaining: bool) -> Result<(), FsError> {
-        let chunk_size = if remaining {
-            // last chunk size maybe less than upload_buffer_size
-            self.upload_state.buffer.remaining()
-        } else {
-            self.fs.upload_buffer_size
+    /// sha1 of the file content, either sent by the client or
+    /// computed when the whole file is buffered
+    fn content_sha1(&self) -> Option<String> {
+        if let Some(sha1) = self.upload_state.sha1.as_ref() {
+            return Some(sha1.to_uppercase());
+        }
+        let size = self.upload_state.size;
+        if size > 0 && self.upload_state.buffer.len() as u64 == size {
+            return Some(format!("{:X}", Sha1::digest(&self.upload_state.buffer)));
+        }
+        None
+    }
+
+    /// Content hash with proof for rapid upload, only available when
+    /// the bytes at the proof offset are still buffered
+    async fn content_hash(&self, sha1: Option<String>) -> Option<ContentHash> {
+        let sha1 = sha1?;
+        if let Some(proof_code) = self.upload_state.proof_code.clone() {
+            return Some(ContentHash { sha1, proof_code });
+        }
+        let size = self.upload_state.size;
+        let offset = self.fs.drive.proof_offset(size).await.ok()?;
+        let end = (offset + 8).min(size);
+        let buffer = &self.upload_state.buffer;
+        if end > buffer.len() as u64 {
+            debug!(file_name = %self.file.name, offset = offset, "proof bytes not buffered, skip rapid upload");
+            return None;
+        }
+        let proof_code = STANDARD.encode(&buffer[offset as usize..end as usize]);
+        Some(ContentHash { sha1, proof_code })
+    }
+
+    /// Continue a previously interrupted upload of the same file
+    async fn resume_upload(&mut self, chunk_size: u64) -> bool {
+        let size = self.upload_state.size;
+        let sessions = &self.fs.upload_sessions;
+        let Some(session) = sessions
+            .load(&self.parent_file_id, &self.file.name, size)
+            .await
+        else {
+            return false;
         };
-        let current_chunk = self.upload_state.chunk;
-        if chunk_size > 0
-            && self.upload_state.buffer.remaining() >= chunk_size
-            && current_chunk <= self.upload_state.chunk_count
+        if session.chunk_size != chunk_size || session.chunk_count != self.upload_state.chunk_count
+        {
+            debug!(file_name = %self.file.name, "upload session chunk size mismatch");
+            sessions
+                .remove(&self.parent_file_id, &self.file.name, size)
+                .await;
+            return false;
+        }
+        match self
+            .fs
+            .drive
+            .get_upload_url(&session.file_id, &session.upload_id, session.chunk_count)
+            .await
         {
+            Ok(part_info_list) => {
+                info!(
+                    file_id = %session.file_id,
+                    file_name = %self.file.name,
+                    uploaded_parts = session.parts.len(),
+                    "resume upload"
+                );
+                self.file.id = session.file_id.clone();
+                self.upload_state.upload_id = session.upload_id.clone();
+                self.upload_state.upload_urls =
+                    part_info_list.into_iter().map(|x| x.upload_url).collect();
+                self.upload_state.session = Some(session);
+                true
+            }
+            Err(err) => {
+                warn!(file_name = %self.file.name, error = %err, "resume upload failed");
+                sessions
+                    .remove(&self.parent_file_id, &self.file.name, size)
+                    .await;
+                false
+            }
+        }
+    }
+
+    async fn save_upload_session(&self) {
+        if let Some(session) = self.upload_state.session.as_ref() {
+            if let Err(err) = self
+                .fs
+                .upload_sessions
+                .save(
+                    &self.parent_file_id,
+                    &self.file.name,
+                    self.upload_state.size,
+                    session,
+                )
+                .await
+            {
+                warn!(file_name = %self.file.name, error = %err, "save upload session failed");
+            }
+        }
+    }
+
+    async fn maybe_upload_chunk(&mut self, remaining: bool) -> Result<(), FsError> {
+        // a single write may carry more than one chunk of data
+        loop {
+            let buffered = self.upload_state.buffer.remaining();
+            let chunk_size = if remaining && buffered < self.upload_state.buffer_size {
+                // last chunk size maybe less than upload_buffer_size
+                buffered
+            } else {
+                self.upload_state.buffer_size
+            };
+            let current_chunk = self.upload_state.chunk;
+            if chunk_size == 0
+                || buffered < chunk_size
+                || current_chunk > self.upload_state.chunk_count
+            {
+                break;
+            }
             let chunk_data = self.upload_state.buffer.split_to(chunk_size);
             debug!(
                 file_id = %self.file.id,
@@ -748,50 +1736,145 @@ impl AliyunDavFile {
                 current_chunk,
                 self.upload_state.chunk_count
             );
-            let mut upload_url = &self.upload_state.upload_urls[current_chunk as usize - 1];
             let upload_data = chunk_data.freeze();
-            let mut res = self.fs.drive.upload(upload_url, upload_data.clone()).await;
-            if let Err(ref err) = res {
-                if err.to_string().contains("expired") {
-                    warn!(
-                        file_id = %self.file.id,
-                        file_name = %self.file.name,
-                        upload_url = %upload_url,
-                        "upload url expired"
-                    );
-                    if let Ok(part_info_list) = self
-                        .fs
-                        .drive
-                        .get_upload_url(
-                            &self.file.id,
-                            &self.upload_state.upload_id,
-                            self.upload_state.chunk_count,
-                        )
-                        .await
-                    {
-                        let upload_urls: Vec<_> =
-                            part_info_list.into_iter().map(|x| x.upload_url).collect();
-                        self.upload_state.upload_urls = upload_urls;
-                        upload_url = &self.upload_state.upload_urls[current_chunk as usize - 1];
-                        // retry upload
-                        res = self.fs.drive.upload(upload_url, upload_data).await;
-                    }
-                }
-                res.map_err(|err| {
-                    error!(
+            let part_hash = self
+                .upload_state
+                .session
+                .as_ref()
+                .map(|_| format!("{:x}", Sha1::digest(&upload_data)));
+            if let (Some(session), Some(part_hash)) =
+                (self.upload_state.session.as_ref(), part_hash.as_ref())
+            {
+                if session.parts.get(&current_chunk) == Some(part_hash) {
+                    debug!(
                         file_id = %self.file.id,
                         file_name = %self.file.name,
-                        upload_url = %upload_url,
-                        size = self.upload_state.size,
-                        error = %err,
-                        "upload file chunk {} failed",
+                        "skip uploaded part {}",
                         current_chunk
                     );
-                    FsError::GeneralFailure
-                })?;
+                    self.upload_state.chunk += 1;
+                    continue;
+                }
+            }
+            let permit = self.fs.upload_memory.acquire(chunk_size).await;
+            let part = PartUpload {
+                drive: self.fs.drive.clone(),
+                file_id: self.file.id.clone(),
+                file_name: self.file.name.clone(),
+                upload_id: self.upload_state.upload_id.clone(),
+                chunk_count: self.upload_state.chunk_count,
+                part_number: current_chunk,
+                upload_url: self.upload_state.upload_urls[current_chunk as usize - 1].clone(),
+                data: upload_data,
+                part_hash,
+                _permit: permit,
+            };
+            self.upload_state.parts_in_flight.spawn(part.run());
+            // keep at most `upload_concurrency - 1` parts running in the
+            // background so that a concurrency of 1 uploads sequentially
+            while self.upload_state.parts_in_flight.len() >= self.fs.upload_concurrency {
+                self.join_next_part().await?;
             }
             self.upload_state.chunk += 1;
         }
+        if remaining {
+            while !self.upload_state.parts_in_flight.is_empty() {
+                self.join_next_part().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for the next background part upload and record it in the upload session
+    async fn join_next_part(&mut self) -> Result<(), FsError> {
+        let Some(res) = self.upload_state.parts_in_flight.join_next().await else {
+            return Ok(());
+        };
+        let part = res.map_err(|err| {
+            error!(file_id = %self.file.id, file_name = %self.file.name, error = %err, "upload part task failed");
+            FsError::GeneralFailure
+        })??;
+        if let Some(upload_urls) = part.upload_urls {
+            self.upload_state.upload_urls = upload_urls;
+        }
+        if let (Some(session), Some(part_hash)) =
+            (self.upload_state.session.as_mut(), part.part_hash)
+        {
+            session.parts.insert(part.part_number, part_hash);
+            self.save_upload_session().await;
+        }
+        Ok(())
+    }
+}
+
+impl AliyunDavFile {
+    async fn write_data(&mut self, data: Bytes) -> Result<(), FsError> {
+        if let Some(spool) = self.upload_state.spool.as_mut() {
+            return spool.write(&data).await.map_err(|err| {
+                error!(file_name = %self.file.name, error = %err, "write spool failed");
+                FsError::GeneralFailure
+            });
+        }
+        self.upload_state.buffer.extend_from_slice(&data);
+        self.upload_buffered().await
+    }
+
+    /// Upload the spooled body now that its size and hash are known
+    async fn upload_spooled(&mut self, mut spool: Spool) -> Result<(), FsError> {
+        let map_err = |err: anyhow::Error| {
+            error!(error = %err, "read spool failed");
+            FsError::GeneralFailure
+        };
+        let size = spool.size();
+        // the size is only known now, an existing file's content is replaced
+        self.fs
+            .admit_upload(
+                &self.parent_dir.join(&self.file.name),
+                size.saturating_sub(self.file.size),
+            )
+            .await?;
+        let sha1 = spool.finish().await.map_err(map_err)?;
+        debug!(file_name = %self.file.name, size = size, sha1 = %sha1, "upload spooled file");
+        self.upload_state.size = size;
+        self.file.size = size;
+        self.upload_state.sha1 = Some(sha1);
+        if let Ok(offset) = self.fs.drive.proof_offset(size).await {
+            let proof = spool.read_at(offset, 8).await.map_err(map_err)?;
+            self.upload_state.proof_code = Some(STANDARD.encode(proof));
+        }
+        let upload_buffer_size = self.upload_state.buffer_size;
+        loop {
+            self.upload_state.buffer.reserve(upload_buffer_size);
+            let n = spool
+                .read_buf(&mut self.upload_state.buffer)
+                .await
+                .map_err(map_err)?;
+            if n == 0 {
+                break;
+            }
+            if self.upload_state.buffer.len() >= upload_buffer_size {
+                self.upload_buffered().await?;
+                if self.upload_state.skipped || self.upload_state.rapid_upload {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn upload_buffered(&mut self) -> Result<(), FsError> {
+        // defer creating the file until the first chunk is buffered,
+        // so that small files can be hashed for rapid upload
+        if self.upload_state.chunk_count == 0
+            && self.upload_state.buffer.len() < self.upload_state.buffer_size
+        {
+            return Ok(());
+        }
+        if self.prepare_for_upload().await? {
+            self.maybe_upload_chunk(false).await?;
+        } else {
+            self.upload_state.buffer.clear();
+        }
         Ok(())
     }
 }
@@ -823,7 +1906,7 @@ impl DavFile for AliyunDavFile {
         .boxed()
     }
 
-    fn redirect_url(&mut self) -> FsFuture<Option<String>> {
+    fn redirect_url(&mut self) -> FsFuture<'_, Option<String>> {
         debug!(file_id = %self.file.id, file_name = %self.file.name, "file: redirect_url");
         async move {
             if self.file.id.is_empty() {
@@ -855,28 +1938,19 @@ impl DavFile for AliyunDavFile {
     fn write_buf(&'_ mut self, buf: Box<dyn Buf + Send>) -> FsFuture<'_, ()> {
         debug!(file_id = %self.file.id, file_name = %self.file.name, "file: write_buf");
         async move {
-            if self.prepare_for_upload().await? {
-                self.upload_state.buffer.put(buf);
-                self.maybe_upload_chunk(false).await?;
-            }
-            Ok(())
+            let mut buf = buf;
+            let data = buf.copy_to_bytes(buf.remaining());
+            self.write_data(data).await
         }
         .boxed()
     }
 
-    fn write_bytes(&mut self, buf: Bytes) -> FsFuture<()> {
+    fn write_bytes(&mut self, buf: Bytes) -> FsFuture<'_, ()> {
         debug!(file_id = %self.file.id, file_name = %self.file.name, size = buf.len(), "file: write_bytes");
-        async move {
-            if self.prepare_for_upload().await? {
-                self.upload_state.buffer.extend_from_slice(&buf);
-                self.maybe_upload_chunk(false).await?;
-            }
-            Ok(())
-        }
-        .boxed()
+        async move { self.write_data(buf).await }.boxed()
     }
 
-    fn read_bytes(&mut self, count: usize) -> FsFuture<Bytes> {
+    fn read_bytes(&mut self, count: usize) -> FsFuture<'_, Bytes> {
         debug!(
             file_id = %self.file.id,
             file_name = %self.file.name,
@@ -909,16 +1983,32 @@ impl DavFile for AliyunDavFile {
                     url.set_scheme("http")
                         .map_err(|_| FsError::GeneralFailure)?;
                 }
-                let content = self
-                    .fs
-                    .drive
-                    .download(url, Some((self.current_pos, count)))
-                    .await
-                    .map_err(|err| {
-                        error!(url = %download_url, error = %err, "download file failed");
-                        FsError::NotFound
-                    })?;
+                let pos = self.current_pos;
+                let sequential = self.prefetcher.last_read_end == Some(pos);
+                let prefetched = match self.prefetcher.take(pos, count, self.file.size) {
+                    Some(handle) => handle.await.ok(),
+                    None => None,
+                };
+                let content = match prefetched {
+                    Some(res) => res?,
+                    None => {
+                        self.fs
+                            .download_range(
+                                &self.file.id,
+                                self.file.content_hash.as_deref(),
+                                self.file.size,
+                                url.clone(),
+                                pos,
+                                count,
+                            )
+                            .await?
+                    }
+                };
                 self.current_pos += content.len() as u64;
+                self.prefetcher.last_read_end = Some(self.current_pos);
+                if sequential {
+                    self.read_ahead(url, count);
+                }
                 self.file.url = Some(download_url);
                 Ok(content)
             } else if streams_url.is_empty() {
@@ -951,7 +2041,7 @@ impl DavFile for AliyunDavFile {
         .boxed()
     }
 
-    fn seek(&mut self, pos: SeekFrom) -> FsFuture<u64> {
+    fn seek(&mut self, pos: SeekFrom) -> FsFuture<'_, u64> {
         debug!(
             file_id = %self.file.id,
             file_name = %self.file.name,
@@ -964,16 +2054,23 @@ impl DavFile for AliyunDavFile {
                 SeekFrom::End(pos) => (self.file.size as i64 + pos) as u64,
                 SeekFrom::Current(size) => self.current_pos + size as u64,
             };
+            if new_pos != self.current_pos {
+                self.prefetcher.cancel();
+            }
             self.current_pos = new_pos;
             Ok(new_pos)
         }
         .boxed()
     }
 
-    fn flush(&mut self) -> FsFuture<()> {
+    fn flush(&mut self) -> FsFuture<'_, ()> {
         debug!(file_id = %self.file.id, file_name = %self.file.name, "file: flush");
         async move {
-            if self.prepare_for_upload().await? {
+            if let Some(spool) = self.upload_state.spool.take() {
+                self.upload_spooled(spool).await?;
+            }
+            let upload = self.prepare_for_upload().await?;
+            if upload {
                 self.maybe_upload_chunk(true).await?;
                 if !self.upload_state.upload_id.is_empty() {
                     self.fs
@@ -987,11 +2084,34 @@ impl DavFile for AliyunDavFile {
                                 error = %err,
                                 "complete file upload failed"
                             );
-                            FsError::GeneralFailure
+                            fs_error(&err)
                         })?;
+                    if self.upload_state.session.take().is_some() {
+                        self.fs
+                            .upload_sessions
+                            .remove(
+                                &self.parent_file_id,
+                                &self.file.name,
+                                self.upload_state.size,
+                            )
+                            .await;
+                    }
+                }
+            }
+            if upload || self.upload_state.rapid_upload {
+                if self.uploading {
+                    self.fs.add_quota_used(self.upload_state.size);
+                } else {
+                    // the replaced content is freed, fetch the usage again
+                    self.fs.quota.lock().unwrap().take();
                 }
                 self.fs
                     .remove_uploading_file(&self.parent_file_id, &self.file.name);
+                self.uploading = false;
+                self.fs
+                    .dir_cache
+                    .index()
+                    .insert(&self.parent_dir.join(&self.file.name), &self.file.id);
                 self.fs.dir_cache.invalidate(&self.parent_dir).await;
             }
             Ok(())
@@ -1000,6 +2120,16 @@ impl DavFile for AliyunDavFile {
     }
 }
 
+impl Drop for AliyunDavFile {
+    fn drop(&mut self) {
+        if self.uploading {
+            // upload interrupted before flush, it can be resumed by a retried PUT
+            self.fs
+                .remove_uploading_file(&self.parent_file_id, &self.file.name);
+        }
+    }
+}
+
 fn is_url_expired(url: &str) -> bool {
     if let Ok(oss_url) = ::url::Url::parse(url) {
         let expires = oss_url.query_pairs().find_map(|(k, v)| {
@@ -0,0 +1,27 @@
+//! Embedded web file browser talking WebDAV to the server it is served from
+
+use dav_server::body::Body;
+use hyper::header::{ACCEPT, CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Method, Request, Response};
+
+const INDEX_HTML: &str = include_str!("index.html");
+
+/// Whether the request is a browser navigating to a directory
+pub fn is_page_request(req: &Request<hyper::Body>) -> bool {
+    req.method() == Method::GET
+        && req.uri().path().ends_with('/')
+        && req
+            .headers()
+            .get(ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// The single page of the file browser, it lists the directory of the current URL itself
+pub fn index() -> Response<Body> {
+    hyper::Response::builder()
+        .header(CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(CACHE_CONTROL, "no-cache")
+        .body(Body::from(INDEX_HTML))
+        .unwrap()
+}
@@ -3,13 +3,24 @@ use std::io;
 use std::net::ToSocketAddrs;
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::sync::{Arc, RwLock};
 use std::task::{Context, Poll};
 
 use anyhow::Result;
 use dav_server::{body::Body, DavConfig, DavHandler};
-use headers::{authorization::Basic, Authorization, HeaderMapExt};
-use hyper::{service::Service, Request, Response};
-use tracing::{error, info};
+use hyper::header::{HeaderValue, ALLOW, CONTENT_TYPE, RETRY_AFTER};
+use hyper::{service::Service, Method, Request, Response, StatusCode};
+use tracing::{error, info, warn};
+
+use crate::admin;
+use crate::auth::{AuthError, Authenticator, Principal};
+use crate::config::LiveSettings;
+use crate::drive::track_failures;
+use crate::metrics::metrics;
+use crate::search;
+use crate::users::UserFileSystem;
+use crate::vfs::AliyunDriveFileSystem;
+use crate::web;
 
 #[cfg(feature = "rustls-tls")]
 use {
@@ -19,17 +30,37 @@ use {
     std::fs::File,
     std::future::ready,
     std::path::Path,
-    std::sync::Arc,
     tls_listener::{SpawningHandshakes, TlsListener},
     tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig},
     tokio_rustls::TlsAcceptor,
 };
 
+/// Authenticator replaced when the configuration is reloaded, `None` disables authentication
+pub type SharedAuth = Arc<RwLock<Option<Arc<Authenticator>>>>;
+
+/// Settings of the service, shared by the connections
+#[derive(Clone, Default)]
+pub struct ServiceOptions {
+    pub auth: SharedAuth,
+    /// File system restricted per user of the users file
+    pub user_fs: Option<Arc<dyn UserFileSystem>>,
+    pub live: Arc<LiveSettings>,
+    /// Serve Prometheus metrics at `/metrics`
+    pub metrics: bool,
+    /// File systems with their mount paths, checked by `/readyz` and managed by the admin API
+    pub mounts: Arc<Vec<(String, AliyunDriveFileSystem)>>,
+    /// Bearer token of the admin API, `None` disables it
+    pub admin_token: Option<Arc<str>>,
+    /// Serve the web file browser to browsers navigating to directories
+    pub web_ui: bool,
+    /// Answer WebDAV SEARCH requests
+    pub search: bool,
+}
+
 pub struct WebDavServer {
     pub host: String,
     pub port: u16,
-    pub auth_user: Option<String>,
-    pub auth_password: Option<String>,
+    pub options: ServiceOptions,
     pub tls_config: Option<(PathBuf, PathBuf)>,
     pub handler: DavHandler,
 }
@@ -56,8 +87,7 @@ impl WebDavServer {
                 }
             });
             let server = hyper::Server::builder(accept::from_stream(incoming)).serve(MakeSvc {
-                auth_user: self.auth_user,
-                auth_password: self.auth_password,
+                options: self.options,
                 handler: self.handler,
             });
             info!("listening on https://{}", addr);
@@ -70,8 +100,7 @@ impl WebDavServer {
         }
 
         let server = hyper::Server::bind(&addr).serve(MakeSvc {
-            auth_user: self.auth_user,
-            auth_password: self.auth_password,
+            options: self.options,
             handler: self.handler,
         });
         info!("listening on http://{}", server.local_addr());
@@ -82,9 +111,8 @@ impl WebDavServer {
 
 #[derive(Clone)]
 pub struct AliyunDriveWebDav {
-    auth_user: Option<String>,
-    auth_password: Option<String>,
-    handler: DavHandler,
+    pub options: ServiceOptions,
+    pub handler: DavHandler,
 }
 
 impl Service<Request<hyper::Body>> for AliyunDriveWebDav {
@@ -98,42 +126,183 @@ impl Service<Request<hyper::Body>> for AliyunDriveWebDav {
     }
 
     fn call(&mut self, req: Request<hyper::Body>) -> Self::Future {
-        let should_auth = self.auth_user.is_some() && self.auth_password.is_some();
+        let path = req.uri().path();
+        if matches!(*req.method(), Method::GET | Method::HEAD)
+            && (path == "/healthz" || path == "/readyz")
+        {
+            let mounts = (path == "/readyz").then(|| self.options.mounts.clone());
+            return Box::pin(async move { Ok(probe(mounts).await) });
+        }
+        if let Some(token) = self.options.admin_token.clone() {
+            if path.starts_with(admin::PREFIX) {
+                let mounts = self.options.mounts.clone();
+                return Box::pin(async move { Ok(admin::handle(&token, &mounts, req).await) });
+            }
+        }
         let dav_server = self.handler.clone();
-        let auth_user = self.auth_user.clone();
-        let auth_pwd = self.auth_password.clone();
+        let options = self.options.clone();
+        let config = DavConfig::new().read_buf_size(options.live.read_buffer_size());
         Box::pin(async move {
-            if should_auth {
-                let auth_user = auth_user.unwrap();
-                let auth_pwd = auth_pwd.unwrap();
-                let user = match req.headers().typed_get::<Authorization<Basic>>() {
-                    Some(Authorization(basic))
-                        if basic.username() == auth_user && basic.password() == auth_pwd =>
-                    {
-                        basic.username().to_string()
-                    }
-                    Some(_) | None => {
-                        // return a 401 reply.
-                        let response = hyper::Response::builder()
-                            .status(401)
-                            .header("WWW-Authenticate", "Basic realm=\"aliyundrive-webdav\"")
-                            .body(Body::from("Authentication required".to_string()))
-                            .unwrap();
-                        return Ok(response);
-                    }
+            let method = req.method().clone();
+            let no_overwrite = req
+                .headers()
+                .get("Overwrite")
+                .is_some_and(|overwrite| overwrite.as_bytes().eq_ignore_ascii_case(b"F"));
+            let (mut response, failures) =
+                track_failures(handle(dav_server, options, config, req)).await;
+            if failures.conflict
+                && matches!(
+                    response.status(),
+                    StatusCode::METHOD_NOT_ALLOWED | StatusCode::PRECONDITION_FAILED
+                )
+            {
+                // the drive refused to replace an existing file
+                *response.status_mut() = if no_overwrite {
+                    StatusCode::PRECONDITION_FAILED
+                } else {
+                    StatusCode::CONFLICT
                 };
-                let config = DavConfig::new().principal(user);
-                Ok(dav_server.handle_with(config, req).await)
-            } else {
-                Ok(dav_server.handle(req).await)
             }
+            if let Some(retry_after) = failures.retry_after {
+                if response.status() == StatusCode::INTERNAL_SERVER_ERROR {
+                    // the drive is throttling or failing, tell the client to come back later
+                    *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                    response.headers_mut().insert(
+                        RETRY_AFTER,
+                        HeaderValue::from(retry_after.as_secs_f64().ceil() as u64),
+                    );
+                }
+            }
+            metrics()
+                .requests
+                .with_label_values(&[method_label(&method), response.status().as_str()])
+                .inc();
+            Ok(response)
         })
     }
 }
 
+/// Label of `method` in metrics, other methods are counted together to bound the label values
+fn method_label(method: &Method) -> &str {
+    match method.as_str() {
+        "GET" | "HEAD" | "POST" | "PUT" | "DELETE" | "OPTIONS" | "PATCH" | "TRACE" | "CONNECT"
+        | "PROPFIND" | "PROPPATCH" | "MKCOL" | "COPY" | "MOVE" | "LOCK" | "UNLOCK" | "SEARCH" => {
+            method.as_str()
+        }
+        _ => "OTHER",
+    }
+}
+
+/// Authenticate a request and pass it to the DAV handler, metrics, search or the web file browser
+async fn handle(
+    dav_server: DavHandler,
+    options: ServiceOptions,
+    config: DavConfig,
+    req: Request<hyper::Body>,
+) -> Response<Body> {
+    let auth = options.auth.read().unwrap().clone();
+    let config = match auth {
+        Some(auth) => match authorize(&auth, options.user_fs, config, &req).await {
+            Ok(config) => config,
+            Err(response) => return response,
+        },
+        None => config,
+    };
+    if options.metrics && req.method() == Method::GET && req.uri().path() == "/metrics" {
+        return hyper::Response::builder()
+            .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+            .body(Body::from(metrics().render()))
+            .unwrap();
+    }
+    if options.web_ui && web::is_page_request(&req) {
+        return web::index();
+    }
+    if !options.search {
+        return dav_server.handle_with(config, req).await;
+    }
+    if search::is_search_request(&req) {
+        return search::handle(dav_server, config, req).await;
+    }
+    let is_options = req.method() == Method::OPTIONS;
+    let mut response = dav_server.handle_with(config, req).await;
+    if is_options {
+        let headers = response.headers_mut();
+        headers.insert("DASL", HeaderValue::from_static(search::DASL));
+        if let Some(allow) = headers.get(ALLOW).and_then(|allow| allow.to_str().ok()) {
+            let allow = format!("{},SEARCH", allow);
+            headers.insert(ALLOW, HeaderValue::from_str(&allow).unwrap());
+        }
+    }
+    response
+}
+
+/// Authenticate a request and set its principal and file system in `config`
+async fn authorize(
+    auth: &Arc<Authenticator>,
+    user_fs: Option<Arc<dyn UserFileSystem>>,
+    config: DavConfig,
+    req: &Request<hyper::Body>,
+) -> Result<DavConfig, Response<Body>> {
+    let principal = match auth
+        .authenticate(req.method(), req.uri(), req.headers())
+        .await
+    {
+        Ok(principal) => principal,
+        Err(err) => return Err(unauthorized(auth, err)),
+    };
+    let config = match (principal, user_fs) {
+        (Principal::User(user), Some(user_fs)) => {
+            let fs = match user_fs.for_user(&user) {
+                Ok(fs) => fs,
+                Err(err) => {
+                    warn!(user = %user.name, error = %err, "user file system unavailable");
+                    return Err(hyper::Response::builder()
+                        .status(403)
+                        .body(Body::from("Forbidden".to_string()))
+                        .unwrap());
+                }
+            };
+            config.principal(user.name).filesystem(fs)
+        }
+        (Principal::User(user), None) => config.principal(user.name),
+        (Principal::Single(name), _) => config.principal(name),
+    };
+    Ok(config)
+}
+
+/// Liveness probe when `mounts` is `None`, otherwise readiness of their drives
+async fn probe(mounts: Option<Arc<Vec<(String, AliyunDriveFileSystem)>>>) -> Response<Body> {
+    let mut errors = Vec::new();
+    for (path, fs) in mounts.iter().flat_map(|mounts| mounts.iter()) {
+        if let Err(err) = fs.drive.check_ready().await {
+            errors.push(format!("{}: {}", path, err));
+        }
+    }
+    let (status, body) = if errors.is_empty() {
+        (200, "ok\n".to_string())
+    } else {
+        warn!(errors = ?errors, "not ready");
+        (503, errors.join("\n") + "\n")
+    };
+    hyper::Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn unauthorized(auth: &Authenticator, err: AuthError) -> Response<Body> {
+    let mut builder = hyper::Response::builder().status(401);
+    for challenge in auth.challenges(err) {
+        builder = builder.header("WWW-Authenticate", challenge);
+    }
+    builder
+        .body(Body::from("Authentication required".to_string()))
+        .unwrap()
+}
+
 pub struct MakeSvc {
-    pub auth_user: Option<String>,
-    pub auth_password: Option<String>,
+    pub options: ServiceOptions,
     pub handler: DavHandler,
 }
 
@@ -148,16 +317,9 @@ impl<T> Service<T> for MakeSvc {
     }
 
     fn call(&mut self, _: T) -> Self::Future {
-        let auth_user = self.auth_user.clone();
-        let auth_password = self.auth_password.clone();
+        let options = self.options.clone();
         let handler = self.handler.clone();
-        let fut = async move {
-            Ok(AliyunDriveWebDav {
-                auth_user,
-                auth_password,
-                handler,
-            })
-        };
+        let fut = async move { Ok(AliyunDriveWebDav { options, handler }) };
         Box::pin(fut)
     }
 }